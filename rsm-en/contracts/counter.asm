@@ -0,0 +1,35 @@
+; Counter. Slot 0 holds the count; every call returns it.
+;   ARG 0 = 0: add one
+;   ARG 0 = 1: add ARG 1
+;   ARG 0 = 2: read only
+        ARG 0
+        ISZERO
+        PUSH @increment
+        JUMPI
+        ARG 0
+        PUSH 1
+        EQ
+        PUSH @add
+        JUMPI
+        ARG 0
+        PUSH 2
+        EQ
+        PUSH @get
+        JUMPI
+        REVERT
+increment:
+        PUSH 1
+        PUSH @store
+        JUMP
+add:
+        ARG 1
+store:
+        PUSH 0
+        SLOAD
+        ADD
+        PUSH 0
+        SSTORE
+get:
+        PUSH 0
+        SLOAD
+        RETURN
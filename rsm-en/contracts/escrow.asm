@@ -0,0 +1,64 @@
+; Escrow. The depositor funds it with a value and names a beneficiary and
+; an arbiter; the arbiter then releases the funds or refunds them.
+;   ARG 0 = 0: fund, ARG 1 = beneficiary, ARG 2 = arbiter
+;   ARG 0 = 1: release to the beneficiary (arbiter only)
+;   ARG 0 = 2: refund the depositor (arbiter only)
+; Slots: 0 depositor, 1 beneficiary, 2 arbiter.
+        ARG 0
+        ISZERO
+        PUSH @fund
+        JUMPI
+        CALLER
+        PUSH 2
+        SLOAD
+        EQ
+        ISZERO
+        PUSH @fail
+        JUMPI
+        BALANCE
+        ISZERO
+        PUSH @fail
+        JUMPI
+        ARG 0
+        PUSH 1
+        EQ
+        PUSH @release
+        JUMPI
+        ARG 0
+        PUSH 2
+        EQ
+        PUSH @refund
+        JUMPI
+fail:
+        REVERT
+release:
+        BALANCE
+        PUSH 1
+        SLOAD
+        TRANSFER
+        STOP
+refund:
+        BALANCE
+        PUSH 0
+        SLOAD
+        TRANSFER
+        STOP
+fund:
+        PUSH 0
+        SLOAD
+        PUSH @fail
+        JUMPI           ; already funded
+        CALLVALUE
+        ISZERO
+        PUSH @fail
+        JUMPI
+        CALLER
+        PUSH 0
+        SSTORE
+        ARG 1
+        PUSH 1
+        SSTORE
+        ARG 2
+        PUSH 2
+        SSTORE
+        STOP
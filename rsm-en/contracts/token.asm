@@ -0,0 +1,55 @@
+; Fungible token. Slot 0 holds the total supply; each holder's balance is
+; stored under their account word.
+;   ARG 0 = 0: mint ARG 1 to the caller; only once
+;   ARG 0 = 1: transfer ARG 2 to account ARG 1
+;   ARG 0 = 2: return the balance of account ARG 1
+        ARG 0
+        ISZERO
+        PUSH @init
+        JUMPI
+        ARG 0
+        PUSH 1
+        EQ
+        PUSH @transfer
+        JUMPI
+        ARG 0
+        PUSH 2
+        EQ
+        PUSH @balance
+        JUMPI
+fail:
+        REVERT
+init:
+        PUSH 0
+        SLOAD
+        PUSH @fail
+        JUMPI           ; already minted
+        ARG 1
+        DUP 0
+        ISZERO
+        PUSH @fail
+        JUMPI
+        DUP 0
+        PUSH 0
+        SSTORE
+        CALLER
+        SSTORE
+        STOP
+transfer:
+        CALLER
+        SLOAD
+        ARG 2
+        SUB             ; traps if the caller holds too little
+        CALLER
+        SSTORE
+        ARG 1
+        SLOAD
+        ARG 2
+        ADD
+        ARG 1
+        SSTORE
+        STOP
+balance:
+        ARG 1
+        SLOAD
+        RETURN
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use num_traits::{CheckedAdd, CheckedSub, Zero};
+use serde::{Deserialize, Serialize};
+use crate::balances;
+use crate::system;
+use crate::merkle::{FastMerkleTree, Hash};
+use crate::runtime::{DefaultConfig, DispatchResult};
+use crate::error::LedgerError;
+
+pub type AssetId = u32;
+
+/// Allowances granted by one owner, keyed by spender.
+pub type Allowances<T> = BTreeMap<<T as system::Config>::AccountId, <T as balances::Config>::Balance>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Call<T: balances::Config = DefaultConfig> {
+    Create { id: AssetId, name: String, symbol: String, decimals: u8 },
+    Mint { id: AssetId, to: T::AccountId, amount: T::Balance },
+    Burn { id: AssetId, from: T::AccountId, amount: T::Balance },
+    Transfer { id: AssetId, to: T::AccountId, amount: T::Balance },
+    Approve { id: AssetId, spender: T::AccountId, amount: T::Balance },
+    TransferFrom { id: AssetId, owner: T::AccountId, to: T::AccountId, amount: T::Balance },
+}
+
+impl<T: balances::Config> Call<T> {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Create { .. } => 50,
+            Call::Mint { .. } | Call::Burn { .. } => 15,
+            Call::Transfer { .. } | Call::Approve { .. } => 10,
+            Call::TransferFrom { .. } => 15,
+        }
+    }
+
+    pub fn asset_id(&self) -> AssetId {
+        match self {
+            Call::Create { id, .. }
+            | Call::Mint { id, .. }
+            | Call::Burn { id, .. }
+            | Call::Transfer { id, .. }
+            | Call::Approve { id, .. }
+            | Call::TransferFrom { id, .. } => *id,
+        }
+    }
+
+    pub fn counterparties(&self) -> Vec<&T::AccountId> {
+        match self {
+            Call::Create { .. } => Vec::new(),
+            Call::Mint { to, .. } | Call::Transfer { to, .. } => vec![to],
+            Call::Burn { from, .. } => vec![from],
+            Call::Approve { spender, .. } => vec![spender],
+            Call::TransferFrom { owner, to, .. } => vec![owner, to],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct AssetDetails<T: balances::Config = DefaultConfig> {
+    pub issuer: T::AccountId,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub supply: T::Balance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Pallet<T: balances::Config = DefaultConfig> {
+    pub assets: BTreeMap<AssetId, AssetDetails<T>>,
+    pub balances: BTreeMap<AssetId, BTreeMap<T::AccountId, T::Balance>>,
+    /// Allowances by asset, owner and spender.
+    pub approvals: BTreeMap<AssetId, BTreeMap<T::AccountId, Allowances<T>>>,
+}
+
+impl<T: balances::Config> Default for Pallet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: balances::Config> Pallet<T> {
+
+    pub fn new() -> Self {
+
+        Self {
+            assets: BTreeMap::new(),
+            balances: BTreeMap::new(),
+            approvals: BTreeMap::new(),
+        }
+    }
+
+    pub fn create(&mut self, issuer: &T::AccountId, id: AssetId, name: String, symbol: String, decimals: u8) -> DispatchResult {
+
+        if self.assets.contains_key(&id) {
+            return Err(LedgerError::AssetExists);
+        }
+        if symbol.is_empty() {
+            return Err(LedgerError::EmptyAssetSymbol);
+        }
+        self.assets.insert(id, AssetDetails {
+            issuer: issuer.clone(),
+            name,
+            symbol,
+            decimals,
+            supply: T::Balance::zero(),
+        });
+        Ok(())
+    }
+
+    pub fn details(&self, id: AssetId) -> Option<&AssetDetails<T>> {
+
+        self.assets.get(&id)
+    }
+
+    pub fn total_supply(&self, id: AssetId) -> T::Balance {
+
+        self.assets.get(&id).map(|a| a.supply).unwrap_or_else(T::Balance::zero)
+    }
+
+    pub fn balance_of(&self, id: AssetId, who: &T::AccountId) -> T::Balance {
+
+        self.balances
+            .get(&id)
+            .and_then(|holders| holders.get(who))
+            .copied()
+            .unwrap_or_else(T::Balance::zero)
+    }
+
+    pub fn allowance(&self, id: AssetId, owner: &T::AccountId, spender: &T::AccountId) -> T::Balance {
+
+        self.approvals
+            .get(&id)
+            .and_then(|owners| owners.get(owner))
+            .and_then(|spenders| spenders.get(spender))
+            .copied()
+            .unwrap_or_else(T::Balance::zero)
+    }
+
+    pub fn mint(&mut self, caller: &T::AccountId, id: AssetId, to: &T::AccountId, amount: T::Balance) -> DispatchResult {
+
+        let asset = self.assets.get_mut(&id).ok_or(LedgerError::UnknownAsset)?;
+        if asset.issuer != *caller {
+            return Err(LedgerError::NotIssuer);
+        }
+        let new_supply = asset.supply.checked_add(&amount).ok_or(LedgerError::Arithmetic)?;
+        let new_balance = self.balance_of(id, to).checked_add(&amount).ok_or(LedgerError::Arithmetic)?;
+
+        self.assets.get_mut(&id).unwrap().supply = new_supply;
+        self.set_balance(id, to, new_balance);
+        Ok(())
+    }
+
+    pub fn burn(&mut self, caller: &T::AccountId, id: AssetId, from: &T::AccountId, amount: T::Balance) -> DispatchResult {
+
+        let asset = self.assets.get(&id).ok_or(LedgerError::UnknownAsset)?;
+        if asset.issuer != *caller {
+            return Err(LedgerError::NotIssuer);
+        }
+        let new_balance = self.balance_of(id, from).checked_sub(&amount).ok_or(LedgerError::InsufficientAssetBalance)?;
+        let new_supply = asset.supply.checked_sub(&amount).ok_or(LedgerError::Arithmetic)?;
+
+        self.assets.get_mut(&id).unwrap().supply = new_supply;
+        self.set_balance(id, from, new_balance);
+        Ok(())
+    }
+
+    pub fn transfer(&mut self, id: AssetId, from: &T::AccountId, to: &T::AccountId, amount: T::Balance) -> DispatchResult {
+
+        if !self.assets.contains_key(&id) {
+            return Err(LedgerError::UnknownAsset);
+        }
+        let new_from = self.balance_of(id, from).checked_sub(&amount).ok_or(LedgerError::InsufficientAssetBalance)?;
+        if from == to {
+            return Ok(());
+        }
+        let new_to = self.balance_of(id, to).checked_add(&amount).ok_or(LedgerError::Arithmetic)?;
+
+        self.set_balance(id, from, new_from);
+        self.set_balance(id, to, new_to);
+        Ok(())
+    }
+
+    pub fn approve(&mut self, id: AssetId, owner: &T::AccountId, spender: &T::AccountId, amount: T::Balance) -> DispatchResult {
+
+        if !self.assets.contains_key(&id) {
+            return Err(LedgerError::UnknownAsset);
+        }
+        let spenders = self.approvals.entry(id).or_default().entry(owner.clone()).or_default();
+        if amount.is_zero() {
+            spenders.remove(spender);
+        } else {
+            spenders.insert(spender.clone(), amount);
+        }
+        Ok(())
+    }
+
+    pub fn transfer_from(&mut self, spender: &T::AccountId, id: AssetId, owner: &T::AccountId, to: &T::AccountId, amount: T::Balance) -> DispatchResult {
+
+        let remaining = self.allowance(id, owner, spender).checked_sub(&amount).ok_or(LedgerError::AllowanceExceeded)?;
+        self.transfer(id, owner, to, amount)?;
+        self.approve(id, owner, spender, remaining)
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call<T>) -> DispatchResult {
+
+        match call {
+            Call::Create { id, name, symbol, decimals } => self.create(caller, *id, name.clone(), symbol.clone(), *decimals),
+            Call::Mint { id, to, amount } => self.mint(caller, *id, to, *amount),
+            Call::Burn { id, from, amount } => self.burn(caller, *id, from, *amount),
+            Call::Transfer { id, to, amount } => self.transfer(*id, caller, to, *amount),
+            Call::Approve { id, spender, amount } => self.approve(*id, caller, spender, *amount),
+            Call::TransferFrom { id, owner, to, amount } => self.transfer_from(caller, *id, owner, to, *amount),
+        }
+    }
+
+    /// Root over asset metadata, holdings and allowances. The chain state
+    /// root commits to it as a single leaf.
+    pub fn state_root(&self) -> Hash {
+
+        let mut tree = FastMerkleTree::new();
+        for (id, asset) in &self.assets {
+            tree.add_leaf(Hash::from_string(&format!(
+                "asset:{}:{:?}:{}:{}:{}:{:?}",
+                id, asset.issuer, asset.name, asset.symbol, asset.decimals, asset.supply
+            )));
+        }
+        for (id, holders) in &self.balances {
+            for (who, balance) in holders {
+                tree.add_leaf(Hash::from_string(&format!("balance:{}:{:?}:{:?}", id, who, balance)));
+            }
+        }
+        for (id, owners) in &self.approvals {
+            for (owner, spenders) in owners {
+                for (spender, amount) in spenders {
+                    tree.add_leaf(Hash::from_string(&format!("approval:{}:{:?}:{:?}:{:?}", id, owner, spender, amount)));
+                }
+            }
+        }
+        tree.build();
+        tree.get_root().cloned().unwrap_or_else(|| Hash::from_string("empty"))
+    }
+
+    fn set_balance(&mut self, id: AssetId, who: &T::AccountId, amount: T::Balance) {
+
+        let holders = self.balances.entry(id).or_default();
+        if amount.is_zero() {
+            holders.remove(who);
+        } else {
+            holders.insert(who.clone(), amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Pallet {
+        let mut assets = Pallet::new();
+        let issuer = "issuer".to_string();
+        assets.create(&issuer, 1, "Bitcoin".to_string(), "BTC".to_string(), 8).unwrap();
+        assets.mint(&issuer, 1, &"alice".to_string(), 1_000).unwrap();
+        assets
+    }
+
+    #[test]
+    fn test_create_mint_burn() {
+        let mut assets = setup();
+        let issuer = "issuer".to_string();
+        let alice = "alice".to_string();
+
+        assert_eq!(assets.details(1).unwrap().decimals, 8);
+        assert!(assets.mint(&alice, 1, &alice, 5).is_err());
+        assets.burn(&issuer, 1, &alice, 400).unwrap();
+        assert_eq!(assets.total_supply(1), 600);
+        assert_eq!(assets.balance_of(1, &alice), 600);
+        assert!(assets.create(&alice, 1, "Dup".to_string(), "DUP".to_string(), 0).is_err());
+    }
+
+    #[test]
+    fn test_transfer_and_allowance() {
+        let mut assets = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let carol = "carol".to_string();
+
+        assets.transfer(1, &alice, &bob, 100).unwrap();
+        assets.approve(1, &alice, &carol, 50).unwrap();
+        assert!(assets.transfer_from(&carol, 1, &alice, &carol, 60).is_err());
+        assets.transfer_from(&carol, 1, &alice, &carol, 30).unwrap();
+
+        assert_eq!(assets.balance_of(1, &alice), 870);
+        assert_eq!(assets.balance_of(1, &bob), 100);
+        assert_eq!(assets.balance_of(1, &carol), 30);
+        assert_eq!(assets.allowance(1, &alice, &carol), 20);
+        assert_eq!(assets.total_supply(1), 1_000);
+    }
+
+    #[test]
+    fn test_state_root_tracks_holdings() {
+        let mut assets = setup();
+        let before = assets.state_root();
+        assets.transfer(1, &"alice".to_string(), &"bob".to_string(), 1).unwrap();
+        assert_ne!(before, assets.state_root());
+    }
+}
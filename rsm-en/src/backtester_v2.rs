@@ -1,7 +1,50 @@
+use crate::error::ErrorCode;
 use crate::market::{MarketBar, OrderSide};
 use crate::strategy::{Position, Signal, Strategy, StrategyContext};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BacktestError {
+    InsufficientCash { required: f64, available: f64 },
+    InsufficientPosition { requested: f64, held: f64 },
+    NoPosition(String),
+}
+
+impl fmt::Display for BacktestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BacktestError::InsufficientCash { required, available } => {
+                write!(f, "Insufficient cash for trade: need {:.2}, have {:.2}", required, available)
+            }
+            BacktestError::InsufficientPosition { requested, held } => {
+                write!(f, "Insufficient position for trade: selling {}, holding {}", requested, held)
+            }
+            BacktestError::NoPosition(symbol) => write!(f, "No {} position to sell", symbol),
+        }
+    }
+}
+
+impl std::error::Error for BacktestError {}
+
+impl ErrorCode for BacktestError {
+    fn code(&self) -> u16 {
+        match self {
+            BacktestError::InsufficientCash { .. } => 4001,
+            BacktestError::InsufficientPosition { .. } => 4002,
+            BacktestError::NoPosition(_) => 4003,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "backtest"
+    }
+
+    fn http_status(&self) -> u16 {
+        422
+    }
+}
 #[derive(Debug, Clone)]
 pub enum ExecutionModel {
     Naive,
@@ -160,7 +203,7 @@ impl Backtester {
         intended_price: f64, 
         quantity: f64,
         volume: f64,
-    ) -> Result<(), String> {
+    ) -> Result<(), BacktestError> {
         let (fill_price, slippage, market_impact) = 
             self.calculate_fill_price(intended_price, side, quantity, volume);
         
@@ -171,7 +214,7 @@ impl Backtester {
             OrderSide::Buy => {
                 let total_cost = trade_value + commission;
                 if self.cash < total_cost {
-                    return Err("Insufficient cash for trade".to_string());
+                    return Err(BacktestError::InsufficientCash { required: total_cost, available: self.cash });
                 }
 
                 self.cash -= total_cost;
@@ -206,7 +249,7 @@ impl Backtester {
             OrderSide::Sell => {
                 if let Some(position) = self.positions.get_mut(&symbol) {
                     if position.quantity < quantity {
-                        return Err("Insufficient position for trade".to_string());
+                        return Err(BacktestError::InsufficientPosition { requested: quantity, held: position.quantity });
                     }
 
                     let proceeds = trade_value - commission;
@@ -232,7 +275,7 @@ impl Backtester {
 
                     Ok(())
                 } else {
-                    Err("No position to sell".to_string())
+                    Err(BacktestError::NoPosition(symbol))
                 }
             }
         }
@@ -728,6 +771,16 @@ mod tests {
         assert_eq!(backtester.cash, 10000.0);
     }
 
+    #[test]
+    fn test_execute_trade_errors() {
+        let mut backtester = Backtester::new(100.0);
+        let buy = backtester.execute_trade(0, "BTC".to_string(), OrderSide::Buy, 50.0, 10.0, 1000.0);
+        assert_eq!(buy.unwrap_err().code(), 4001);
+        let sell = backtester.execute_trade(0, "BTC".to_string(), OrderSide::Sell, 50.0, 1.0, 1000.0);
+        assert_eq!(sell, Err(BacktestError::NoPosition("BTC".to_string())));
+        assert!(backtester.trade_history.is_empty());
+    }
+
     #[test]
     fn test_sharpe_ratio() {
         let returns = vec![0.01, 0.02, -0.01, 0.015, 0.005];
@@ -1,42 +1,515 @@
 use std::collections::BTreeMap;
+use std::fmt::Debug;
+use num_traits::{CheckedAdd, CheckedSub, One, PrimInt, Saturating, Unsigned, Zero};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use crate::runtime::{DefaultConfig, DispatchResult};
+use crate::system;
+use crate::error::LedgerError;
+
+pub trait Config: system::Config {
+    type Balance: PrimInt + Unsigned + Debug + Serialize + DeserializeOwned;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Call<T: Config = DefaultConfig> {
+    Transfer { to: T::AccountId, amount: T::Balance },
+}
+
+impl<T: Config> Call<T> {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Transfer { .. } => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Event<T: Config = DefaultConfig> {
+    Transfer { from: T::AccountId, to: T::AccountId, amount: T::Balance },
+}
+
+pub type LockId = [u8; 8];
+
+/// Freezes part of an account's free balance until block `until`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct BalanceLock<T: Config = DefaultConfig> {
+    pub id: LockId,
+    pub amount: T::Balance,
+    pub until: T::BlockNumber,
+}
+
+// Derived `Clone` would require `T: Clone`; the fields are all `Copy`.
+impl<T: Config> Clone for BalanceLock<T> {
+    fn clone(&self) -> Self {
+        BalanceLock { id: self.id, amount: self.amount, until: self.until }
+    }
+}
+
+/// An account's free balance and locks, `None` where it had none.
+type AccountRecord<T> = (<T as system::Config>::AccountId, Option<<T as Config>::Balance>, Option<Vec<BalanceLock<T>>>);
+
+/// Free balances and locks of a few accounts, plus issuance and queued
+/// events, taken by `Pallet::checkpoint` to roll back to. Costs what the
+/// accounts do, not the whole pallet.
+#[derive(Debug)]
+pub struct Checkpoint<T: Config = DefaultConfig> {
+    accounts: Vec<AccountRecord<T>>,
+    total_issuance: T::Balance,
+    events: usize,
+}
+
+/// Where repatriated reserved funds end up on the beneficiary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BalanceStatus {
+    Free,
+    Reserved,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Pallet {
-    pub balances: BTreeMap<String, u128>
+#[serde(bound = "")]
+pub struct Pallet<T: Config = DefaultConfig> {
+    /// Free balances. Only the unlocked part of these can be spent.
+    pub balances: BTreeMap<T::AccountId, T::Balance>,
+    pub reserved: BTreeMap<T::AccountId, T::Balance>,
+    pub locks: BTreeMap<T::AccountId, Vec<BalanceLock<T>>>,
+    /// Accounts whose balance would drop below this are reaped and the
+    /// remaining dust is burned.
+    pub existential_deposit: T::Balance,
+    pub total_issuance: T::Balance,
+    block_number: T::BlockNumber,
+    /// Emitted since the runtime last took them. Not part of state.
+    #[serde(skip)]
+    events: Vec<Event<T>>,
 }
 
-impl Pallet {
+impl<T: Config> Default for Pallet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Config> Pallet<T> {
 
     pub fn new() -> Self {
         
         Self {
 
-            balances: BTreeMap::new()
+            balances: BTreeMap::new(),
+            reserved: BTreeMap::new(),
+            locks: BTreeMap::new(),
+            existential_deposit: T::Balance::one(),
+            total_issuance: T::Balance::zero(),
+            block_number: T::BlockNumber::zero(),
+            events: Vec::new(),
         }
     }
 
-    pub fn set_balance(&mut self, who: &String, amount: u128){
+    /// Forces an account's balance, minting or burning the difference.
+    pub fn set_balance(&mut self, who: &T::AccountId, amount: T::Balance){
 
-        self.balances.insert(who.clone(), amount);
+        let old: T::Balance = self.get_balance(who);
+        self.total_issuance = self.total_issuance.saturating_sub(old).saturating_add(amount);
+        self.write_balance(who, amount);
     }
 
-    pub fn get_balance(&mut self, who: &String) -> u128{
+    pub fn get_balance(&self, who: &T::AccountId) -> T::Balance{
         
-        *self.balances.get(who).unwrap_or(&0)
+        self.balances.get(who).copied().unwrap_or_else(T::Balance::zero)
+    }
+
+    pub fn reserved_balance(&self, who: &T::AccountId) -> T::Balance{
+
+        self.reserved.get(who).copied().unwrap_or_else(T::Balance::zero)
+    }
+
+    pub fn total_balance(&self, who: &T::AccountId) -> T::Balance{
+
+        self.get_balance(who).saturating_add(self.reserved_balance(who))
+    }
+
+    /// Free balance held back by locks. Overlapping locks do not stack; the
+    /// largest one applies.
+    pub fn frozen_balance(&self, who: &T::AccountId) -> T::Balance{
+
+        self.locks
+            .get(who)
+            .into_iter()
+            .flatten()
+            .filter(|lock| lock.until > self.block_number)
+            .map(|lock| lock.amount)
+            .max()
+            .unwrap_or_else(T::Balance::zero)
+    }
+
+    /// Free balance that transfers may spend.
+    pub fn usable_balance(&self, who: &T::AccountId) -> T::Balance{
+
+        self.get_balance(who).saturating_sub(self.frozen_balance(who))
     }
 
-    pub fn transfer(&mut self, sender: String, reciever: String, amount: u128) -> Result<(), &'static str>{
+    pub fn transfer(&mut self, sender: T::AccountId, reciever: T::AccountId, amount: T::Balance) -> Result<(), LedgerError>{
         
-        let sender_balance: u128 = self.get_balance(&sender);
-        let reciever_balance: u128 = self.get_balance(&reciever);
+        let sender_balance: T::Balance = self.get_balance(&sender);
+        let reciever_balance: T::Balance = self.get_balance(&reciever);
+
+        let new_sender_balance: T::Balance = sender_balance.checked_sub(&amount).ok_or(LedgerError::InsufficientBalance)?;
+        if amount > self.usable_balance(&sender) {
+            return Err(LedgerError::BalanceLocked);
+        }
+        if sender == reciever {
+            return Ok(());
+        }
+        let new_reciever_balance: T::Balance = reciever_balance.checked_add(&amount).ok_or(LedgerError::Arithmetic)?;
+        if new_reciever_balance < self.existential_deposit {
+            return Err(LedgerError::ExistentialDeposit);
+        }
+
+        self.write_balance(&sender, new_sender_balance);
+        self.write_balance(&reciever, new_reciever_balance);
+        self.events.push(Event::Transfer { from: sender, to: reciever, amount });
+
+        Ok(())
+    }
+
+    /// Burns `amount` from an account.
+    pub fn withdraw(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), LedgerError>{
+
+        let balance: T::Balance = self.get_balance(who);
+        let new_balance: T::Balance = balance.checked_sub(&amount).ok_or(LedgerError::InsufficientBalance)?;
+        if amount > self.usable_balance(who) {
+            return Err(LedgerError::BalanceLocked);
+        }
+        self.total_issuance = self.total_issuance.saturating_sub(amount);
+        self.write_balance(who, new_balance);
+
+        Ok(())
+    }
+
+    /// Burns a transaction fee. Locks only guard against spending, so fees
+    /// may come out of locked funds.
+    pub fn withdraw_fee(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), LedgerError>{
+
+        let balance: T::Balance = self.get_balance(who);
+        let new_balance: T::Balance = balance.checked_sub(&amount).ok_or(LedgerError::InsufficientBalance)?;
+        self.total_issuance = self.total_issuance.saturating_sub(amount);
+        self.write_balance(who, new_balance);
+
+        Ok(())
+    }
+
+    /// Mints `amount` into an account. Deposits that would leave the account
+    /// below the existential deposit are dropped. Returns what was credited.
+    pub fn deposit(&mut self, who: &T::AccountId, amount: T::Balance) -> T::Balance{
+
+        let balance: T::Balance = self.get_balance(who);
+        let new_balance: T::Balance = balance.saturating_add(amount);
+        if new_balance < self.existential_deposit {
+            return T::Balance::zero();
+        }
+        let credited: T::Balance = new_balance - balance;
+        self.total_issuance = self.total_issuance.saturating_add(credited);
+        self.write_balance(who, new_balance);
+
+        credited
+    }
+
+    /// Moves `amount` of unlocked free balance into the reserve.
+    pub fn reserve(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), LedgerError>{
+
+        if amount > self.usable_balance(who) {
+            return Err(LedgerError::InsufficientBalance);
+        }
+        let reserved: T::Balance = self.reserved_balance(who).checked_add(&amount).ok_or(LedgerError::Arithmetic)?;
+        self.reserved.insert(who.clone(), reserved);
+        let free: T::Balance = self.get_balance(who) - amount;
+        self.write_balance(who, free);
+
+        Ok(())
+    }
+
+    /// Moves up to `amount` back from the reserve. Returns what was moved.
+    pub fn unreserve(&mut self, who: &T::AccountId, amount: T::Balance) -> T::Balance{
+
+        let actual: T::Balance = amount.min(self.reserved_balance(who));
+        if actual.is_zero() {
+            return actual;
+        }
+        self.write_reserved(who, self.reserved_balance(who) - actual);
+        let free: T::Balance = self.get_balance(who).saturating_add(actual);
+        self.write_balance(who, free);
+
+        actual
+    }
+
+    /// Burns up to `amount` from the reserve. Returns what was burned.
+    pub fn slash_reserved(&mut self, who: &T::AccountId, amount: T::Balance) -> T::Balance{
 
-        let new_sender_balance: u128 = sender_balance.checked_sub(amount).ok_or("Insufficient sender balance")?;
-        let new_reciever_balance: u128 = reciever_balance.checked_add(amount).ok_or("Error adding balance")?;
+        let actual: T::Balance = amount.min(self.reserved_balance(who));
+        self.write_reserved(who, self.reserved_balance(who) - actual);
+        self.total_issuance = self.total_issuance.saturating_sub(actual);
+        let free: T::Balance = self.get_balance(who);
+        self.write_balance(who, free);
 
-        self.set_balance(&sender, new_sender_balance);
-        self.set_balance(&reciever, new_reciever_balance);
+        actual
+    }
+
+    /// Moves up to `amount` of `slashed`'s reserve to `beneficiary`, landing
+    /// in its free or reserved balance. Returns what was moved.
+    pub fn repatriate_reserved(&mut self, slashed: &T::AccountId, beneficiary: &T::AccountId, amount: T::Balance, status: BalanceStatus) -> Result<T::Balance, LedgerError>{
+
+        let actual: T::Balance = amount.min(self.reserved_balance(slashed));
+        if slashed == beneficiary {
+            return Ok(match status {
+                BalanceStatus::Free => self.unreserve(slashed, actual),
+                BalanceStatus::Reserved => actual,
+            });
+        }
+        match status {
+            BalanceStatus::Free => {
+                let free: T::Balance = self.get_balance(beneficiary).checked_add(&actual).ok_or(LedgerError::Arithmetic)?;
+                if free < self.existential_deposit && self.reserved_balance(beneficiary).is_zero() {
+                    return Err(LedgerError::ExistentialDeposit);
+                }
+                self.write_balance(beneficiary, free);
+            }
+            BalanceStatus::Reserved => {
+                let reserved: T::Balance = self.reserved_balance(beneficiary).checked_add(&actual).ok_or(LedgerError::Arithmetic)?;
+                self.write_reserved(beneficiary, reserved);
+            }
+        }
+        self.write_reserved(slashed, self.reserved_balance(slashed) - actual);
+        let free: T::Balance = self.get_balance(slashed);
+        self.write_balance(slashed, free);
+
+        Ok(actual)
+    }
+
+    /// Adds or replaces the lock named `id` on an account.
+    pub fn set_lock(&mut self, id: LockId, who: &T::AccountId, amount: T::Balance, until: T::BlockNumber){
+
+        let locks = self.locks.entry(who.clone()).or_default();
+        locks.retain(|lock| lock.id != id);
+        locks.push(BalanceLock { id, amount, until });
+    }
+
+    pub fn remove_lock(&mut self, id: LockId, who: &T::AccountId){
+
+        if let Some(locks) = self.locks.get_mut(who) {
+            locks.retain(|lock| lock.id != id);
+            if locks.is_empty() {
+                self.locks.remove(who);
+            }
+        }
+    }
+
+    /// Advances the pallet's view of the block number and drops locks that
+    /// have expired.
+    pub fn on_finalize(&mut self, now: T::BlockNumber){
+
+        self.block_number = now;
+        self.locks.retain(|_, locks| {
+            locks.retain(|lock| lock.until > now);
+            !locks.is_empty()
+        });
+    }
+
+    /// Records `accounts` so `restore` can undo transfers between them.
+    /// Reserves are not recorded; transfers never move them.
+    pub fn checkpoint<'a>(&self, accounts: impl IntoIterator<Item = &'a T::AccountId>) -> Checkpoint<T> where T::AccountId: 'a {
+
+        let mut recorded: Vec<AccountRecord<T>> = Vec::new();
+        for who in accounts {
+            if !recorded.iter().any(|(account, _, _)| account == who) {
+                recorded.push((who.clone(), self.balances.get(who).copied(), self.locks.get(who).cloned()));
+            }
+        }
+        Checkpoint { accounts: recorded, total_issuance: self.total_issuance, events: self.events.len() }
+    }
+
+    /// Puts back what `checkpoint` recorded and drops events queued since.
+    pub fn restore(&mut self, checkpoint: Checkpoint<T>){
+
+        for (who, balance, locks) in checkpoint.accounts {
+            match balance {
+                Some(balance) => self.balances.insert(who.clone(), balance),
+                None => self.balances.remove(&who),
+            };
+            match locks {
+                Some(locks) => self.locks.insert(who, locks),
+                None => self.locks.remove(&who),
+            };
+        }
+        self.total_issuance = checkpoint.total_issuance;
+        self.events.truncate(checkpoint.events);
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event<T>>{
+
+        std::mem::take(&mut self.events)
+    }
+
+    /// Checks that free and reserved balances add up to `total_issuance`.
+    pub fn check_total_issuance(&self) -> DispatchResult{
+
+        let sum: T::Balance = self.balances
+            .values()
+            .chain(self.reserved.values())
+            .try_fold(T::Balance::zero(), |acc, balance| acc.checked_add(balance))
+            .ok_or(LedgerError::Arithmetic)?;
+        if sum != self.total_issuance {
+            return Err(LedgerError::IssuanceMismatch(format!(
+                "sum of balances {:?} does not match total issuance {:?}", sum, self.total_issuance
+            )));
+        }
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Stores a balance whose issuance is already accounted for, reaping the
+    /// account and burning the dust if it falls below the existential deposit.
+    /// Accounts still holding a reserve are never reaped.
+    fn write_balance(&mut self, who: &T::AccountId, amount: T::Balance){
+
+        let has_reserve: bool = !self.reserved_balance(who).is_zero();
+        if amount.is_zero() || (amount < self.existential_deposit && !has_reserve) {
+            self.balances.remove(who);
+            self.total_issuance = self.total_issuance.saturating_sub(amount);
+            if !has_reserve {
+                self.locks.remove(who);
+            }
+        } else {
+            self.balances.insert(who.clone(), amount);
+        }
+    }
+
+    fn write_reserved(&mut self, who: &T::AccountId, amount: T::Balance){
+
+        if amount.is_zero() {
+            self.reserved.remove(who);
+        } else {
+            self.reserved.insert(who.clone(), amount);
+        }
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call<T>) -> DispatchResult {
+
+        match call {
+            Call::Transfer { to, amount } => self.transfer(caller.clone(), to.clone(), *amount),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pallet() -> Pallet {
+        let mut pallet = Pallet::new();
+        pallet.existential_deposit = 10;
+        pallet.set_balance(&"alice".to_string(), 100);
+        pallet
+    }
+
+    #[test]
+    fn test_transfer_reaps_dust() {
+        let mut pallet = pallet();
+        pallet.transfer("alice".to_string(), "bob".to_string(), 95).unwrap();
+
+        assert!(!pallet.balances.contains_key("alice"));
+        assert_eq!(pallet.get_balance(&"bob".to_string()), 95);
+        assert_eq!(pallet.total_issuance, 95);
+        assert!(pallet.check_total_issuance().is_ok());
+    }
+
+    #[test]
+    fn test_transfer_below_existential_deposit_rejected() {
+        let mut pallet = pallet();
+        assert_eq!(
+            pallet.transfer("alice".to_string(), "bob".to_string(), 5),
+            Err(LedgerError::ExistentialDeposit)
+        );
+        assert_eq!(pallet.get_balance(&"alice".to_string()), 100);
+    }
+
+    #[test]
+    fn test_mint_and_burn_track_issuance() {
+        let mut pallet = pallet();
+        assert_eq!(pallet.deposit(&"bob".to_string(), 5), 0);
+        assert_eq!(pallet.deposit(&"bob".to_string(), 50), 50);
+        pallet.withdraw(&"alice".to_string(), 30).unwrap();
+
+        assert_eq!(pallet.total_issuance, 120);
+        assert!(pallet.check_total_issuance().is_ok());
+
+        pallet.balances.insert("mallory".to_string(), 1);
+        assert!(pallet.check_total_issuance().is_err());
+    }
+
+    #[test]
+    fn test_reserve_and_repatriate() {
+        let mut pallet = pallet();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        pallet.reserve(&alice, 60).unwrap();
+        assert_eq!(pallet.get_balance(&alice), 40);
+        assert_eq!(pallet.reserved_balance(&alice), 60);
+        assert!(pallet.transfer(alice.clone(), bob.clone(), 50).is_err());
+
+        assert_eq!(pallet.repatriate_reserved(&alice, &bob, 25, BalanceStatus::Free), Ok(25));
+        assert_eq!(pallet.unreserve(&alice, 100), 35);
+        assert_eq!(pallet.get_balance(&alice), 75);
+        assert_eq!(pallet.get_balance(&bob), 25);
+        assert!(pallet.check_total_issuance().is_ok());
+    }
+
+    #[test]
+    fn test_reserved_account_is_not_reaped() {
+        let mut pallet = pallet();
+        let alice = "alice".to_string();
+
+        pallet.reserve(&alice, 95).unwrap();
+        assert_eq!(pallet.get_balance(&alice), 5);
+        assert_eq!(pallet.slash_reserved(&alice, 95), 95);
+        assert!(!pallet.balances.contains_key(&alice));
+        assert_eq!(pallet.total_issuance, 0);
+    }
+
+    #[test]
+    fn test_locks_expire() {
+        let mut pallet = pallet();
+        let alice = "alice".to_string();
+
+        pallet.set_lock(*b"vesting ", &alice, 70, 5);
+        pallet.set_lock(*b"staking ", &alice, 50, 8);
+        assert_eq!(pallet.usable_balance(&alice), 30);
+        assert!(pallet.transfer(alice.clone(), "bob".to_string(), 40).is_err());
+
+        pallet.on_finalize(5);
+        assert_eq!(pallet.usable_balance(&alice), 50);
+        pallet.remove_lock(*b"staking ", &alice);
+        pallet.transfer(alice.clone(), "bob".to_string(), 90).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_restores_touched_accounts() {
+        let mut pallet = pallet();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        pallet.set_lock(*b"vesting ", &alice, 5, 10);
+
+        let checkpoint = pallet.checkpoint([&alice, &bob]);
+        pallet.transfer(alice.clone(), bob.clone(), 95).unwrap();
+        assert_eq!(pallet.get_balance(&alice), 0);
+        pallet.restore(checkpoint);
+
+        assert_eq!(pallet.get_balance(&alice), 100);
+        assert_eq!(pallet.get_balance(&bob), 0);
+        assert_eq!(pallet.frozen_balance(&alice), 5);
+        assert!(pallet.take_events().is_empty());
+        assert!(pallet.check_total_issuance().is_ok());
+    }
+}
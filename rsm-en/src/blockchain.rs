@@ -1,100 +1,568 @@
+use crate::assets::AssetId;
+use crate::error::{ErrorCode, LedgerError};
+use crate::genesis::{halved_reward, GenesisError, GenesisSpec, LedgerMode, DEFAULT_CHAIN_ID};
+use crate::events::{Event, EventFilter, EventRecord, Phase};
+use crate::governance::{ParameterChange, Proposal};
+use crate::vesting::VestingInfo;
 use crate::merkle::{Hash, FastMerkleTree};
 use crate::transaction::{Transaction, Block};
-use crate::system::Pallet as SystemPallet;
-use crate::balances::Pallet as BalancesPallet;
-use crate::rps_mining::RPSMiner;
+use crate::runtime::Runtime;
+use crate::rps_mining::{MiningError, PlayerOutcome, RPSMiner, RPSMiningConfig};
+use crate::snapshot::{StateSnapshot, SyncBundle, SyncError, DEFAULT_SNAPSHOT_INTERVAL};
+use crate::storage::{MemoryStorage, OverlayedStorage, Storage, StorageError};
+use crate::utxo::{Script, TxOutput, UtxoBody, UtxoTransaction};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Backend the runtime state is written through to.
+pub type StateBackend = Box<dyn Storage + Send>;
+
+fn default_state() -> OverlayedStorage<StateBackend> {
+    OverlayedStorage::new(Box::new(MemoryStorage::new()))
+}
+
+fn default_snapshot_interval() -> u32 {
+    DEFAULT_SNAPSHOT_INTERVAL
+}
+
+fn default_chain_id() -> u64 {
+    DEFAULT_CHAIN_ID
+}
+
+/// Who a UTXO block's coinbase pays: the address of its first output, or
+/// the script itself when that is not pay-to-pubkey-hash.
+pub(crate) fn coinbase_payee(coinbase: &UtxoTransaction) -> String {
+    match coinbase.outputs.first().map(|output| &output.script) {
+        Some(Script::PayToPubkeyHash(address)) => address.to_hex(),
+        Some(script) => script.to_string(),
+        None => "network".to_string(),
+    }
+}
+
+/// Where a transaction stands, as far as this node knows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransactionStatus {
+    /// Queued for the next block, behind `position` others.
+    Pending { position: usize },
+    Included { block_index: u32, tx_index: usize },
+    /// Refused by the pool (no block), or failed while its block was built.
+    Rejected { block_index: Option<u32>, code: u16, reason: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Blockchain {
+    #[serde(default)]
+    pub chain_name: String,
+    /// Transactions must carry this chain ID.
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
+    #[serde(default)]
+    pub ledger_mode: LedgerMode,
     pub chain: Vec<Block>,
     pub difficulty: usize,
     pub pending_transactions: VecDeque<Transaction>,
+    #[serde(default)]
+    pub pending_utxo: VecDeque<UtxoTransaction>,
     pub mining_reward: u128,
-    pub system: SystemPallet,
-    pub balances: BalancesPallet,
+    /// Blocks between halvings of `mining_reward`. Zero never halves.
+    #[serde(default)]
+    pub halving_interval: u32,
+    pub runtime: Runtime,
     pub rps_miner: RPSMiner,
+    /// Per-player RPS outcomes by block index. Blocks only carry the root,
+    /// so proofs are served from here.
+    #[serde(default)]
+    pub rps_outcomes: BTreeMap<u32, Vec<PlayerOutcome>>,
+    /// (block index, transaction index) of every assets call, by asset.
+    #[serde(default)]
+    pub asset_index: BTreeMap<AssetId, Vec<(u32, usize)>>,
+    /// Events emitted while building each block, by block index.
+    #[serde(default)]
+    pub events: BTreeMap<u32, Vec<EventRecord>>,
+    /// Blocks at multiples of this height commit a state snapshot. Zero
+    /// turns snapshots off.
+    #[serde(default = "default_snapshot_interval")]
+    pub snapshot_interval: u32,
+    #[serde(default)]
+    pub latest_snapshot: Option<StateSnapshot>,
+    /// Committed runtime state plus the open block and transaction layers
+    /// while a block is being built.
+    #[serde(skip, default = "default_state")]
+    state: OverlayedStorage<StateBackend>,
+    /// Unix seconds to stamp mined blocks with instead of the wall clock.
+    #[serde(skip)]
+    clock: Option<u64>,
+    /// Whether unsigned transactions from named accounts are admitted.
+    #[serde(skip)]
+    dev_accounts: bool,
 }
 
 impl Blockchain {
+    /// The development chain, from `GenesisSpec::default()`, with dev
+    /// accounts enabled.
     pub fn new() -> Self {
-        let rps_config = crate::rps_mining::RPSMiningConfig::new();
-        let rps_miner = RPSMiner::new(rps_config);
-        
+        let mut blockchain = Self::from_genesis(&GenesisSpec::default()).expect("default genesis spec is valid");
+        blockchain.set_dev_accounts(true);
+        blockchain
+    }
+
+    /// Builds a chain from a genesis spec. The genesis block's previous hash
+    /// is the spec's hash, so chains from different specs never share blocks.
+    pub fn from_genesis(spec: &GenesisSpec) -> Result<Self, GenesisError> {
+        spec.validate()?;
+        let mut rps_config = RPSMiningConfig::new();
+        rps_config.total_players = spec.consensus.rps_total_players;
+
+        let mut runtime = Runtime::new();
+        runtime.fee_per_weight = spec.rewards.fee_per_weight;
+        runtime.staking.reward_per_block = spec.rewards.staking_reward_per_block;
+        let mut genesis = Block::new(0, Vec::new(), spec.hash());
+        genesis.timestamp = spec.timestamp;
+        genesis.hash = genesis.calculate_hash();
+        match spec.ledger {
+            LedgerMode::Account => {
+                for (account, balance) in &spec.balances {
+                    runtime.balances.set_balance(account, *balance);
+                }
+                for (account, schedules) in &spec.vesting {
+                    for schedule in schedules {
+                        runtime.vesting
+                            .add_schedule(account, schedule.clone(), 0, &mut runtime.balances)
+                            .map_err(|e| GenesisError::Invalid(format!("vesting for '{}': {}", account, e)))?;
+                    }
+                }
+            }
+            LedgerMode::Utxo => {
+                // Genesis balances become the outputs of a height-0 coinbase.
+                let outputs: Vec<TxOutput> = spec.balances
+                    .iter()
+                    .filter(|(_, value)| **value > 0)
+                    .map(|(address, value)| TxOutput {
+                        value: *value,
+                        script: Script::for_address(address).expect("validated"),
+                    })
+                    .collect();
+                let coinbase = UtxoTransaction::coinbase(spec.chain_id, 0, outputs);
+                runtime.utxo.apply_coinbase(&coinbase, 0, coinbase.output_value()).expect("validated");
+                genesis = genesis.with_utxo(UtxoBody { transactions: vec![coinbase], set_root: runtime.utxo.root() });
+            }
+        }
+
+        Ok(Self {
+            chain_name: spec.name.clone(),
+            chain_id: spec.chain_id,
+            ledger_mode: spec.ledger,
+            chain: vec![genesis],
+            difficulty: spec.consensus.difficulty,
+            pending_transactions: VecDeque::new(),
+            pending_utxo: VecDeque::new(),
+            mining_reward: spec.rewards.mining_reward,
+            halving_interval: spec.rewards.halving_interval,
+            runtime,
+            rps_miner: RPSMiner::with_seed(rps_config, spec.consensus.rps_seed),
+            rps_outcomes: BTreeMap::new(),
+            asset_index: BTreeMap::new(),
+            events: BTreeMap::new(),
+            snapshot_interval: spec.consensus.snapshot_interval,
+            latest_snapshot: None,
+            state: default_state(),
+            clock: None,
+            dev_accounts: false,
+        })
+    }
+
+    /// A new chain whose runtime state is written through to `backend`.
+    /// Any runtime state already in `backend` is replaced by genesis.
+    pub fn with_storage(backend: impl Storage + Send + 'static) -> Self {
+        let mut blockchain = Self::new();
+        blockchain.state = OverlayedStorage::new(Box::new(backend));
+        blockchain.runtime.store(&mut blockchain.state);
+        blockchain
+    }
+
+    /// Stamps blocks mined from now on with `now` (Unix seconds) rather
+    /// than the wall clock, for reproducible chains.
+    pub fn set_clock(&mut self, now: u64) {
+        self.clock = Some(now);
+    }
+
+    /// Admits unsigned transactions from named accounts such as "alice".
+    /// Anyone can spend from those, so only development nodes turn it on.
+    pub fn set_dev_accounts(&mut self, enabled: bool) {
+        self.dev_accounts = enabled;
+    }
+
+    fn now(&self) -> u64 {
+        self.clock.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    pub fn state(&self) -> &OverlayedStorage<StateBackend> {
+        &self.state
+    }
+
+    /// Persists committed state. File backends only reach disk here.
+    pub fn flush_state(&mut self) -> Result<(), StorageError> {
+        self.state.flush()
+    }
+
+    /// Starts a node from a snapshot instead of genesis. The first block of
+    /// the bundle must commit the snapshot's hash; it and every later block
+    /// are then checked and replayed on top of the snapshot state.
+    pub fn from_snapshot(bundle: SyncBundle) -> Result<Self, SyncError> {
+        let SyncBundle { snapshot, blocks } = bundle;
+        let height = snapshot.height;
+        match blocks.first() {
+            Some(first) if first.index == height => {
+                if first.snapshot_hash != Some(snapshot.hash()) || first.previous_hash != snapshot.parent_hash {
+                    return Err(SyncError::SnapshotMismatch { height });
+                }
+            }
+            _ => return Err(SyncError::MissingSnapshotBlock { height }),
+        }
+
         let mut blockchain = Self {
+            chain_name: snapshot.chain_name.clone(),
+            chain_id: snapshot.chain_id,
+            ledger_mode: snapshot.ledger_mode,
             chain: Vec::new(),
-            difficulty: 2,
+            difficulty: snapshot.difficulty,
             pending_transactions: VecDeque::new(),
-            mining_reward: 100,
-            system: SystemPallet::new(),
-            balances: BalancesPallet::new(),
-            rps_miner,
+            pending_utxo: VecDeque::new(),
+            mining_reward: snapshot.mining_reward,
+            halving_interval: snapshot.halving_interval,
+            runtime: snapshot.runtime,
+            rps_miner: snapshot.rps_miner,
+            rps_outcomes: BTreeMap::new(),
+            asset_index: BTreeMap::new(),
+            events: BTreeMap::new(),
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            latest_snapshot: None,
+            state: default_state(),
+            clock: None,
+            dev_accounts: false,
         };
-        
+        for block in blocks {
+            blockchain.import_block(block)?;
+        }
+        Ok(blockchain)
+    }
 
-        blockchain.create_genesis_block();
-        blockchain
+    /// Switches to a fork that leaves this chain at the parent of the first
+    /// of `blocks`, if the fork ends higher. State is rebuilt from `spec` by
+    /// replaying the shared blocks and then `blocks`; transactions that were
+    /// only in dropped blocks go back to the pool. A chain synced from a
+    /// snapshot has no blocks to rebuild from and never switches. The
+    /// storage backend, clock, dev-accounts setting and snapshot interval
+    /// carry over. Returns whether it switched.
+    pub fn reorganize(&mut self, spec: &GenesisSpec, blocks: Vec<Block>) -> Result<bool, SyncError> {
+        let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+            return Ok(false);
+        };
+        let fork = self.chain
+            .iter()
+            .position(|block| block.hash == first.previous_hash)
+            .ok_or(SyncError::InvalidBlock { index: first.index })?;
+        if last.index <= self.get_latest_block().index || self.chain[0].index != 0 {
+            return Ok(false);
+        }
+        let mut candidate = Blockchain::from_genesis(spec).map_err(|_| SyncError::InvalidBlock { index: 0 })?;
+        if candidate.chain[0].hash != self.chain[0].hash {
+            return Err(SyncError::InvalidBlock { index: 0 });
+        }
+        for block in self.chain[1..=fork].iter().cloned().chain(blocks) {
+            candidate.import_block(block)?;
+        }
+        candidate.snapshot_interval = self.snapshot_interval;
+        candidate.clock = self.clock;
+        candidate.dev_accounts = self.dev_accounts;
+        for (index, outcomes) in self.rps_outcomes.range(..=fork as u32) {
+            candidate.rps_outcomes.entry(*index).or_insert_with(|| outcomes.clone());
+        }
+        // Keep writing to this chain's backend, which now gets the new state.
+        std::mem::swap(&mut candidate.state, &mut self.state);
+        candidate.runtime.store(&mut candidate.state);
+
+        let dropped = std::mem::replace(self, candidate);
+        let orphaned = dropped.chain[fork + 1..]
+            .iter()
+            .flat_map(|block| block.transactions.iter().skip(1))
+            .chain(&dropped.pending_transactions);
+        for tx in orphaned {
+            // Those already in the new chain fail their nonce check.
+            let _ = self.add_transaction(tx.clone());
+        }
+        let orphaned_utxo = dropped.chain[fork + 1..]
+            .iter()
+            .filter_map(|block| block.utxo.as_ref())
+            .flat_map(|body| body.transactions.iter().skip(1))
+            .chain(&dropped.pending_utxo);
+        for tx in orphaned_utxo {
+            let _ = self.add_utxo_transaction(tx.clone());
+        }
+        Ok(true)
     }
 
-    fn create_genesis_block(&mut self) {
-        let mut genesis = Block::genesis();
+    /// The latest snapshot and the blocks from its height on, for a new node
+    /// to sync from. `None` until the first snapshot height is mined.
+    pub fn export_snapshot(&self) -> Option<SyncBundle> {
+        let snapshot = self.latest_snapshot.clone()?;
+        let blocks = self.chain
+            .iter()
+            .filter(|block| block.index >= snapshot.height)
+            .cloned()
+            .collect();
+        Some(SyncBundle { snapshot, blocks })
+    }
 
-        genesis.hash = genesis.calculate_hash();
-        self.chain.push(genesis);
-        
+    /// Checks a block mined elsewhere against the tip, replays its
+    /// transactions and appends it. Every transaction must apply; otherwise
+    /// the block is rolled back and rejected. Pending transactions the block
+    /// includes or conflicts with leave the pool.
+    pub fn import_block(&mut self, block: Block) -> Result<(), SyncError> {
+        let index = block.index;
+        let linked = match self.chain.last() {
+            Some(previous) => block.is_valid(Some(previous)),
+            None => block.is_self_consistent(),
+        };
+        let rps_result = block.rps_mining_result.clone().filter(|result| result.success);
+        let reward = self.block_reward(index);
+        let author = match self.ledger_mode {
+            LedgerMode::Account => block.transactions
+                .first()
+                .filter(|_| block.utxo.is_none())
+                .filter(|tx| tx.from == "network")
+                .and_then(|tx| tx.transfer_details())
+                .filter(|(_, amount)| *amount == reward)
+                .map(|(author, _)| author.clone()),
+            LedgerMode::Utxo => block.utxo
+                .as_ref()
+                .filter(|_| block.transactions.is_empty())
+                .and_then(|body| body.transactions.first())
+                .map(coinbase_payee),
+        };
+        let (Some(rps_result), Some(author)) = (rps_result, author) else {
+            return Err(SyncError::InvalidBlock { index });
+        };
+        let Some(outcomes) = self.rps_miner.replay_seal(&block.header().seal_data(), &rps_result) else {
+            return Err(SyncError::InvalidSeal { index });
+        };
+        let foreign = block.transactions.iter().any(|tx| tx.chain_id != self.chain_id);
+        if !linked || foreign {
+            return Err(SyncError::InvalidBlock { index });
+        }
+
+        let snapshot = match &block.snapshot_hash {
+            Some(committed) => {
+                let snapshot = self.snapshot_at(index, block.previous_hash.clone());
+                if snapshot.hash() != *committed {
+                    return Err(SyncError::SnapshotMismatch { height: index });
+                }
+                Some(snapshot)
+            }
+            None => None,
+        };
+
+        self.runtime.store(&mut self.state);
+        self.state.start_transaction();
+        let mut fees: u128 = 0;
+        let mut records = Vec::new();
+        for (tx_index, tx) in block.transactions.iter().enumerate().skip(1) {
+            match self.runtime.apply_extrinsic(tx) {
+                Ok(fee) => {
+                    fees += fee;
+                    let phase = Phase::ApplyExtrinsic(tx_index as u32);
+                    records.extend(self.runtime.take_events().into_iter().map(|event| {
+                        EventRecord { block: index, phase: phase.clone(), event }
+                    }));
+                }
+                Err(e) => {
+                    self.rollback_state();
+                    return Err(SyncError::ReplayFailed { index, reason: e.to_string() });
+                }
+            }
+        }
+
+        let mut payout = reward + fees;
+        if let Some(body) = &block.utxo {
+            if let Err(e) = self.replay_utxo(index, body, payout) {
+                self.rollback_state();
+                return Err(e);
+            }
+            records.push(EventRecord {
+                block: index,
+                phase: Phase::Finalization,
+                event: Event::RewardPaid { who: author.clone(), amount: body.transactions[0].output_value() },
+            });
+            payout = 0;
+        }
+
+        self.rps_miner.advance(&rps_result);
+        self.rps_outcomes.insert(index, outcomes);
+        if snapshot.is_some() {
+            self.latest_snapshot = snapshot;
+        }
+        self.finish_block(&block, &author, payout, records);
+        self.pending_transactions.retain(|tx| !block.transactions.iter().any(|included| included.hash == tx.hash));
+        let unspent = &self.runtime.utxo;
+        self.pending_utxo.retain(|tx| tx.inputs.iter().all(|input| unspent.get(&input.outpoint).is_some()));
+        Ok(())
+    }
+
+    /// Applies the UTXO body of an imported block: its spends, then a
+    /// coinbase of at most `max_coinbase` plus their fees, then checks the
+    /// committed set root.
+    fn replay_utxo(&mut self, index: u32, body: &UtxoBody, max_coinbase: u128) -> Result<(), SyncError> {
+        let replay_failed = |e: LedgerError| SyncError::ReplayFailed { index, reason: e.to_string() };
+        let (coinbase, spends) = body.transactions.split_first().ok_or(SyncError::InvalidBlock { index })?;
+        let mut fees: u128 = 0;
+        for tx in spends {
+            if tx.chain_id != self.chain_id {
+                return Err(SyncError::InvalidBlock { index });
+            }
+            fees += self.runtime.utxo.apply(tx, index).map_err(replay_failed)?;
+        }
+        self.runtime.utxo.apply_coinbase(coinbase, index, max_coinbase + fees).map_err(replay_failed)?;
+        if coinbase.chain_id != self.chain_id || self.runtime.utxo.root() != body.set_root {
+            return Err(SyncError::InvalidBlock { index });
+        }
+        Ok(())
+    }
+
+    fn snapshot_at(&self, height: u32, parent_hash: Hash) -> StateSnapshot {
+        StateSnapshot {
+            height,
+            parent_hash,
+            chain_name: self.chain_name.clone(),
+            chain_id: self.chain_id,
+            ledger_mode: self.ledger_mode,
+            runtime: self.runtime.clone(),
+            rps_miner: self.rps_miner.clone(),
+            mining_reward: self.mining_reward,
+            halving_interval: self.halving_interval,
+            difficulty: self.difficulty,
+        }
+    }
 
-        self.balances.set_balance(&"genesis".to_string(), 1_000_000);
-        self.balances.set_balance(&"alice".to_string(), 1000);
-        self.balances.set_balance(&"bob".to_string(), 500);
+    /// Mining reward for the block at `height`, after halvings.
+    pub fn block_reward(&self, height: u32) -> u128 {
+        halved_reward(self.mining_reward, self.halving_interval, height)
     }
 
     pub fn get_latest_block(&self) -> &Block {
         self.chain.last().expect("Chain should have at least genesis block")
     }
 
-    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), LedgerError> {
         if !transaction.is_valid() {
-            return Err("Invalid transaction".to_string());
+            return Err(LedgerError::InvalidTransaction);
         }
-        let sender_balance = self.balances.get_balance(&transaction.from);
-        if sender_balance < transaction.amount {
-            return Err("Insufficient balance".to_string());
+        if transaction.chain_id != self.chain_id {
+            return Err(LedgerError::WrongChain);
         }
-        let expected_nonce = self.system.get_nonce(&transaction.from);
-        if transaction.nonce != expected_nonce + 1 {
-            return Err("Invalid nonce".to_string());
+        if self.ledger_mode != LedgerMode::Account {
+            return Err(LedgerError::WrongLedgerMode);
         }
+        if transaction.signature.is_none() && !self.dev_accounts {
+            return Err(LedgerError::UnsignedTransaction);
+        }
+        let pending_from_signer = self.pending_transactions
+            .iter()
+            .filter(|tx| tx.from == transaction.from)
+            .count() as u32;
+        self.runtime.validate_transaction(&transaction, pending_from_signer)?;
 
         self.pending_transactions.push_back(transaction);
         Ok(())
     }
 
-    pub fn mine_pending_transactions(&mut self, mining_reward_address: String) -> Result<Block, String> {
+    /// Queues a spend for the next block, in UTXO mode. It may only spend
+    /// confirmed outputs that no queued spend uses.
+    pub fn add_utxo_transaction(&mut self, transaction: UtxoTransaction) -> Result<(), LedgerError> {
+        if self.ledger_mode != LedgerMode::Utxo {
+            return Err(LedgerError::WrongLedgerMode);
+        }
+        if transaction.chain_id != self.chain_id {
+            return Err(LedgerError::WrongChain);
+        }
+        let conflicts = transaction.inputs.iter().any(|input| {
+            self.pending_utxo.iter().flat_map(|tx| &tx.inputs).any(|queued| queued.outpoint == input.outpoint)
+        });
+        if conflicts {
+            return Err(LedgerError::DoubleSpend);
+        }
+        self.runtime.utxo.check(&transaction, self.get_latest_block().index + 1)?;
 
+        self.pending_utxo.push_back(transaction);
+        Ok(())
+    }
+
+    /// Builds, mines and appends a block. Each transaction runs in its own
+    /// storage transaction inside one for the whole block: a failed
+    /// transaction is rolled back alone, and if mining fails the block is
+    /// rolled back and its transactions return to the pool. In UTXO mode
+    /// the reward goes to a coinbase paying `mining_reward_address`, which
+    /// must then be an address.
+    pub fn mine_pending_transactions(&mut self, mining_reward_address: String) -> Result<Block, MiningError> {
+
+        let block_index = self.get_latest_block().index + 1;
+        let reward = self.block_reward(block_index);
+        let coinbase_script = match self.ledger_mode {
+            LedgerMode::Account => None,
+            LedgerMode::Utxo => Some(
+                Script::for_address(&mining_reward_address)
+                    .ok_or_else(|| MiningError::InvalidPayoutAddress(mining_reward_address.clone()))?,
+            ),
+        };
         let reward_tx = Transaction::new(
             "network".to_string(),
             mining_reward_address.clone(),
-            self.mining_reward,
+            reward,
             0
-        );
+        ).with_chain_id(self.chain_id).with_timestamp(self.now());
+
+        let snapshot = (self.snapshot_interval > 0 && block_index.is_multiple_of(self.snapshot_interval))
+            .then(|| self.snapshot_at(block_index, self.get_latest_block().hash.clone()));
+        let mut transactions = match coinbase_script {
+            None => vec![reward_tx],
+            Some(_) => Vec::new(),
+        };
+        let mut records = Vec::new();
+        let mut fees: u128 = 0;
+        let mut taken = Vec::new();
 
-        let mut transactions = Vec::new();
-        transactions.push(reward_tx);
+        // Direct edits to the runtime (genesis, tests) are captured here,
+        // so rollbacks restore exactly the state the block started from.
+        self.runtime.store(&mut self.state);
+        self.state.start_transaction();
         while let Some(tx) = self.pending_transactions.pop_front() {
 
-            match self.balances.transfer(
-                tx.from.clone(),
-                tx.to.clone(),
-                tx.amount
-            ) {
-                Ok(_) => {
-                    self.system.inc_nonce(&tx.from);
+            taken.push(tx.clone());
+            self.state.start_transaction();
+            match self.runtime.apply_extrinsic(&tx) {
+                Ok(fee) => {
+                    self.runtime.store(&mut self.state);
+                    self.commit_state();
+                    fees += fee;
+                    let phase = Phase::ApplyExtrinsic(transactions.len() as u32);
+                    records.extend(self.runtime.take_events().into_iter().map(|event| {
+                        EventRecord { block: block_index, phase: phase.clone(), event }
+                    }));
                     transactions.push(tx);
                 }
                 Err(e) => {
-                    println!("Transaction failed: {}", e);
-
+                    self.rollback_state();
+                    records.push(EventRecord {
+                        block: block_index,
+                        phase: Phase::Rejected,
+                        event: Event::TransactionFailed {
+                            tx_hash: tx.hash.to_hex(),
+                            who: tx.from.clone(),
+                            code: e.code(),
+                            reason: e.to_string(),
+                        },
+                    });
                 }
             }
             if transactions.len() >= 100 {
@@ -102,36 +570,177 @@ impl Blockchain {
             }
         }
 
+        let mut taken_utxo = Vec::new();
+        let utxo_body = coinbase_script.map(|script| {
+            let mut spends = Vec::new();
+            while let Some(tx) = self.pending_utxo.pop_front() {
+                taken_utxo.push(tx.clone());
+                // Spends were checked on submission; one that no longer
+                // applies (e.g. its timelock moved) is dropped.
+                if let Ok(fee) = self.runtime.utxo.apply(&tx, block_index) {
+                    fees += fee;
+                    spends.push(tx);
+                }
+                if spends.len() >= 100 {
+                    break;
+                }
+            }
+            let value = reward + fees;
+            let outputs = if value > 0 { vec![TxOutput { value, script }] } else { Vec::new() };
+            let coinbase = UtxoTransaction::coinbase(self.chain_id, block_index, outputs);
+            self.runtime.utxo.apply_coinbase(&coinbase, block_index, value).expect("coinbase is well formed");
+            records.push(EventRecord {
+                block: block_index,
+                phase: Phase::Finalization,
+                event: Event::RewardPaid { who: mining_reward_address.clone(), amount: value },
+            });
+            spends.insert(0, coinbase);
+            UtxoBody { transactions: spends, set_root: self.runtime.utxo.root() }
+        });
+        // A coinbase pays the author itself, so finalization pays nothing.
+        let payout = if utxo_body.is_some() { 0 } else { reward + fees };
+
         let previous_hash = self.get_latest_block().hash.clone();
         let mut new_block = Block::new(
-            self.chain.len() as u32,
+            block_index,
             transactions,
             previous_hash
-        );
+        ).with_timestamp(self.now());
+        if let Some(ref snapshot) = snapshot {
+            new_block = new_block.with_snapshot_hash(snapshot.hash());
+        }
+        if let Some(body) = utxo_body {
+            new_block = new_block.with_utxo(body);
+        }
         match new_block.mine_block_rps(&mut self.rps_miner) {
-            Ok(_) => {
-
-                let current_balance = self.balances.get_balance(&mining_reward_address);
-                self.balances.set_balance(
-                    &mining_reward_address,
-                    current_balance + self.mining_reward
-                );
-                self.system.inc_block_number(&mining_reward_address);
-
-                self.chain.push(new_block.clone());
+            Ok(outcomes) => {
+                self.rps_outcomes.insert(new_block.index, outcomes);
+                if snapshot.is_some() {
+                    self.latest_snapshot = snapshot;
+                }
+                self.finish_block(&new_block, &mining_reward_address, payout, records);
                 Ok(new_block)
             }
-            Err(e) => Err(format!("RPS Mining failed: {}", e))
+            Err(e) => {
+                self.rollback_state();
+                let mut requeued = VecDeque::from(taken);
+                requeued.append(&mut self.pending_transactions);
+                self.pending_transactions = requeued;
+                let mut requeued = VecDeque::from(taken_utxo);
+                requeued.append(&mut self.pending_utxo);
+                self.pending_utxo = requeued;
+                Err(e)
+            }
+        }
+    }
+
+    /// Finalizes a block whose transactions have been applied inside an
+    /// open block transaction, records its events, commits its state and
+    /// appends it.
+    fn finish_block(&mut self, block: &Block, author: &String, payout: u128, mut records: Vec<EventRecord>) {
+        let enacted = self.runtime.finalize_block(author, payout);
+        for change in enacted {
+            self.apply_parameter_change(change);
+        }
+        self.index_block(block);
+
+        let rps_result = block.rps_mining_result.as_ref();
+        let finalization = self.runtime.take_events().into_iter().chain(std::iter::once(Event::BlockMined {
+            number: block.index,
+            author: author.clone(),
+            hash: block.hash.to_hex(),
+            rounds: rps_result.map_or(0, |r| r.rounds),
+            total_games: rps_result.map_or(0, |r| r.total_games),
+        }));
+        records.extend(finalization.map(|event| EventRecord { block: block.index, phase: Phase::Finalization, event }));
+        self.events.insert(block.index, records);
+
+        self.runtime.store(&mut self.state);
+        self.commit_state();
+        self.chain.push(block.clone());
+    }
+
+    fn commit_state(&mut self) {
+        self.state.commit_transaction().expect("a storage transaction is open");
+    }
+
+    /// Discards the innermost storage transaction and reloads the runtime
+    /// from what is left, dropping any events it emitted.
+    fn rollback_state(&mut self) {
+        self.state.rollback_transaction().expect("a storage transaction is open");
+        self.runtime = Runtime::load(&self.state)
+            .expect("runtime state was stored at block start")
+            .expect("runtime state was stored at block start");
+    }
+
+    /// Applies the enacted changes that live outside the runtime.
+    fn apply_parameter_change(&mut self, change: ParameterChange) {
+        match change {
+            ParameterChange::MiningReward(reward) => self.mining_reward = reward,
+            ParameterChange::Difficulty(difficulty) => self.difficulty = difficulty,
+            ParameterChange::RpsTotalPlayers(players) => self.rps_miner.set_total_players(players),
+            _ => {}
         }
     }
 
+    /// The nonce the signer's next transaction must carry, counting the ones
+    /// already in the pool.
+    pub fn next_nonce(&self, address: &String) -> u32 {
+        let pending = self.pending_transactions
+            .iter()
+            .filter(|tx| tx.from == *address)
+            .count() as u32;
+        self.runtime.system.get_nonce(address) + pending + 1
+    }
+
+    pub fn get_proposals(&self) -> Vec<&Proposal> {
+        self.runtime.governance.proposals.values().collect()
+    }
+
+    /// Events matching `filter`, oldest first.
+    pub fn query_events(&self, filter: &EventFilter) -> Vec<&EventRecord> {
+        let from = filter.from_block.unwrap_or(0);
+        let to = filter.to_block.unwrap_or(u32::MAX);
+        if from > to {
+            return Vec::new();
+        }
+        self.events
+            .range(from..=to)
+            .flat_map(|(_, records)| records)
+            .filter(|record| filter.matches(record))
+            .collect()
+    }
+
+    /// Locked and vested funds of `address` at block `at`, or at the current
+    /// height. Future heights show how much will have unlocked by then.
+    pub fn get_vesting_info(&self, address: &String, at: Option<u32>) -> VestingInfo {
+        let at = at.unwrap_or_else(|| self.runtime.system.get_block_number());
+        self.runtime.vesting.vesting_info(address, at)
+    }
+
     #[allow(dead_code)]
-    pub fn get_balance(&mut self, address: &String) -> u128 {
-        self.balances.get_balance(address)
+    pub fn get_balance(&self, address: &String) -> u128 {
+        self.runtime.balances.get_balance(address)
     }
 
+    /// `get_balance` in account mode. In UTXO mode, the value of the
+    /// outputs locked to `account` alone, which must be a hex address.
+    pub fn account_balance(&self, account: &str) -> u128 {
+        match self.ledger_mode {
+            LedgerMode::Account => self.get_balance(&account.to_string()),
+            LedgerMode::Utxo => Hash::from_hex(account)
+                .map(|address| self.runtime.utxo.unspent_for(&address).iter().map(|(_, utxo)| utxo.output.value).sum())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Whether every block links to and is valid after its parent, and
+    /// balances still add up to total issuance.
     #[allow(dead_code)]
     pub fn is_chain_valid(&self) -> bool {
+        if self.runtime.balances.check_total_issuance().is_err() {
+            return false;
+        }
         for i in 1..self.chain.len() {
             let current_block = &self.chain[i];
             let previous_block = &self.chain[i - 1];
@@ -158,7 +767,7 @@ impl Blockchain {
         
         for block in &self.chain {
             for tx in &block.transactions {
-                if tx.from == *address || tx.to == *address {
+                if tx.involves(address) {
                     history.push(tx);
                 }
             }
@@ -167,6 +776,30 @@ impl Blockchain {
         history
     }
 
+    fn index_block(&mut self, block: &Block) {
+        for (tx_index, tx) in block.transactions.iter().enumerate() {
+            if let Some(asset_id) = tx.call.asset_id() {
+                self.asset_index.entry(asset_id).or_default().push((block.index, tx_index));
+            }
+        }
+    }
+
+    /// Transactions touching `asset_id`, optionally narrowed to those that
+    /// involve `address`.
+    pub fn get_asset_transaction_history(&self, asset_id: AssetId, address: Option<&String>) -> Vec<&Transaction> {
+        let Some(locations) = self.asset_index.get(&asset_id) else {
+            return Vec::new();
+        };
+
+        locations
+            .iter()
+            .filter_map(|(block_index, tx_index)| {
+                self.block_at(*block_index)?.transactions.get(*tx_index)
+            })
+            .filter(|tx| address.is_none_or(|a| tx.involves(a)))
+            .collect()
+    }
+
     #[allow(dead_code)]
     pub fn find_transaction(&self, tx_hash: &Hash) -> Option<(&Block, &Transaction, usize)> {
         for block in &self.chain {
@@ -179,6 +812,26 @@ impl Blockchain {
         None
     }
 
+    /// Pending, included, or failed in a block. Transactions the pool
+    /// refused were never seen, so they are `None`.
+    pub fn transaction_status(&self, tx_hash: &Hash) -> Option<TransactionStatus> {
+        if let Some(position) = self.pending_transactions.iter().position(|tx| tx.hash == *tx_hash) {
+            return Some(TransactionStatus::Pending { position });
+        }
+        if let Some((block, _, tx_index)) = self.find_transaction(tx_hash) {
+            return Some(TransactionStatus::Included { block_index: block.index, tx_index });
+        }
+        let hex = tx_hash.to_hex();
+        self.events.values().rev().flatten().find_map(|record| match &record.event {
+            Event::TransactionFailed { tx_hash, code, reason, .. } if *tx_hash == hex => Some(TransactionStatus::Rejected {
+                block_index: Some(record.block),
+                code: *code,
+                reason: reason.clone(),
+            }),
+            _ => None,
+        })
+    }
+
     #[allow(dead_code)]
     pub fn get_transaction_proof(&self, tx_hash: &Hash) -> Option<(Vec<Hash>, usize, u32)> {
         if let Some((block, _tx, tx_index)) = self.find_transaction(tx_hash) {
@@ -191,7 +844,7 @@ impl Blockchain {
 
     #[allow(dead_code)]
     pub fn verify_transaction_proof(&self, tx: &Transaction, proof: &[Hash], tx_index: usize, block_index: u32) -> bool {
-        if let Some(block) = self.chain.get(block_index as usize) {
+        if let Some(block) = self.block_at(block_index) {
             return block.verify_transaction_inclusion(tx, proof, tx_index);
         }
         false
@@ -199,7 +852,14 @@ impl Blockchain {
 
     #[allow(dead_code)]
     pub fn get_block_by_index(&self, index: u32) -> Option<&Block> {
-        self.chain.get(index as usize)
+        self.block_at(index)
+    }
+
+    /// A chain synced from a snapshot starts at the snapshot height, so
+    /// block indices are offset from positions in `chain`.
+    fn block_at(&self, index: u32) -> Option<&Block> {
+        let first = self.chain.first()?.index;
+        self.chain.get(index.checked_sub(first)? as usize)
     }
 
     #[allow(dead_code)]
@@ -207,8 +867,9 @@ impl Blockchain {
         self.chain.iter().find(|block| block.hash == *hash)
     }
 
+    /// Height plus one, counting blocks before a sync snapshot.
     pub fn get_chain_length(&self) -> usize {
-        self.chain.len()
+        self.get_latest_block().index as usize + 1
     }
 
     #[allow(dead_code)]
@@ -244,6 +905,18 @@ impl Blockchain {
         }
     }
 
+    pub fn get_player_outcome_proof(&self, block_index: u32, player_id: u32) -> Option<(PlayerOutcome, Vec<Hash>)> {
+        let outcomes = self.rps_outcomes.get(&block_index)?;
+        crate::rps_mining::outcome_proof(outcomes, player_id)
+    }
+
+    pub fn verify_player_outcome(&self, block_index: u32, outcome: &PlayerOutcome, proof: &[Hash]) -> bool {
+        match self.get_block_by_index(block_index).and_then(|b| b.rps_mining_result.as_ref()) {
+            Some(rps_result) => rps_result.verify_player_outcome(outcome, proof),
+            None => false,
+        }
+    }
+
     pub fn get_rps_difficulty_info(&self) -> crate::rps_mining::DifficultyInfo {
         self.rps_miner.get_difficulty_info()
     }
@@ -266,10 +939,16 @@ impl Blockchain {
         let mut tree = FastMerkleTree::new();
         
 
-        for (account, balance) in &self.balances.balances {
+        for (account, balance) in &self.runtime.balances.balances {
             let state_data = format!("{}:{}", account, balance);
             tree.add_leaf(Hash::from_string(&state_data));
         }
+        for (account, reserved) in &self.runtime.balances.reserved {
+            tree.add_leaf(Hash::from_string(&format!("reserved:{}:{}", account, reserved)));
+        }
+        tree.add_leaf(Hash::from_string(&format!("assets:{}", self.runtime.assets.state_root())));
+        tree.add_leaf(Hash::from_string(&format!("contracts:{}", self.runtime.contracts.state_root())));
+        tree.add_leaf(Hash::from_string(&format!("utxo:{}", self.runtime.utxo.root())));
         
         tree.build();
         tree
@@ -332,6 +1011,17 @@ mod tests {
         assert!(blockchain.is_chain_valid());
     }
 
+    #[test]
+    fn test_issuance_invariant_in_validation() {
+        let mut blockchain = Blockchain::new();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        assert_eq!(blockchain.runtime.balances.total_issuance, 1_001_600);
+        assert!(blockchain.is_chain_valid());
+
+        blockchain.runtime.balances.balances.insert("mallory".to_string(), 1);
+        assert!(!blockchain.is_chain_valid());
+    }
+
     #[test]
     fn test_transaction_history() {
         let mut blockchain = Blockchain::new();
@@ -348,4 +1038,367 @@ mod tests {
         let alice_history = blockchain.get_transaction_history(&"alice".to_string());
         assert!(!alice_history.is_empty());
     }
+
+    #[test]
+    fn test_asset_history_and_state_root() {
+        use crate::assets;
+        use crate::runtime::Call;
+
+        let mut blockchain = Blockchain::new();
+        let root_before = blockchain.get_state_root();
+        let create = Call::Assets(assets::Call::Create {
+            id: 7,
+            name: "US Dollar".to_string(),
+            symbol: "USD".to_string(),
+            decimals: 2,
+        });
+        let mint = Call::Assets(assets::Call::Mint { id: 7, to: "bob".to_string(), amount: 500 });
+        blockchain.add_transaction(Transaction::new_call("alice".to_string(), create, 1)).unwrap();
+        blockchain.add_transaction(Transaction::new_call("alice".to_string(), mint, 2)).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+
+        assert_eq!(blockchain.runtime.assets.total_supply(7), 500);
+        assert_eq!(blockchain.get_asset_transaction_history(7, None).len(), 2);
+        assert_eq!(blockchain.get_asset_transaction_history(7, Some(&"bob".to_string())).len(), 1);
+        assert!(blockchain.get_asset_transaction_history(8, None).is_empty());
+        assert_ne!(root_before, blockchain.get_state_root());
+    }
+
+    #[test]
+    fn test_player_outcome_proof() {
+        let mut blockchain = Blockchain::new();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+
+        let (outcome, proof) = blockchain.get_player_outcome_proof(1, 7).unwrap();
+        assert_eq!(outcome.id, 7);
+        assert!(outcome.wins >= outcome.required_wins);
+        assert!(blockchain.verify_player_outcome(1, &outcome, &proof));
+        assert!(!blockchain.verify_player_outcome(0, &outcome, &proof));
+
+        // Outcomes come from replaying the seal, so importers serve them too.
+        let mut imported = Blockchain::new();
+        imported.import_block(blockchain.chain[1].clone()).unwrap();
+        assert_eq!(imported.get_player_outcome_proof(1, 7), Some((outcome, proof)));
+    }
+
+    #[test]
+    fn test_governance_changes_chain_parameters() {
+        use crate::governance::Call as GovernanceCall;
+        use crate::runtime::Call;
+
+        let mut blockchain = Blockchain::new();
+        let alice = "alice".to_string();
+        for change in [ParameterChange::MiningReward(150), ParameterChange::RpsTotalPlayers(10)] {
+            let call = Call::Governance(GovernanceCall::Propose { change });
+            let nonce = blockchain.next_nonce(&alice);
+            blockchain.add_transaction(Transaction::new_call(alice.clone(), call, nonce)).unwrap();
+        }
+        for proposal_id in 0..2 {
+            let call = Call::Governance(GovernanceCall::Vote { proposal_id, aye: true });
+            let nonce = blockchain.next_nonce(&alice);
+            blockchain.add_transaction(Transaction::new_call(alice.clone(), call, nonce)).unwrap();
+        }
+        assert_eq!(blockchain.next_nonce(&alice), 5);
+
+        for _ in 0..7 {
+            blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        }
+        assert_eq!(blockchain.mining_reward, 150);
+        assert_eq!(blockchain.rps_miner.players.len(), 10);
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn test_vested_transfer_locks_until_height() {
+        use crate::runtime::Call;
+        use crate::vesting::{Call as VestingCall, VestingSchedule};
+
+        let mut blockchain = Blockchain::new();
+        let schedule = VestingSchedule::Linear { locked: 300, per_block: 100, starting_block: 1 };
+        let call = Call::Vesting(VestingCall::VestedTransfer { target: "dave".to_string(), schedule });
+        blockchain.add_transaction(Transaction::new_call("alice".to_string(), call, 1)).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+
+        let dave = "dave".to_string();
+        assert_eq!(blockchain.get_vesting_info(&dave, None).locked, 300);
+        assert_eq!(blockchain.get_vesting_info(&dave, Some(3)).vested, 200);
+        assert!(blockchain.add_transaction(Transaction::new(dave.clone(), "bob".to_string(), 50, 1)).is_err());
+
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        blockchain.add_transaction(Transaction::new(dave, "bob".to_string(), 50, 1)).unwrap();
+    }
+
+    #[test]
+    fn test_genesis_vesting_locks_allocation() {
+        use crate::vesting::VestingSchedule;
+
+        let mut spec = GenesisSpec::default();
+        spec.vesting.insert("alice".to_string(), vec![VestingSchedule::Cliff { locked: 800, unlock_at: 2 }]);
+        let mut blockchain = Blockchain::from_genesis(&spec).unwrap();
+        blockchain.set_dev_accounts(true);
+
+        let alice = "alice".to_string();
+        assert_eq!(blockchain.get_vesting_info(&alice, None).locked, 800);
+        assert!(blockchain.add_transaction(Transaction::new(alice.clone(), "bob".to_string(), 500, 1)).is_err());
+
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        assert_eq!(blockchain.get_vesting_info(&alice, None).vested, 800);
+        blockchain.add_transaction(Transaction::new(alice, "bob".to_string(), 500, 1)).unwrap();
+    }
+
+    #[test]
+    fn test_block_events_and_queries() {
+        let mut blockchain = Blockchain::new();
+        blockchain.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 100, 1)).unwrap();
+        blockchain.add_transaction(Transaction::new("alice".to_string(), "carol".to_string(), 900, 2)).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+
+        let kinds: Vec<&str> = blockchain.events[&1].iter().map(|r| r.event.kind()).collect();
+        assert_eq!(kinds, vec!["Transfer", "NonceIncremented", "TransactionFailed", "RewardPaid", "BlockMined"]);
+        assert_eq!(blockchain.events[&1][0].phase, Phase::ApplyExtrinsic(1));
+
+        let alice = EventFilter { account: Some("alice".to_string()), ..Default::default() };
+        assert_eq!(blockchain.query_events(&alice).len(), 3);
+
+        let rewards = EventFilter { kind: Some("RewardPaid".to_string()), from_block: Some(2), ..Default::default() };
+        let records = blockchain.query_events(&rewards);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].event, Event::RewardPaid { who: "miner".to_string(), amount: 100 });
+    }
+
+    #[test]
+    fn test_failed_mining_rolls_back_block() {
+        let mut blockchain = Blockchain::new();
+        blockchain.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 100, 1)).unwrap();
+        blockchain.add_transaction(Transaction::new("alice".to_string(), "carol".to_string(), 50, 2)).unwrap();
+        blockchain.rps_miner.set_total_players(0);
+
+        assert_eq!(blockchain.mine_pending_transactions("miner".to_string()).unwrap_err(), MiningError::NoPlayers);
+        assert_eq!(blockchain.get_balance(&"alice".to_string()), 1000);
+        assert_eq!(blockchain.runtime.system.get_nonce(&"alice".to_string()), 0);
+        assert_eq!(blockchain.pending_transactions.len(), 2);
+        assert_eq!(blockchain.state().transaction_depth(), 0);
+
+        blockchain.rps_miner.set_total_players(100);
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        assert_eq!(blockchain.get_balance(&"bob".to_string()), 600);
+        assert_eq!(blockchain.get_balance(&"carol".to_string()), 50);
+    }
+
+    #[test]
+    fn test_state_written_through_to_storage() {
+        use crate::storage::FileStorage;
+
+        let path = std::env::temp_dir().join(format!("phlop_chain_state_{}.json", std::process::id()));
+        let mut blockchain = Blockchain::with_storage(FileStorage::open(&path).unwrap());
+        blockchain.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 100, 1)).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        blockchain.flush_state().unwrap();
+
+        let stored = Runtime::load(&FileStorage::open(&path).unwrap()).unwrap().unwrap();
+        assert_eq!(stored.balances.get_balance(&"bob".to_string()), 600);
+        assert_eq!(stored.system.get_nonce(&"alice".to_string()), 1);
+        assert_eq!(stored.system.get_block_number(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_from_genesis_spec() {
+        let mut spec = GenesisSpec {
+            name: "PhlopChain Test".to_string(),
+            chain_id: 42,
+            balances: BTreeMap::from([("carol".to_string(), 700)]),
+            ..Default::default()
+        };
+        spec.rewards.mining_reward = 80;
+        spec.rewards.halving_interval = 2;
+        spec.consensus.rps_total_players = 3;
+
+        let mut blockchain = Blockchain::from_genesis(&spec).unwrap();
+        assert_eq!(blockchain.get_balance(&"carol".to_string()), 700);
+        assert_eq!(blockchain.get_balance(&"alice".to_string()), 0);
+        assert_eq!(blockchain.chain[0].previous_hash, spec.hash());
+        assert_eq!(blockchain.chain[0].hash, Blockchain::from_genesis(&spec).unwrap().chain[0].hash);
+
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        assert_eq!(blockchain.get_balance(&"miner".to_string()), 80 + 40);
+        assert!(blockchain.is_chain_valid());
+
+        let file = GenesisSpec::from_json(include_str!("../genesis/dev.json")).unwrap();
+        assert_eq!(file, GenesisSpec::default());
+    }
+
+    #[test]
+    fn test_rejects_transaction_from_other_chain() {
+        let mut blockchain = Blockchain::new();
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 5, 1);
+        assert_eq!(blockchain.add_transaction(tx.clone().with_chain_id(2)), Err(LedgerError::WrongChain));
+        assert!(blockchain.add_transaction(tx).is_ok());
+    }
+
+    #[test]
+    fn test_fast_sync_from_snapshot() {
+        let mut blockchain = Blockchain::new();
+        blockchain.snapshot_interval = 2;
+        for nonce in 1..=3 {
+            blockchain.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 10, nonce)).unwrap();
+            blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        }
+        assert!(blockchain.get_block_by_index(2).unwrap().snapshot_hash.is_some());
+
+        let bundle = blockchain.export_snapshot().unwrap();
+        assert_eq!(bundle.snapshot.height, 2);
+        let bundle: SyncBundle = serde_json::from_str(&serde_json::to_string(&bundle).unwrap()).unwrap();
+        let mut synced = Blockchain::from_snapshot(bundle).unwrap();
+        synced.set_dev_accounts(true);
+
+        assert_eq!(synced.get_latest_block().hash, blockchain.get_latest_block().hash);
+        assert_eq!(synced.get_chain_length(), 4);
+        assert_eq!(synced.get_balance(&"bob".to_string()), 530);
+        assert_eq!(synced.runtime.system.get_nonce(&"alice".to_string()), 3);
+        assert_eq!(synced.rps_miner.config.blocks_mined, blockchain.rps_miner.config.blocks_mined);
+
+        synced.snapshot_interval = 2;
+        synced.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 10, 4)).unwrap();
+        let block = synced.mine_pending_transactions("miner".to_string()).unwrap();
+        assert_eq!(block.index, 4);
+        assert!(block.snapshot_hash.is_some());
+    }
+
+    #[test]
+    fn test_sync_rejects_tampered_snapshot() {
+        let mut blockchain = Blockchain::new();
+        blockchain.snapshot_interval = 1;
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+
+        let mut bundle = blockchain.export_snapshot().unwrap();
+        bundle.snapshot.runtime.balances.set_balance(&"mallory".to_string(), 1_000_000);
+        assert_eq!(Blockchain::from_snapshot(bundle).unwrap_err(), SyncError::SnapshotMismatch { height: 1 });
+
+        let mut bundle = blockchain.export_snapshot().unwrap();
+        bundle.blocks.clear();
+        assert_eq!(Blockchain::from_snapshot(bundle).unwrap_err(), SyncError::MissingSnapshotBlock { height: 1 });
+
+        let mut bundle = blockchain.export_snapshot().unwrap();
+        bundle.blocks[0].transactions[0] = Transaction::new("network".to_string(), "mallory".to_string(), 100, 0);
+        assert_eq!(Blockchain::from_snapshot(bundle).unwrap_err(), SyncError::InvalidBlock { index: 1 });
+    }
+
+    #[test]
+    fn test_utxo_ledger_mode() {
+        use crate::keys::KeyPair;
+        use crate::utxo::OutPoint;
+
+        let alice = KeyPair::from_seed([1; 32]);
+        let miner = KeyPair::from_seed([2; 32]);
+        let bob = KeyPair::from_seed([3; 32]).address();
+        let spec = GenesisSpec {
+            ledger: LedgerMode::Utxo,
+            balances: BTreeMap::from([(alice.address().to_hex(), 1000)]),
+            ..Default::default()
+        };
+        let mut blockchain = Blockchain::from_genesis(&spec).unwrap();
+        blockchain.snapshot_interval = 2;
+        assert_eq!(blockchain.runtime.utxo.total_value(), 1000);
+        let account_tx = Transaction::new("alice".to_string(), "bob".to_string(), 5, 1);
+        assert_eq!(blockchain.add_transaction(account_tx), Err(LedgerError::WrongLedgerMode));
+        assert!(matches!(
+            blockchain.mine_pending_transactions("miner".to_string()),
+            Err(MiningError::InvalidPayoutAddress(_))
+        ));
+
+        let genesis_output = OutPoint { tx_hash: blockchain.chain[0].utxo.as_ref().unwrap().transactions[0].hash.clone(), index: 0 };
+        let mut spend = UtxoTransaction::new(spec.chain_id, vec![genesis_output], vec![
+            TxOutput { value: 300, script: Script::PayToPubkeyHash(bob.clone()) },
+            TxOutput { value: 690, script: Script::PayToPubkeyHash(alice.address()) },
+        ]);
+        assert_eq!(blockchain.add_utxo_transaction(spend.clone()), Err(LedgerError::ScriptFailed));
+        spend.sign_input(0, &alice);
+        blockchain.add_utxo_transaction(spend.clone()).unwrap();
+        assert_eq!(blockchain.add_utxo_transaction(spend), Err(LedgerError::DoubleSpend));
+
+        for _ in 0..3 {
+            blockchain.mine_pending_transactions(miner.address().to_hex()).unwrap();
+        }
+        let value_of = |chain: &Blockchain, address: &Hash| -> u128 {
+            chain.runtime.utxo.unspent_for(address).iter().map(|(_, utxo)| utxo.output.value).sum()
+        };
+        assert_eq!(value_of(&blockchain, &bob), 300);
+        assert_eq!(value_of(&blockchain, &miner.address()), 3 * 100 + 10);
+        assert_eq!(blockchain.runtime.utxo.total_value(), 1000 + 3 * 100);
+
+        let bundle = blockchain.export_snapshot().unwrap();
+        let synced = Blockchain::from_snapshot(bundle).unwrap();
+        assert_eq!(synced.get_latest_block().hash, blockchain.get_latest_block().hash);
+        assert_eq!(synced.runtime.utxo.root(), blockchain.runtime.utxo.root());
+    }
+
+    #[test]
+    fn test_reorganize_to_longer_fork() {
+        let spec = GenesisSpec::default();
+        let mut ours = Blockchain::new();
+        let mut theirs = Blockchain::new();
+        ours.mine_pending_transactions("miner".to_string()).unwrap();
+        theirs.import_block(ours.chain[1].clone()).unwrap();
+
+        ours.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 10, 1)).unwrap();
+        ours.mine_pending_transactions("ours".to_string()).unwrap();
+        theirs.mine_pending_transactions("theirs".to_string()).unwrap();
+        assert!(!ours.reorganize(&spec, theirs.chain[2..].to_vec()).unwrap());
+
+        theirs.mine_pending_transactions("theirs".to_string()).unwrap();
+        assert!(ours.reorganize(&spec, theirs.chain[2..].to_vec()).unwrap());
+        assert_eq!(ours.get_latest_block().hash, theirs.get_latest_block().hash);
+        assert_eq!(ours.get_balance(&"ours".to_string()), 0);
+        assert_eq!(ours.pending_transactions.len(), 1);
+        assert!(ours.reorganize(&spec, vec![Blockchain::new().chain[0].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_reorganize_keeps_storage_and_clock() {
+        use crate::storage::FileStorage;
+
+        let spec = GenesisSpec::default();
+        let path = std::env::temp_dir().join(format!("phlop_reorg_state_{}.json", std::process::id()));
+        let mut ours = Blockchain::with_storage(FileStorage::open(&path).unwrap());
+        ours.set_clock(1_000);
+        let mut theirs = Blockchain::new();
+        ours.mine_pending_transactions("miner".to_string()).unwrap();
+        theirs.import_block(ours.chain[1].clone()).unwrap();
+        ours.mine_pending_transactions("ours".to_string()).unwrap();
+        theirs.mine_pending_transactions("theirs".to_string()).unwrap();
+        theirs.mine_pending_transactions("theirs".to_string()).unwrap();
+
+        assert!(ours.reorganize(&spec, theirs.chain[2..].to_vec()).unwrap());
+        assert!(ours.get_player_outcome_proof(1, 0).is_some());
+        assert_eq!(ours.mine_pending_transactions("ours".to_string()).unwrap().timestamp, 1_000);
+        ours.flush_state().unwrap();
+
+        let stored = Runtime::load(&FileStorage::open(&path).unwrap()).unwrap().unwrap();
+        assert_eq!(stored.balances.get_balance(&"theirs".to_string()), 200);
+        assert_eq!(stored.balances.get_balance(&"ours".to_string()), 100);
+        assert_eq!(stored.system.get_block_number(), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_transaction_status() {
+        let mut blockchain = Blockchain::new();
+        let sent = Transaction::new("alice".to_string(), "bob".to_string(), 100, 1);
+        let overspent = Transaction::new("alice".to_string(), "carol".to_string(), 900, 2);
+        blockchain.add_transaction(sent.clone()).unwrap();
+        blockchain.add_transaction(overspent.clone()).unwrap();
+        assert_eq!(blockchain.transaction_status(&overspent.hash), Some(TransactionStatus::Pending { position: 1 }));
+
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        assert_eq!(blockchain.transaction_status(&sent.hash), Some(TransactionStatus::Included { block_index: 1, tx_index: 1 }));
+        let Some(TransactionStatus::Rejected { block_index, code, .. }) = blockchain.transaction_status(&overspent.hash) else {
+            panic!("overspend should be rejected");
+        };
+        assert_eq!((block_index, code), (Some(1), LedgerError::InsufficientBalance.code()));
+        assert_eq!(blockchain.transaction_status(&Transaction::new("alice".to_string(), "bob".to_string(), 1, 9).hash), None);
+    }
 }
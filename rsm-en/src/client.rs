@@ -0,0 +1,59 @@
+//! Minimal HTTP client the command-line tools use to talk to a node: one
+//! request per connection, JSON bodies, no TLS.
+
+use std::io::prelude::*;
+use std::net::TcpStream;
+
+pub const DEFAULT_NODE: &str = "127.0.0.1:3030";
+
+/// Sends one request to `node` (`host:port`) and returns the response body.
+/// Anything but a 200 is an error carrying the body the node sent.
+pub fn http_request(node: &str, method: &str, path: &str, body: &str) -> Result<String, String> {
+    let mut stream = TcpStream::connect(node).map_err(|e| format!("Cannot reach node at {}: {}", node, e))?;
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method, path, node, body.len(), body
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    if !head.starts_with("HTTP/1.1 200") {
+        return Err(format!("Node rejected request: {}", body));
+    }
+    Ok(body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Answers one request with `response` and hands back what was sent.
+    fn serve_once(response: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let read = stream.read(&mut buffer).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&buffer[..read]).into_owned()
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn test_http_request() {
+        let (node, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+        assert_eq!(http_request(&node, "POST", "/rpc", "[]").unwrap(), "{}");
+        let sent = server.join().unwrap();
+        assert!(sent.starts_with("POST /rpc HTTP/1.1\r\n"));
+        assert!(sent.ends_with("Content-Length: 2\r\nConnection: close\r\n\r\n[]"));
+
+        let (node, server) = serve_once("HTTP/1.1 404 Not Found\r\n\r\nmissing");
+        assert_eq!(http_request(&node, "GET", "/nowhere", ""), Err("Node rejected request: missing".to_string()));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use num_traits::{CheckedMul, NumCast, Zero};
+use serde::{Deserialize, Serialize};
+use crate::balances::{self, Pallet as BalancesPallet};
+use crate::merkle::{FastMerkleTree, Hash};
+use crate::runtime::DefaultConfig;
+use crate::vm::{self, Context, Execution, Word};
+use crate::error::LedgerError;
+
+/// Chains running contracts must say how a contract's address is derived
+/// and how an account is written as a VM word.
+pub trait Config: balances::Config {
+    fn contract_account(hash: &Hash) -> Self::AccountId;
+    fn account_word(who: &Self::AccountId) -> Word;
+}
+
+/// A contract argument. Accounts are passed to the VM as their word.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Arg<T: Config = DefaultConfig> {
+    Int(Word),
+    Account(T::AccountId),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Call<T: Config = DefaultConfig> {
+    /// Stores `code` under a new contract account.
+    Deploy { code: Vec<u8> },
+    /// Runs a contract, sending it `value`. `gas_limit` gas is charged up
+    /// front at the fee rate and what is left over is refunded.
+    Call { contract: T::AccountId, args: Vec<Arg<T>>, value: T::Balance, gas_limit: u64 },
+}
+
+impl<T: Config> Call<T> {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Deploy { code } => 50 + code.len() as u64 / 32,
+            Call::Call { .. } => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ContractInfo<T: Config = DefaultConfig> {
+    pub deployer: T::AccountId,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<Word, Word>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Event<T: Config = DefaultConfig> {
+    Deployed { contract: T::AccountId, deployer: T::AccountId },
+    Executed { contract: T::AccountId, caller: T::AccountId, gas_used: u64, output: Option<Word> },
+    /// The call was undone; only its gas was kept.
+    Reverted { contract: T::AccountId, caller: T::AccountId, gas_used: u64, reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Pallet<T: Config = DefaultConfig> {
+    pub contracts: BTreeMap<T::AccountId, ContractInfo<T>>,
+    /// Every account that has called a contract, been passed to one or is
+    /// one, by word. Contracts can only pay accounts listed here.
+    pub accounts: BTreeMap<Word, T::AccountId>,
+    /// Contracts deployed so far; salts the next address.
+    pub deployed: u64,
+    pub max_code_size: usize,
+    pub max_gas: u64,
+    #[serde(skip)]
+    events: Vec<Event<T>>,
+}
+
+// Calls checkpoint the balances so a revert can restore them.
+impl<T: Config> Pallet<T> where BalancesPallet<T>: Clone {
+
+    pub fn new(max_code_size: usize, max_gas: u64) -> Self {
+
+        Self {
+            contracts: BTreeMap::new(),
+            accounts: BTreeMap::new(),
+            deployed: 0,
+            max_code_size,
+            max_gas,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn deploy(&mut self, deployer: &T::AccountId, code: Vec<u8>) -> Result<T::AccountId, LedgerError> {
+
+        if code.len() > self.max_code_size {
+            return Err(LedgerError::CodeTooLarge);
+        }
+        if code.is_empty() {
+            return Err(LedgerError::InvalidBytecode("code is empty".to_string()));
+        }
+        vm::validate(&code).map_err(|trap| LedgerError::InvalidBytecode(trap.to_string()))?;
+
+        let contract = T::contract_account(&Hash::from_string(&format!("{:?}:{}", deployer, self.deployed)));
+        self.deployed += 1;
+        self.register(&contract);
+        self.contracts.insert(contract.clone(), ContractInfo {
+            deployer: deployer.clone(),
+            code,
+            storage: BTreeMap::new(),
+        });
+        self.events.push(Event::Deployed { contract: contract.clone(), deployer: deployer.clone() });
+        Ok(contract)
+    }
+
+    /// Runs a call without applying it, e.g. to read a contract's state.
+    pub fn query(&self, caller: &T::AccountId, contract: &T::AccountId, args: &[Arg<T>], value: T::Balance, gas_limit: u64, balances: &BalancesPallet<T>) -> Result<Execution, LedgerError> {
+
+        let info = self.contracts.get(contract).ok_or(LedgerError::UnknownContract)?;
+        let args: Vec<Word> = args
+            .iter()
+            .map(|arg| match arg {
+                Arg::Int(value) => *value,
+                Arg::Account(who) => T::account_word(who),
+            })
+            .collect();
+        let ctx = Context {
+            caller: T::account_word(caller),
+            value: to_word(value)?,
+            balance: to_word(balances.get_balance(contract))?,
+            args: &args,
+            storage: &info.storage,
+            gas_limit,
+        };
+        Ok(vm::execute(&info.code, &ctx))
+    }
+
+    /// Executes a call. A trap or a failed payout reverts the call but not
+    /// the transaction: the caller still pays for the gas used. Returns
+    /// that gas fee.
+    #[allow(clippy::too_many_arguments)]
+    pub fn call(&mut self, caller: &T::AccountId, contract: &T::AccountId, args: &[Arg<T>], value: T::Balance, gas_limit: u64, gas_price: T::Balance, balances: &mut BalancesPallet<T>) -> Result<T::Balance, LedgerError> {
+
+        if gas_limit == 0 || gas_limit > self.max_gas {
+            return Err(LedgerError::InvalidGasLimit);
+        }
+        if !self.contracts.contains_key(contract) {
+            return Err(LedgerError::UnknownContract);
+        }
+        let max_fee = gas_price.checked_mul(&gas_cost(gas_limit)?).ok_or(LedgerError::Arithmetic)?;
+        balances.withdraw_fee(caller, max_fee)?;
+
+        // Only the caller and the contract are touched before the call runs.
+        let checkpoint = balances.checkpoint([caller, contract]);
+        if !value.is_zero() {
+            if let Err(e) = balances.transfer(caller.clone(), contract.clone(), value) {
+                balances.restore(checkpoint);
+                balances.deposit(caller, max_fee);
+                return Err(e);
+            }
+        }
+        let accounts = args.iter().filter_map(|arg| match arg {
+            Arg::Account(who) => Some(who),
+            Arg::Int(_) => None,
+        });
+        let registered: Vec<Word> = std::iter::once(caller).chain(accounts).filter_map(|who| self.register(who)).collect();
+
+        let execution = match self.query(caller, contract, args, value, gas_limit, balances) {
+            Ok(execution) => execution,
+            Err(e) => {
+                self.unregister(&registered);
+                balances.restore(checkpoint);
+                balances.deposit(caller, max_fee);
+                return Err(e);
+            }
+        };
+        let gas_used = execution.gas_used;
+        // Payouts also touch their recipients, known once the call has run.
+        let recipients: Vec<T::AccountId> = execution.transfers.iter().filter_map(|(to, _)| self.accounts.get(to).cloned()).collect();
+        let payouts = balances.checkpoint(recipients.iter().chain([caller, contract]));
+        match self.apply(contract, execution, balances) {
+            Ok(output) => {
+                self.events.push(Event::Executed { contract: contract.clone(), caller: caller.clone(), gas_used, output });
+            }
+            Err(reason) => {
+                self.unregister(&registered);
+                balances.restore(payouts);
+                balances.restore(checkpoint);
+                self.events.push(Event::Reverted { contract: contract.clone(), caller: caller.clone(), gas_used, reason });
+            }
+        }
+
+        let fee = gas_price.checked_mul(&gas_cost(gas_used)?).ok_or(LedgerError::Arithmetic)?;
+        balances.deposit(caller, max_fee - fee);
+        Ok(fee)
+    }
+
+    /// Pays out and commits a finished execution, or says why it reverts.
+    fn apply(&mut self, contract: &T::AccountId, execution: Execution, balances: &mut BalancesPallet<T>) -> Result<Option<Word>, String> {
+
+        let output = execution.result.map_err(|trap| trap.to_string())?;
+        for (to, amount) in execution.transfers {
+            let to = self.accounts.get(&to).ok_or_else(|| format!("Unknown account word {:#x}", to))?;
+            let amount: T::Balance = NumCast::from(amount).ok_or_else(|| LedgerError::Arithmetic.to_string())?;
+            balances.transfer(contract.clone(), to.clone(), amount).map_err(|e| e.to_string())?;
+        }
+        let storage = &mut self.contracts.get_mut(contract).expect("contract exists").storage;
+        for (key, value) in execution.storage_writes {
+            if value == 0 {
+                storage.remove(&key);
+            } else {
+                storage.insert(key, value);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Lists `who` for payouts. Returns its word if it was not listed yet.
+    fn register(&mut self, who: &T::AccountId) -> Option<Word> {
+
+        let word = T::account_word(who);
+        self.accounts.insert(word, who.clone()).is_none().then_some(word)
+    }
+
+    fn unregister(&mut self, words: &[Word]) {
+
+        for word in words {
+            self.accounts.remove(word);
+        }
+    }
+
+    pub fn storage_at(&self, contract: &T::AccountId, key: Word) -> Word {
+
+        self.contracts
+            .get(contract)
+            .and_then(|info| info.storage.get(&key))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event<T>> {
+
+        std::mem::take(&mut self.events)
+    }
+
+    /// Commits to every contract's code and storage.
+    pub fn state_root(&self) -> Hash {
+
+        let mut tree = FastMerkleTree::new();
+        for (contract, info) in &self.contracts {
+            tree.add_leaf(Hash::from_string(&format!(
+                "contract:{:?}:{:?}:{}",
+                contract, info.deployer, hex::encode(&info.code)
+            )));
+            for (key, value) in &info.storage {
+                tree.add_leaf(Hash::from_string(&format!("storage:{:?}:{}:{}", contract, key, value)));
+            }
+        }
+        tree.build();
+        tree.get_root().cloned().unwrap_or_else(|| Hash::from_string(""))
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call<T>, gas_price: T::Balance, balances: &mut BalancesPallet<T>) -> Result<T::Balance, LedgerError> {
+
+        match call {
+            Call::Deploy { code } => self.deploy(caller, code.clone()).map(|_| T::Balance::zero()),
+            Call::Call { contract, args, value, gas_limit } => {
+                self.call(caller, contract, args, *value, *gas_limit, gas_price, balances)
+            }
+        }
+    }
+}
+
+fn to_word<B: NumCast>(value: B) -> Result<Word, LedgerError> {
+    NumCast::from(value).ok_or(LedgerError::Arithmetic)
+}
+
+fn gas_cost<B: NumCast>(gas: u64) -> Result<B, LedgerError> {
+    NumCast::from(gas).ok_or(LedgerError::Arithmetic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{assemble, examples};
+
+    fn setup() -> (Pallet, BalancesPallet) {
+        let mut balances = BalancesPallet::new();
+        balances.set_balance(&"alice".to_string(), 10_000);
+        balances.set_balance(&"bob".to_string(), 1_000);
+        (Pallet::new(4096, 10_000), balances)
+    }
+
+    fn call(contracts: &mut Pallet, balances: &mut BalancesPallet, caller: &str, contract: &String, args: Vec<Arg>, value: u128) -> u128 {
+        contracts.call(&caller.to_string(), contract, &args, value, 1_000, 1, balances).unwrap()
+    }
+
+    #[test]
+    fn test_token_contract() {
+        let (mut contracts, mut balances) = setup();
+        let alice = "alice".to_string();
+        let token = contracts.deploy(&alice, assemble(examples::TOKEN).unwrap()).unwrap();
+
+        let gas_fee = call(&mut contracts, &mut balances, "alice", &token, vec![Arg::Int(0), Arg::Int(500)], 0);
+        assert!(gas_fee > 0);
+        assert_eq!(balances.get_balance(&alice), 10_000 - gas_fee);
+        call(&mut contracts, &mut balances, "alice", &token, vec![Arg::Int(1), Arg::Account("bob".to_string()), Arg::Int(120)], 0);
+
+        let balance_of = |who: &str| {
+            let args = [Arg::Int(2), Arg::Account(who.to_string())];
+            contracts.query(&alice, &token, &args, 0, 1_000, &balances).unwrap().result
+        };
+        assert_eq!(balance_of("alice"), Ok(Some(380)));
+        assert_eq!(balance_of("bob"), Ok(Some(120)));
+
+        // Overdrawing traps, so the transfer reverts but its gas is kept.
+        let before = balances.get_balance(&"bob".to_string());
+        let fee = call(&mut contracts, &mut balances, "bob", &token, vec![Arg::Int(1), Arg::Account(alice.clone()), Arg::Int(121)], 0);
+        assert_eq!(balances.get_balance(&"bob".to_string()), before - fee);
+        assert!(matches!(contracts.take_events().last(), Some(Event::Reverted { .. })));
+        assert_eq!(contracts.storage_at(&token, DefaultConfig::account_word(&"bob".to_string())), 120);
+
+        // A reverted call does not leave its accounts listed for payouts.
+        let dave = "dave".to_string();
+        call(&mut contracts, &mut balances, "alice", &token, vec![Arg::Int(1), Arg::Account(dave.clone()), Arg::Int(381)], 0);
+        assert!(!contracts.accounts.contains_key(&DefaultConfig::account_word(&dave)));
+    }
+
+    #[test]
+    fn test_escrow_contract() {
+        let (mut contracts, mut balances) = setup();
+        let escrow = contracts.deploy(&"alice".to_string(), assemble(examples::ESCROW).unwrap()).unwrap();
+        let fund = vec![Arg::Int(0), Arg::Account("carol".to_string()), Arg::Account("bob".to_string())];
+
+        let fee = call(&mut contracts, &mut balances, "alice", &escrow, fund, 2_000);
+        assert_eq!(balances.get_balance(&escrow), 2_000);
+        assert_eq!(balances.get_balance(&"alice".to_string()), 8_000 - fee);
+
+        // Only the arbiter can release; a failed release keeps the funds.
+        call(&mut contracts, &mut balances, "alice", &escrow, vec![Arg::Int(1)], 0);
+        assert_eq!(balances.get_balance(&escrow), 2_000);
+
+        call(&mut contracts, &mut balances, "bob", &escrow, vec![Arg::Int(1)], 0);
+        assert_eq!(balances.get_balance(&escrow), 0);
+        assert_eq!(balances.get_balance(&"carol".to_string()), 2_000);
+        assert!(balances.check_total_issuance().is_ok());
+    }
+
+    #[test]
+    fn test_deploy_and_call_checks() {
+        let (mut contracts, mut balances) = setup();
+        let alice = "alice".to_string();
+        assert_eq!(contracts.deploy(&alice, vec![0xff]), Err(LedgerError::InvalidBytecode("Invalid opcode 0xff".to_string())));
+        assert_eq!(contracts.deploy(&alice, vec![0; 5000]), Err(LedgerError::CodeTooLarge));
+
+        let counter = contracts.deploy(&alice, assemble(examples::COUNTER).unwrap()).unwrap();
+        assert_eq!(contracts.call(&alice, &counter, &[], 0, 0, 1, &mut balances), Err(LedgerError::InvalidGasLimit));
+        assert_eq!(contracts.call(&alice, &alice, &[], 0, 100, 1, &mut balances), Err(LedgerError::UnknownContract));
+        assert_eq!(contracts.call(&"nobody".to_string(), &counter, &[], 0, 100, 1, &mut balances), Err(LedgerError::InsufficientBalance));
+    }
+}
@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Implemented by every module's error type so the HTTP servers can report
+/// failures uniformly. Codes are stable and never reused. They are grouped
+/// by module: 1xxx ledger, 2xxx mining, 3xxx order book, 4xxx backtest,
+/// 5xxx live trading, 6xxx storage, 7xxx sync, 8xxx genesis, 9xxx request
+/// handling, 10xxx the contract VM, 11xxx peer-to-peer networking.
+pub trait ErrorCode: fmt::Display {
+    fn code(&self) -> u16;
+    fn module(&self) -> &'static str;
+    fn http_status(&self) -> u16;
+
+    fn to_body(&self) -> ErrorBody {
+        ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                module: self.module().to_string(),
+                message: self.to_string(),
+            },
+        }
+    }
+}
+
+/// JSON error body: `{"error": {"code": 1001, "module": "ledger", "message": "..."}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    pub code: u16,
+    pub module: String,
+    pub message: String,
+}
+
+/// Status line reason phrases for the codes the servers use.
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "BAD REQUEST",
+        403 => "FORBIDDEN",
+        404 => "NOT FOUND",
+        409 => "CONFLICT",
+        422 => "UNPROCESSABLE ENTITY",
+        503 => "SERVICE UNAVAILABLE",
+        _ => "INTERNAL SERVER ERROR",
+    }
+}
+
+/// Failures in the ledger: pallets, the runtime and the transaction pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LedgerError {
+    InsufficientBalance,
+    BalanceLocked,
+    ExistentialDeposit,
+    Arithmetic,
+    IssuanceMismatch(String),
+    InvalidTransaction,
+    MissingSigner,
+    ReservedSigner,
+    InvalidNonce,
+    WrongChain,
+    UnsignedTransaction,
+
+    BondZero,
+    NotBonded,
+    InsufficientBonded,
+    NoNominationTargets,
+    NotValidator,
+
+    AssetExists,
+    EmptyAssetSymbol,
+    UnknownAsset,
+    NotIssuer,
+    InsufficientAssetBalance,
+    AllowanceExceeded,
+
+    UnknownParameter(String),
+    InvalidParameter(String),
+    UnknownProposal,
+    VotingClosed,
+    NoVotingBalance,
+
+    MultisigAlreadyOpen,
+    UnknownMultisig,
+    AlreadyApproved,
+    NotDepositor,
+    CallerInSignatories,
+    DuplicateSignatories,
+    InvalidSignatoryCount,
+    InvalidThreshold,
+
+    ZeroVestingRate,
+    NothingToVest,
+    TooManySchedules,
+    VestedTransferTooSmall,
+
+    CodeTooLarge,
+    InvalidBytecode(String),
+    UnknownContract,
+    InvalidGasLimit,
+
+    InvalidScript(String),
+    UnknownOutput,
+    DoubleSpend,
+    ScriptFailed,
+    OutputsExceedInputs,
+    InvalidCoinbase,
+    WrongLedgerMode,
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::InsufficientBalance => write!(f, "Insufficient balance"),
+            LedgerError::BalanceLocked => write!(f, "Balance is locked"),
+            LedgerError::ExistentialDeposit => write!(f, "Existential deposit not met"),
+            LedgerError::Arithmetic => write!(f, "Arithmetic overflow or underflow"),
+            LedgerError::IssuanceMismatch(detail) => write!(f, "Issuance invariant violated: {}", detail),
+            LedgerError::InvalidTransaction => write!(f, "Invalid transaction"),
+            LedgerError::MissingSigner => write!(f, "Missing signer"),
+            LedgerError::ReservedSigner => write!(f, "Reserved account cannot sign"),
+            LedgerError::InvalidNonce => write!(f, "Invalid nonce"),
+            LedgerError::WrongChain => write!(f, "Transaction is for another chain"),
+            LedgerError::UnsignedTransaction => write!(f, "Unsigned transactions are only accepted with dev accounts enabled"),
+            LedgerError::BondZero => write!(f, "Cannot bond zero"),
+            LedgerError::NotBonded => write!(f, "Not bonded"),
+            LedgerError::InsufficientBonded => write!(f, "Insufficient bonded balance"),
+            LedgerError::NoNominationTargets => write!(f, "No nomination targets"),
+            LedgerError::NotValidator => write!(f, "Nomination target is not a validator"),
+            LedgerError::AssetExists => write!(f, "Asset already exists"),
+            LedgerError::EmptyAssetSymbol => write!(f, "Asset symbol is empty"),
+            LedgerError::UnknownAsset => write!(f, "Unknown asset"),
+            LedgerError::NotIssuer => write!(f, "Only the issuer can mint or burn"),
+            LedgerError::InsufficientAssetBalance => write!(f, "Insufficient asset balance"),
+            LedgerError::AllowanceExceeded => write!(f, "Allowance exceeded"),
+            LedgerError::UnknownParameter(name) => write!(f, "Unknown parameter '{}'", name),
+            LedgerError::InvalidParameter(reason) => write!(f, "Invalid parameter: {}", reason),
+            LedgerError::UnknownProposal => write!(f, "Unknown proposal"),
+            LedgerError::VotingClosed => write!(f, "Voting has closed"),
+            LedgerError::NoVotingBalance => write!(f, "No balance to vote with"),
+            LedgerError::MultisigAlreadyOpen => write!(f, "Multisig operation already open"),
+            LedgerError::UnknownMultisig => write!(f, "No such multisig operation"),
+            LedgerError::AlreadyApproved => write!(f, "Already approved"),
+            LedgerError::NotDepositor => write!(f, "Only the depositor can cancel"),
+            LedgerError::CallerInSignatories => write!(f, "Caller listed as other signatory"),
+            LedgerError::DuplicateSignatories => write!(f, "Duplicate signatories"),
+            LedgerError::InvalidSignatoryCount => write!(f, "Invalid number of signatories"),
+            LedgerError::InvalidThreshold => write!(f, "Invalid threshold"),
+            LedgerError::ZeroVestingRate => write!(f, "Vesting rate is zero"),
+            LedgerError::NothingToVest => write!(f, "Nothing to vest"),
+            LedgerError::TooManySchedules => write!(f, "Too many vesting schedules"),
+            LedgerError::VestedTransferTooSmall => write!(f, "Vested transfer below minimum"),
+            LedgerError::CodeTooLarge => write!(f, "Contract code too large"),
+            LedgerError::InvalidBytecode(reason) => write!(f, "Invalid bytecode: {}", reason),
+            LedgerError::UnknownContract => write!(f, "Unknown contract"),
+            LedgerError::InvalidGasLimit => write!(f, "Gas limit is zero or above the maximum"),
+            LedgerError::InvalidScript(reason) => write!(f, "Invalid script: {}", reason),
+            LedgerError::UnknownOutput => write!(f, "Unknown or spent output"),
+            LedgerError::DoubleSpend => write!(f, "Output already spent"),
+            LedgerError::ScriptFailed => write!(f, "Witness does not satisfy the locking script"),
+            LedgerError::OutputsExceedInputs => write!(f, "Outputs exceed inputs"),
+            LedgerError::InvalidCoinbase => write!(f, "Invalid coinbase"),
+            LedgerError::WrongLedgerMode => write!(f, "Transaction kind not used by this chain's ledger mode"),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl ErrorCode for LedgerError {
+    fn code(&self) -> u16 {
+        match self {
+            LedgerError::InsufficientBalance => 1001,
+            LedgerError::BalanceLocked => 1002,
+            LedgerError::ExistentialDeposit => 1003,
+            LedgerError::Arithmetic => 1004,
+            LedgerError::IssuanceMismatch(_) => 1005,
+            LedgerError::InvalidTransaction => 1010,
+            LedgerError::MissingSigner => 1011,
+            LedgerError::ReservedSigner => 1012,
+            LedgerError::InvalidNonce => 1013,
+            LedgerError::WrongChain => 1014,
+            LedgerError::UnsignedTransaction => 1015,
+            LedgerError::BondZero => 1101,
+            LedgerError::NotBonded => 1102,
+            LedgerError::InsufficientBonded => 1103,
+            LedgerError::NoNominationTargets => 1104,
+            LedgerError::NotValidator => 1105,
+            LedgerError::AssetExists => 1201,
+            LedgerError::EmptyAssetSymbol => 1202,
+            LedgerError::UnknownAsset => 1203,
+            LedgerError::NotIssuer => 1204,
+            LedgerError::InsufficientAssetBalance => 1205,
+            LedgerError::AllowanceExceeded => 1206,
+            LedgerError::UnknownParameter(_) => 1301,
+            LedgerError::InvalidParameter(_) => 1302,
+            LedgerError::UnknownProposal => 1303,
+            LedgerError::VotingClosed => 1304,
+            LedgerError::NoVotingBalance => 1305,
+            LedgerError::MultisigAlreadyOpen => 1401,
+            LedgerError::UnknownMultisig => 1402,
+            LedgerError::AlreadyApproved => 1403,
+            LedgerError::NotDepositor => 1404,
+            LedgerError::CallerInSignatories => 1405,
+            LedgerError::DuplicateSignatories => 1406,
+            LedgerError::InvalidSignatoryCount => 1407,
+            LedgerError::InvalidThreshold => 1408,
+            LedgerError::ZeroVestingRate => 1501,
+            LedgerError::NothingToVest => 1502,
+            LedgerError::TooManySchedules => 1503,
+            LedgerError::VestedTransferTooSmall => 1504,
+            LedgerError::CodeTooLarge => 1601,
+            LedgerError::InvalidBytecode(_) => 1602,
+            LedgerError::UnknownContract => 1603,
+            LedgerError::InvalidGasLimit => 1604,
+            LedgerError::InvalidScript(_) => 1701,
+            LedgerError::UnknownOutput => 1702,
+            LedgerError::DoubleSpend => 1703,
+            LedgerError::ScriptFailed => 1704,
+            LedgerError::OutputsExceedInputs => 1705,
+            LedgerError::InvalidCoinbase => 1706,
+            LedgerError::WrongLedgerMode => 1707,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "ledger"
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            LedgerError::IssuanceMismatch(_) => 500,
+            LedgerError::ReservedSigner
+            | LedgerError::UnsignedTransaction
+            | LedgerError::NotIssuer
+            | LedgerError::NotDepositor
+            | LedgerError::ScriptFailed => 403,
+            LedgerError::UnknownAsset
+            | LedgerError::UnknownProposal
+            | LedgerError::UnknownMultisig
+            | LedgerError::UnknownContract
+            | LedgerError::UnknownOutput => 404,
+            LedgerError::InvalidNonce
+            | LedgerError::AssetExists
+            | LedgerError::VotingClosed
+            | LedgerError::MultisigAlreadyOpen
+            | LedgerError::AlreadyApproved
+            | LedgerError::DoubleSpend
+            | LedgerError::WrongLedgerMode => 409,
+            LedgerError::InsufficientBalance
+            | LedgerError::BalanceLocked
+            | LedgerError::ExistentialDeposit
+            | LedgerError::Arithmetic
+            | LedgerError::NotBonded
+            | LedgerError::InsufficientBonded
+            | LedgerError::NotValidator
+            | LedgerError::InsufficientAssetBalance
+            | LedgerError::AllowanceExceeded
+            | LedgerError::NoVotingBalance
+            | LedgerError::TooManySchedules
+            | LedgerError::OutputsExceedInputs => 422,
+            _ => 400,
+        }
+    }
+}
+
+/// Malformed HTTP input, before any module is involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    InvalidBody(String),
+    InvalidQuery(String),
+    NotFound(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::InvalidBody(detail) => write!(f, "Invalid request body: {}", detail),
+            RequestError::InvalidQuery(detail) => write!(f, "Invalid query: {}", detail),
+            RequestError::NotFound(what) => write!(f, "{} not found", what),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+impl ErrorCode for RequestError {
+    fn code(&self) -> u16 {
+        match self {
+            RequestError::InvalidBody(_) => 9001,
+            RequestError::InvalidQuery(_) => 9002,
+            RequestError::NotFound(_) => 9003,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "request"
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            RequestError::NotFound(_) => 404,
+            _ => 400,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_body_json() {
+        let body = serde_json::to_value(LedgerError::InvalidNonce.to_body()).unwrap();
+        assert_eq!(body["error"]["code"], 1013);
+        assert_eq!(body["error"]["module"], "ledger");
+        assert_eq!(body["error"]["message"], "Invalid nonce");
+        assert_eq!(LedgerError::InvalidNonce.http_status(), 409);
+    }
+
+    #[test]
+    fn test_request_errors() {
+        let missing = RequestError::NotFound("Session".to_string());
+        assert_eq!(missing.to_string(), "Session not found");
+        assert_eq!((missing.code(), missing.http_status()), (9003, 404));
+        assert_eq!(reason_phrase(422), "UNPROCESSABLE ENTITY");
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+/// Everything the chain reports about a block, in the order it happened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Event {
+    Transfer { from: String, to: String, amount: u128 },
+    NonceIncremented { who: String, nonce: u32 },
+    BlockMined { number: u32, author: String, hash: String, rounds: u32, total_games: u64 },
+    TransactionFailed { tx_hash: String, who: String, code: u16, reason: String },
+    RewardPaid { who: String, amount: u128 },
+    ContractDeployed { contract: String, deployer: String },
+    ContractExecuted { contract: String, caller: String, gas_used: u64, output: Option<u128> },
+    ContractReverted { contract: String, caller: String, gas_used: u64, reason: String },
+}
+
+impl Event {
+    /// Variant name, as used by `EventFilter::kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Transfer { .. } => "Transfer",
+            Event::NonceIncremented { .. } => "NonceIncremented",
+            Event::BlockMined { .. } => "BlockMined",
+            Event::TransactionFailed { .. } => "TransactionFailed",
+            Event::RewardPaid { .. } => "RewardPaid",
+            Event::ContractDeployed { .. } => "ContractDeployed",
+            Event::ContractExecuted { .. } => "ContractExecuted",
+            Event::ContractReverted { .. } => "ContractReverted",
+        }
+    }
+
+    pub fn accounts(&self) -> Vec<&String> {
+        match self {
+            Event::Transfer { from, to, .. } => vec![from, to],
+            Event::NonceIncremented { who, .. }
+            | Event::TransactionFailed { who, .. }
+            | Event::RewardPaid { who, .. } => vec![who],
+            Event::BlockMined { author, .. } => vec![author],
+            Event::ContractDeployed { contract, deployer } => vec![contract, deployer],
+            Event::ContractExecuted { contract, caller, .. }
+            | Event::ContractReverted { contract, caller, .. } => vec![contract, caller],
+        }
+    }
+}
+
+/// When in block execution an event was emitted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Phase {
+    /// While applying the transaction at this index in the block.
+    ApplyExtrinsic(u32),
+    /// A pending transaction that failed and was left out of the block.
+    Rejected,
+    Finalization,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub block: u32,
+    pub phase: Phase,
+    pub event: Event,
+}
+
+/// Unset fields match everything. Block bounds are inclusive.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EventFilter {
+    pub account: Option<String>,
+    pub kind: Option<String>,
+    pub from_block: Option<u32>,
+    pub to_block: Option<u32>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, record: &EventRecord) -> bool {
+        if self.from_block.is_some_and(|from| record.block < from) {
+            return false;
+        }
+        if self.to_block.is_some_and(|to| record.block > to) {
+            return false;
+        }
+        if self.kind.as_ref().is_some_and(|kind| kind != record.event.kind()) {
+            return false;
+        }
+        match &self.account {
+            Some(account) => record.event.accounts().contains(&account),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(block: u32, event: Event) -> EventRecord {
+        EventRecord { block, phase: Phase::Finalization, event }
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let transfer = record(3, Event::Transfer { from: "alice".to_string(), to: "bob".to_string(), amount: 5 });
+        let reward = record(4, Event::RewardPaid { who: "miner".to_string(), amount: 100 });
+
+        let by_account = EventFilter { account: Some("bob".to_string()), ..Default::default() };
+        assert!(by_account.matches(&transfer));
+        assert!(!by_account.matches(&reward));
+
+        let by_kind = EventFilter { kind: Some("RewardPaid".to_string()), ..Default::default() };
+        assert!(by_kind.matches(&reward));
+        assert!(!by_kind.matches(&transfer));
+
+        let by_range = EventFilter { from_block: Some(4), to_block: Some(4), ..Default::default() };
+        assert!(by_range.matches(&reward));
+        assert!(!by_range.matches(&transfer));
+    }
+
+    #[test]
+    fn test_event_json_is_tagged() {
+        let event = Event::NonceIncremented { who: "alice".to_string(), nonce: 2 };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "NonceIncremented");
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+    }
+}
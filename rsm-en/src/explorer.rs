@@ -0,0 +1,301 @@
+//! Read-only views for the block explorer: blocks by number or hash,
+//! transactions with their inclusion proofs, accounts and the rich list.
+//! Lists are paginated and newest first. Hashes are hex strings.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::blockchain::{coinbase_payee, Blockchain};
+use crate::error::RequestError;
+use crate::genesis::LedgerMode;
+use crate::merkle::Hash;
+use crate::runtime::Call;
+use crate::transaction::{Block, Transaction};
+use crate::utxo::Script;
+
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Which slice of a list to return. Pages count from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    pub page: usize,
+    pub limit: usize,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        PageRequest { page: 0, limit: DEFAULT_PAGE_SIZE }
+    }
+}
+
+impl PageRequest {
+    /// Reads `page` and `limit` from a query string. `limit` must be 1 to
+    /// `MAX_PAGE_SIZE`.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, RequestError> {
+        let mut request = PageRequest::default();
+        if let Some(page) = query.get("page") {
+            request.page = page.parse().map_err(|_| RequestError::InvalidQuery("page must be a number".to_string()))?;
+        }
+        if let Some(limit) = query.get("limit") {
+            request.limit = limit
+                .parse()
+                .ok()
+                .filter(|limit| (1..=MAX_PAGE_SIZE).contains(limit))
+                .ok_or_else(|| RequestError::InvalidQuery(format!("limit must be 1 to {}", MAX_PAGE_SIZE)))?;
+        }
+        Ok(request)
+    }
+
+    fn slice<T>(self, items: impl ExactSizeIterator<Item = T>) -> Page<T> {
+        let total = items.len();
+        let items = items.skip(self.page.saturating_mul(self.limit)).take(self.limit).collect();
+        Page { page: self.page, limit: self.limit, total, items }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub page: usize,
+    pub limit: usize,
+    pub total: usize,
+    pub items: Vec<T>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockSummary {
+    pub index: u32,
+    pub hash: String,
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub transaction_count: usize,
+    /// Who the block reward went to.
+    pub author: Option<String>,
+    pub total_games: Option<u64>,
+}
+
+impl BlockSummary {
+    pub fn new(block: &Block) -> Self {
+        let author = match &block.utxo {
+            Some(body) => body.transactions.first().map(coinbase_payee),
+            None => block.transactions.first().and_then(|tx| tx.transfer_details()).map(|(to, _)| to.clone()),
+        };
+        BlockSummary {
+            index: block.index,
+            hash: block.hash.to_hex(),
+            previous_hash: block.previous_hash.to_hex(),
+            timestamp: block.timestamp,
+            transaction_count: block.transactions.len() + block.utxo.as_ref().map_or(0, |body| body.transactions.len()),
+            author,
+            total_games: block.rps_mining_result.as_ref().map(|result| result.total_games),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockDetail {
+    #[serde(flatten)]
+    pub summary: BlockSummary,
+    pub merkle_root: String,
+    pub snapshot_hash: Option<String>,
+    pub transactions: Vec<TransactionView>,
+    /// Hashes of the UTXO spends, in UTXO mode.
+    pub utxo_transactions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionView {
+    pub hash: String,
+    pub from: String,
+    pub nonce: u32,
+    pub timestamp: u64,
+    pub call: Call,
+    pub signed: bool,
+}
+
+impl TransactionView {
+    fn new(tx: &Transaction) -> Self {
+        TransactionView {
+            hash: tx.hash.to_hex(),
+            from: tx.from.clone(),
+            nonce: tx.nonce,
+            timestamp: tx.timestamp,
+            call: tx.call.clone(),
+            signed: tx.signature.is_some(),
+        }
+    }
+}
+
+/// A confirmed transaction with the Merkle proof that puts it in its
+/// block.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionDetail {
+    pub transaction: TransactionView,
+    pub block_index: u32,
+    pub block_hash: String,
+    pub tx_index: usize,
+    pub merkle_root: String,
+    pub proof: Vec<String>,
+    pub verified: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountView {
+    pub account: String,
+    pub balance: u128,
+    pub next_nonce: u32,
+    pub history: Page<TransactionView>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RichListEntry {
+    pub rank: usize,
+    pub account: String,
+    pub balance: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainOverview {
+    pub height: u32,
+    pub state_root: Option<String>,
+    pub blocks: Page<BlockSummary>,
+}
+
+pub fn latest_blocks(blockchain: &Blockchain, request: PageRequest) -> ChainOverview {
+    ChainOverview {
+        height: blockchain.get_latest_block().index,
+        state_root: blockchain.get_state_root().map(|root| root.to_hex()),
+        blocks: request.slice(blockchain.chain.iter().rev().map(BlockSummary::new)),
+    }
+}
+
+/// A block by number or by hex hash.
+pub fn block(blockchain: &Blockchain, id: &str) -> Result<BlockDetail, RequestError> {
+    let block = match (id.parse::<u32>(), Hash::from_hex(id)) {
+        (Ok(index), _) => blockchain.get_block_by_index(index),
+        (_, Some(hash)) => blockchain.get_block_by_hash(&hash),
+        _ => return Err(RequestError::InvalidQuery("expected a block number or hash".to_string())),
+    };
+    let block = block.ok_or_else(|| RequestError::NotFound("Block".to_string()))?;
+    Ok(BlockDetail {
+        summary: BlockSummary::new(block),
+        merkle_root: block.merkle_root.to_hex(),
+        snapshot_hash: block.snapshot_hash.as_ref().map(Hash::to_hex),
+        transactions: block.transactions.iter().map(TransactionView::new).collect(),
+        utxo_transactions: block.utxo.iter().flat_map(|body| &body.transactions).map(|tx| tx.hash.to_hex()).collect(),
+    })
+}
+
+pub fn transaction(blockchain: &Blockchain, hash: &str) -> Result<TransactionDetail, RequestError> {
+    let hash = Hash::from_hex(hash).ok_or_else(|| RequestError::InvalidQuery("expected a transaction hash".to_string()))?;
+    let (block, tx, tx_index) = blockchain.find_transaction(&hash).ok_or_else(|| RequestError::NotFound("Transaction".to_string()))?;
+    let proof = block.get_transaction_proof(tx_index).unwrap_or_default();
+    Ok(TransactionDetail {
+        transaction: TransactionView::new(tx),
+        block_index: block.index,
+        block_hash: block.hash.to_hex(),
+        tx_index,
+        merkle_root: block.merkle_root.to_hex(),
+        verified: blockchain.verify_transaction_proof(tx, &proof, tx_index, block.index),
+        proof: proof.iter().map(Hash::to_hex).collect(),
+    })
+}
+
+pub fn account(blockchain: &Blockchain, account: &str, request: PageRequest) -> AccountView {
+    let account = account.to_string();
+    let history = blockchain.get_transaction_history(&account);
+    AccountView {
+        balance: blockchain.account_balance(&account),
+        next_nonce: blockchain.next_nonce(&account),
+        history: request.slice(history.into_iter().rev().map(TransactionView::new)),
+        account,
+    }
+}
+
+/// Accounts by balance, largest first. In UTXO mode, outputs are summed
+/// per address, or per script when not pay-to-pubkey-hash.
+pub fn rich_list(blockchain: &Blockchain, request: PageRequest) -> Page<RichListEntry> {
+    let mut holdings: Vec<(String, u128)> = match blockchain.ledger_mode {
+        LedgerMode::Account => blockchain
+            .runtime
+            .balances
+            .balances
+            .iter()
+            .map(|(account, balance)| (account.clone(), *balance))
+            .collect(),
+        LedgerMode::Utxo => {
+            let mut by_owner: BTreeMap<String, u128> = BTreeMap::new();
+            for utxo in blockchain.runtime.utxo.unspent.values() {
+                let owner = match &utxo.output.script {
+                    Script::PayToPubkeyHash(address) => address.to_hex(),
+                    script => script.to_string(),
+                };
+                let held = by_owner.entry(owner).or_default();
+                *held = held.saturating_add(utxo.output.value);
+            }
+            by_owner.into_iter().collect()
+        }
+    };
+    holdings.retain(|(_, balance)| *balance > 0);
+    holdings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    request.slice(
+        holdings
+            .into_iter()
+            .enumerate()
+            .map(|(position, (account, balance))| RichListEntry { rank: position + 1, account, balance }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain_with_transfer() -> (Blockchain, Transaction) {
+        let mut blockchain = Blockchain::new();
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 100, 1);
+        blockchain.add_transaction(tx.clone()).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        (blockchain, tx)
+    }
+
+    #[test]
+    fn test_blocks_and_transactions() {
+        let (blockchain, tx) = chain_with_transfer();
+        let overview = latest_blocks(&blockchain, PageRequest { page: 0, limit: 2 });
+        assert_eq!(overview.height, 2);
+        assert_eq!(overview.blocks.total, 3);
+        assert_eq!(overview.blocks.items.iter().map(|block| block.index).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(overview.blocks.items[0].author.as_deref(), Some("miner"));
+
+        let by_number = block(&blockchain, "1").unwrap();
+        let by_hash = block(&blockchain, &by_number.summary.hash).unwrap();
+        assert_eq!(by_hash.summary.index, 1);
+        assert_eq!(by_hash.transactions[1].hash, tx.hash.to_hex());
+        assert_eq!(block(&blockchain, "9").unwrap_err(), RequestError::NotFound("Block".to_string()));
+        assert!(matches!(block(&blockchain, "latest"), Err(RequestError::InvalidQuery(_))));
+
+        let detail = transaction(&blockchain, &tx.hash.to_hex()).unwrap();
+        assert_eq!((detail.block_index, detail.tx_index), (1, 1));
+        assert!(detail.verified);
+    }
+
+    #[test]
+    fn test_accounts_and_rich_list_paginate() {
+        let (blockchain, tx) = chain_with_transfer();
+        let bob = account(&blockchain, "bob", PageRequest::default());
+        assert_eq!(bob.balance, blockchain.get_balance(&"bob".to_string()));
+        assert_eq!(bob.history.items[0].hash, tx.hash.to_hex());
+
+        let rich = rich_list(&blockchain, PageRequest { page: 0, limit: 2 });
+        assert_eq!(rich.items[0].rank, 1);
+        assert!(rich.items[0].balance >= rich.items[1].balance);
+        let next = rich_list(&blockchain, PageRequest { page: 1, limit: 2 });
+        assert_eq!(next.items[0].rank, 3);
+        assert_eq!(next.total, rich.total);
+
+        let query: HashMap<String, String> = [("limit".to_string(), "500".to_string())].into();
+        assert!(PageRequest::from_query(&query).is_err());
+    }
+}
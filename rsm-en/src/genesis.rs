@@ -0,0 +1,221 @@
+use crate::error::ErrorCode;
+use crate::merkle::Hash;
+use crate::rps_mining::MAX_RPS_PLAYERS;
+use crate::utxo::Script;
+use crate::vesting::VestingSchedule;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Chain ID of the built-in development chain, and of transactions built
+/// without one.
+pub const DEFAULT_CHAIN_ID: u64 = 1;
+
+/// How value is held: account balances in `balances::Pallet`, or unspent
+/// outputs in a `utxo::UtxoSet`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerMode {
+    #[default]
+    Account,
+    Utxo,
+}
+
+/// Everything a chain starts from. Nodes given the same spec produce the
+/// same genesis block, whose previous hash commits to the spec.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisSpec {
+    pub name: String,
+    pub chain_id: u64,
+    #[serde(default)]
+    pub ledger: LedgerMode,
+    /// Genesis block timestamp, in seconds.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// Initial balances by account, or by address in UTXO mode.
+    pub balances: BTreeMap<String, u128>,
+    /// Vesting schedules on part of an account's initial balance, which
+    /// stays locked until it vests. Account mode only.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vesting: BTreeMap<String, Vec<VestingSchedule>>,
+    pub consensus: ConsensusParams,
+    pub rewards: RewardSchedule,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsensusParams {
+    pub difficulty: usize,
+    pub rps_total_players: u32,
+    /// Seeds the RPS players. Shared so every node's miner state, and so
+    /// every state snapshot, agrees.
+    pub rps_seed: u64,
+    pub snapshot_interval: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RewardSchedule {
+    pub mining_reward: u128,
+    /// Blocks between halvings of the mining reward. Zero never halves.
+    #[serde(default)]
+    pub halving_interval: u32,
+    pub staking_reward_per_block: u128,
+    pub fee_per_weight: u128,
+}
+
+/// Mining reward at `height`: `base` (the governed mining reward) halved
+/// once every `halving_interval` blocks.
+pub fn halved_reward(base: u128, halving_interval: u32, height: u32) -> u128 {
+    if halving_interval == 0 {
+        return base;
+    }
+    base.checked_shr(height / halving_interval).unwrap_or(0)
+}
+
+impl Default for GenesisSpec {
+    /// The development chain.
+    fn default() -> Self {
+        Self {
+            name: "PhlopChain Dev".to_string(),
+            chain_id: DEFAULT_CHAIN_ID,
+            ledger: LedgerMode::Account,
+            timestamp: 0,
+            balances: BTreeMap::from([
+                ("genesis".to_string(), 1_000_000),
+                ("alice".to_string(), 1000),
+                ("bob".to_string(), 500),
+            ]),
+            vesting: BTreeMap::new(),
+            consensus: ConsensusParams {
+                difficulty: 2,
+                rps_total_players: 100,
+                rps_seed: 0x5048_4c4f_5000_0001,
+                snapshot_interval: 10,
+            },
+            rewards: RewardSchedule {
+                mining_reward: 100,
+                halving_interval: 0,
+                staking_reward_per_block: 10,
+                fee_per_weight: 1,
+            },
+        }
+    }
+}
+
+impl GenesisSpec {
+    /// Parses and validates a spec.
+    pub fn from_json(json: &str) -> Result<Self, GenesisError> {
+        let spec: Self = serde_json::from_str(json).map_err(|e| GenesisError::Decode(e.to_string()))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn validate(&self) -> Result<(), GenesisError> {
+        if self.name.trim().is_empty() {
+            return Err(GenesisError::Invalid("name is empty".to_string()));
+        }
+        if !(1..=MAX_RPS_PLAYERS).contains(&self.consensus.rps_total_players) {
+            return Err(GenesisError::Invalid(format!("rps_total_players must be 1 to {}", MAX_RPS_PLAYERS)));
+        }
+        if let Some(account) = self.balances.keys().find(|account| account.is_empty() || *account == "network") {
+            return Err(GenesisError::Invalid(format!("cannot endow account '{}'", account)));
+        }
+        for (account, schedules) in &self.vesting {
+            if let Some(e) = schedules.iter().find_map(|schedule| schedule.validate().err()) {
+                return Err(GenesisError::Invalid(format!("vesting for '{}': {}", account, e)));
+            }
+            let locked = schedules.iter().fold(0u128, |total, schedule| total.saturating_add(schedule.locked()));
+            if locked > self.balances.get(account).copied().unwrap_or(0) {
+                return Err(GenesisError::Invalid(format!("'{}' vests more than its balance", account)));
+            }
+        }
+        if self.ledger == LedgerMode::Utxo {
+            if !self.vesting.is_empty() {
+                return Err(GenesisError::Invalid("vesting needs account mode".to_string()));
+            }
+            if let Some(address) = self.balances.keys().find(|address| Script::for_address(address).is_none()) {
+                return Err(GenesisError::Invalid(format!("'{}' is not an address", address)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn hash(&self) -> Hash {
+        Hash::from_string(&serde_json::to_string(self).expect("genesis spec is serializable"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenesisError {
+    Decode(String),
+    Invalid(String),
+}
+
+impl fmt::Display for GenesisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenesisError::Decode(detail) => write!(f, "Cannot decode genesis spec: {}", detail),
+            GenesisError::Invalid(reason) => write!(f, "Invalid genesis spec: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GenesisError {}
+
+impl ErrorCode for GenesisError {
+    fn code(&self) -> u16 {
+        match self {
+            GenesisError::Decode(_) => 8001,
+            GenesisError::Invalid(_) => 8002,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "genesis"
+    }
+
+    fn http_status(&self) -> u16 {
+        400
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_json_round_trip() {
+        let spec = GenesisSpec::default();
+        let parsed = GenesisSpec::from_json(&serde_json::to_string_pretty(&spec).unwrap()).unwrap();
+        assert_eq!(parsed, spec);
+        assert_eq!(parsed.hash(), spec.hash());
+
+        let mut invalid = spec.clone();
+        invalid.balances.insert("network".to_string(), 1);
+        assert_eq!(invalid.validate().unwrap_err().code(), 8002);
+        assert_eq!(GenesisSpec::from_json("{}").unwrap_err().code(), 8001);
+    }
+
+    #[test]
+    fn test_vesting_within_balance() {
+        let mut spec = GenesisSpec::default();
+        let json = serde_json::to_string(&spec).unwrap();
+        assert!(!json.contains("vesting"));
+
+        spec.vesting.insert("alice".to_string(), vec![VestingSchedule::Cliff { locked: 1000, unlock_at: 10 }]);
+        assert_eq!(spec.validate(), Ok(()));
+        assert_ne!(spec.hash(), GenesisSpec::default().hash());
+
+        spec.vesting.insert("bob".to_string(), vec![VestingSchedule::Cliff { locked: 501, unlock_at: 10 }]);
+        assert_eq!(spec.validate().unwrap_err().code(), 8002);
+        spec.vesting.insert("bob".to_string(), vec![VestingSchedule::Linear { locked: 100, per_block: 0, starting_block: 0 }]);
+        assert_eq!(spec.validate().unwrap_err().code(), 8002);
+    }
+
+    #[test]
+    fn test_reward_halving() {
+        assert_eq!(halved_reward(100, 0, 1_000), 100);
+        assert_eq!(halved_reward(100, 10, 9), 100);
+        assert_eq!(halved_reward(100, 10, 25), 25);
+        assert_eq!(halved_reward(100, 1, 200), 0);
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+use num_traits::{CheckedAdd, CheckedMul, NumCast, Saturating, Zero};
+use serde::{Deserialize, Serialize};
+use crate::balances::{self, Pallet as BalancesPallet};
+use crate::runtime::{DefaultConfig, DispatchResult};
+use crate::error::LedgerError;
+use crate::rps_mining::MAX_RPS_PLAYERS;
+
+pub type ProposalId = u32;
+
+/// A chain parameter that governance can change. The runtime applies the
+/// ones it owns; the rest are handed back to `Blockchain`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParameterChange {
+    MiningReward(u128),
+    Difficulty(usize),
+    RpsTotalPlayers(u32),
+    FeePerWeight(u128),
+    ExistentialDeposit(u128),
+    StakingRewardPerBlock(u128),
+    BondingDuration(u32),
+}
+
+impl ParameterChange {
+    /// Builds a change from a snake_case parameter name and a value, as
+    /// taken by the CLI and the web API.
+    pub fn parse(parameter: &str, value: &str) -> Result<Self, LedgerError> {
+        let bad_value = |_| LedgerError::InvalidParameter(format!("invalid value '{}' for {}", value, parameter));
+        let change = match parameter {
+            "mining_reward" => ParameterChange::MiningReward(value.parse().map_err(bad_value)?),
+            "difficulty" => ParameterChange::Difficulty(value.parse().map_err(bad_value)?),
+            "rps_total_players" => ParameterChange::RpsTotalPlayers(value.parse().map_err(bad_value)?),
+            "fee_per_weight" => ParameterChange::FeePerWeight(value.parse().map_err(bad_value)?),
+            "existential_deposit" => ParameterChange::ExistentialDeposit(value.parse().map_err(bad_value)?),
+            "staking_reward_per_block" => ParameterChange::StakingRewardPerBlock(value.parse().map_err(bad_value)?),
+            "bonding_duration" => ParameterChange::BondingDuration(value.parse().map_err(bad_value)?),
+            _ => return Err(LedgerError::UnknownParameter(parameter.to_string())),
+        };
+        change.validate()?;
+        Ok(change)
+    }
+
+    pub fn validate(&self) -> DispatchResult {
+        match self {
+            ParameterChange::RpsTotalPlayers(0) => Err(LedgerError::InvalidParameter("RPS needs at least one player".to_string())),
+            ParameterChange::RpsTotalPlayers(players) if *players > MAX_RPS_PLAYERS => {
+                Err(LedgerError::InvalidParameter(format!("RPS allows at most {} players", MAX_RPS_PLAYERS)))
+            }
+            ParameterChange::ExistentialDeposit(0) => Err(LedgerError::InvalidParameter("Existential deposit must be positive".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Call {
+    Propose { change: ParameterChange },
+    Vote { proposal_id: ProposalId, aye: bool },
+}
+
+impl Call {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Propose { .. } => 30,
+            Call::Vote { .. } => 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum ProposalStatus<T: balances::Config = DefaultConfig> {
+    Voting,
+    Scheduled { at: T::BlockNumber },
+    Enacted,
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Vote<T: balances::Config = DefaultConfig> {
+    pub aye: bool,
+    pub weight: T::Balance,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Proposal<T: balances::Config = DefaultConfig> {
+    pub id: ProposalId,
+    pub proposer: T::AccountId,
+    pub change: ParameterChange,
+    pub deposit: T::Balance,
+    pub voting_ends: T::BlockNumber,
+    pub ayes: T::Balance,
+    pub nays: T::Balance,
+    pub votes: BTreeMap<T::AccountId, Vote<T>>,
+    pub status: ProposalStatus<T>,
+}
+
+/// Balance-weighted voting on parameter changes. A vote locks the voter's
+/// free balance until voting ends so the same funds cannot vote twice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Pallet<T: balances::Config = DefaultConfig> {
+    pub proposals: BTreeMap<ProposalId, Proposal<T>>,
+    pub next_proposal_id: ProposalId,
+    pub enactment_queue: BTreeMap<T::BlockNumber, Vec<ProposalId>>,
+    pub voting_period: T::BlockNumber,
+    pub enactment_delay: T::BlockNumber,
+    /// Share of the turnout, in percent, that must vote aye to pass.
+    pub threshold_percent: u8,
+    pub proposal_deposit: T::Balance,
+}
+
+impl<T: balances::Config> Pallet<T> {
+
+    pub fn new(voting_period: T::BlockNumber, enactment_delay: T::BlockNumber, threshold_percent: u8, proposal_deposit: T::Balance) -> Self {
+
+        Self {
+            proposals: BTreeMap::new(),
+            next_proposal_id: 0,
+            enactment_queue: BTreeMap::new(),
+            voting_period,
+            enactment_delay,
+            threshold_percent: threshold_percent.min(100),
+            proposal_deposit,
+        }
+    }
+
+    pub fn propose(&mut self, who: &T::AccountId, change: ParameterChange, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> Result<ProposalId, LedgerError> {
+
+        change.validate()?;
+        let voting_ends = now.checked_add(&self.voting_period).ok_or(LedgerError::Arithmetic)?;
+        balances.reserve(who, self.proposal_deposit)?;
+
+        let id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        self.proposals.insert(id, Proposal {
+            id,
+            proposer: who.clone(),
+            change,
+            deposit: self.proposal_deposit,
+            voting_ends,
+            ayes: T::Balance::zero(),
+            nays: T::Balance::zero(),
+            votes: BTreeMap::new(),
+            status: ProposalStatus::Voting,
+        });
+        Ok(id)
+    }
+
+    /// Records or replaces a vote weighted by the voter's free balance.
+    pub fn vote(&mut self, who: &T::AccountId, proposal_id: ProposalId, aye: bool, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> DispatchResult {
+
+        let proposal = self.proposals.get_mut(&proposal_id).ok_or(LedgerError::UnknownProposal)?;
+        if !matches!(proposal.status, ProposalStatus::Voting) || proposal.voting_ends <= now {
+            return Err(LedgerError::VotingClosed);
+        }
+        let weight = balances.get_balance(who);
+        if weight.is_zero() {
+            return Err(LedgerError::NoVotingBalance);
+        }
+
+        if let Some(previous) = proposal.votes.insert(who.clone(), Vote { aye, weight }) {
+            if previous.aye {
+                proposal.ayes = proposal.ayes.saturating_sub(previous.weight);
+            } else {
+                proposal.nays = proposal.nays.saturating_sub(previous.weight);
+            }
+        }
+        if aye {
+            proposal.ayes = proposal.ayes.saturating_add(weight);
+        } else {
+            proposal.nays = proposal.nays.saturating_add(weight);
+        }
+
+        balances.set_lock(Self::lock_id(proposal_id), who, weight, proposal.voting_ends);
+        Ok(())
+    }
+
+    pub fn passes(&self, proposal: &Proposal<T>) -> bool {
+
+        let turnout = proposal.ayes.saturating_add(proposal.nays);
+        if turnout.is_zero() {
+            return false;
+        }
+        let hundred: T::Balance = NumCast::from(100u8).unwrap_or_else(T::Balance::zero);
+        let threshold: T::Balance = NumCast::from(self.threshold_percent).unwrap_or_else(T::Balance::zero);
+        let required = turnout
+            .checked_mul(&threshold)
+            .map(|scaled| scaled / hundred)
+            .unwrap_or_else(|| turnout / hundred * threshold);
+        proposal.ayes > required
+    }
+
+    /// Closes proposals whose voting period has ended, returning deposits
+    /// and scheduling the ones that passed. Returns the changes due at
+    /// `now`, in proposal order.
+    pub fn on_finalize(&mut self, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> Vec<ParameterChange> {
+
+        let closing: Vec<ProposalId> = self.proposals
+            .values()
+            .filter(|p| matches!(p.status, ProposalStatus::Voting) && p.voting_ends <= now)
+            .map(|p| p.id)
+            .collect();
+
+        for id in closing {
+            let passed = self.passes(&self.proposals[&id]);
+            let at = now.saturating_add(self.enactment_delay);
+            let proposal = self.proposals.get_mut(&id).unwrap();
+            balances.unreserve(&proposal.proposer, proposal.deposit);
+
+            if passed {
+                proposal.status = ProposalStatus::Scheduled { at };
+                self.enactment_queue.entry(at).or_default().push(id);
+            } else {
+                proposal.status = ProposalStatus::Rejected;
+            }
+        }
+
+        let due: Vec<T::BlockNumber> = self.enactment_queue.range(..=now).map(|(at, _)| *at).collect();
+        let mut changes = Vec::new();
+        for at in due {
+            for id in self.enactment_queue.remove(&at).unwrap_or_default() {
+                if let Some(proposal) = self.proposals.get_mut(&id) {
+                    proposal.status = ProposalStatus::Enacted;
+                    changes.push(proposal.change.clone());
+                }
+            }
+        }
+        changes
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call, balances: &mut BalancesPallet<T>, now: T::BlockNumber) -> DispatchResult {
+
+        match call {
+            Call::Propose { change } => self.propose(caller, change.clone(), now, balances).map(|_| ()),
+            Call::Vote { proposal_id, aye } => self.vote(caller, *proposal_id, *aye, now, balances),
+        }
+    }
+
+    fn lock_id(proposal_id: ProposalId) -> balances::LockId {
+
+        let mut id = *b"gov\0\0\0\0\0";
+        id[4..].copy_from_slice(&proposal_id.to_be_bytes());
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Pallet, BalancesPallet) {
+        let mut balances = BalancesPallet::new();
+        balances.set_balance(&"alice".to_string(), 600);
+        balances.set_balance(&"bob".to_string(), 300);
+        (Pallet::new(3, 2, 50, 10), balances)
+    }
+
+    #[test]
+    fn test_proposal_passes_and_enacts_later() {
+        let (mut gov, mut balances) = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        let id = gov.propose(&alice, ParameterChange::MiningReward(150), 0, &mut balances).unwrap();
+        assert_eq!(balances.reserved_balance(&alice), 10);
+        gov.vote(&alice, id, true, 1, &mut balances).unwrap();
+        gov.vote(&bob, id, false, 1, &mut balances).unwrap();
+        assert!(balances.transfer(alice.clone(), bob.clone(), 1).is_err());
+
+        assert!(gov.on_finalize(2, &mut balances).is_empty());
+        assert!(gov.on_finalize(3, &mut balances).is_empty());
+        assert_eq!(gov.proposals[&id].status, ProposalStatus::Scheduled { at: 5 });
+        assert_eq!(balances.reserved_balance(&alice), 0);
+
+        assert!(gov.on_finalize(4, &mut balances).is_empty());
+        assert_eq!(gov.on_finalize(5, &mut balances), vec![ParameterChange::MiningReward(150)]);
+        assert_eq!(gov.proposals[&id].status, ProposalStatus::Enacted);
+    }
+
+    #[test]
+    fn test_proposal_rejected_below_threshold() {
+        let (mut gov, mut balances) = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        gov.threshold_percent = 70;
+
+        let id = gov.propose(&bob, ParameterChange::Difficulty(4), 0, &mut balances).unwrap();
+        gov.vote(&bob, id, true, 0, &mut balances).unwrap();
+        gov.vote(&alice, id, true, 0, &mut balances).unwrap();
+        gov.vote(&alice, id, false, 1, &mut balances).unwrap();
+
+        gov.on_finalize(3, &mut balances);
+        assert_eq!(gov.proposals[&id].status, ProposalStatus::Rejected);
+        assert!(gov.vote(&alice, id, true, 4, &mut balances).is_err());
+    }
+
+    #[test]
+    fn test_parse_parameter_change() {
+        assert_eq!(ParameterChange::parse("mining_reward", "150"), Ok(ParameterChange::MiningReward(150)));
+        assert!(ParameterChange::parse("difficulty", "-1").is_err());
+        assert!(ParameterChange::parse("block_size", "1").is_err());
+        assert!(ParameterChange::parse("rps_total_players", "0").is_err());
+        assert!(ParameterChange::parse("rps_total_players", &MAX_RPS_PLAYERS.to_string()).is_ok());
+        assert!(ParameterChange::RpsTotalPlayers(u32::MAX).validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_change_rejected() {
+        let (mut gov, mut balances) = setup();
+        let result = gov.propose(&"alice".to_string(), ParameterChange::RpsTotalPlayers(0), 0, &mut balances);
+        assert!(result.is_err());
+        assert_eq!(balances.reserved_balance(&"alice".to_string()), 0);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::merkle::Hash;
+use ed25519_compact::{KeyPair as Ed25519KeyPair, PublicKey, Seed, Signature};
+
+/// An ed25519 key pair. Keys and signatures travel as hex; an address is
+/// the hash of the public key.
+#[derive(Debug, Clone)]
+pub struct KeyPair {
+    seed: [u8; 32],
+    pair: Ed25519KeyPair,
+}
+
+impl KeyPair {
+    pub fn generate() -> Self {
+        Self::from_seed(*Seed::generate())
+    }
+
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self { seed, pair: Ed25519KeyPair::from_seed(Seed::new(seed)) }
+    }
+
+    /// Restores a key from `secret_hex()`.
+    pub fn from_secret_hex(secret: &str) -> Option<Self> {
+        let seed = hex::decode(secret.trim()).ok()?.try_into().ok()?;
+        Some(Self::from_seed(seed))
+    }
+
+    pub fn secret_hex(&self) -> String {
+        hex::encode(self.seed)
+    }
+
+    pub fn public_hex(&self) -> String {
+        hex::encode(*self.pair.pk)
+    }
+
+    pub fn address(&self) -> Hash {
+        Hash::from_bytes(&*self.pair.pk)
+    }
+
+    /// Deterministic signature over `message`, as hex.
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(*self.pair.sk.sign(message, None))
+    }
+}
+
+/// Hash of a hex public key. `None` if it is not a valid key.
+pub fn address_of(public_hex: &str) -> Option<Hash> {
+    let bytes = hex::decode(public_hex).ok()?;
+    PublicKey::from_slice(&bytes).ok()?;
+    Some(Hash::from_bytes(&bytes))
+}
+
+pub fn verify(public_hex: &str, message: &[u8], signature_hex: &str) -> bool {
+    let (Ok(public), Ok(signature)) = (hex::decode(public_hex), hex::decode(signature_hex)) else {
+        return false;
+    };
+    match (PublicKey::from_slice(&public), Signature::from_slice(&signature)) {
+        (Ok(public), Ok(signature)) => public.verify(message, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = KeyPair::from_seed([7; 32]);
+        let signature = key.sign(b"spend");
+        assert!(verify(&key.public_hex(), b"spend", &signature));
+        assert!(!verify(&key.public_hex(), b"spend twice", &signature));
+        assert!(!verify(&KeyPair::from_seed([8; 32]).public_hex(), b"spend", &signature));
+
+        let restored = KeyPair::from_secret_hex(&key.secret_hex()).unwrap();
+        assert_eq!(restored.address(), key.address());
+        assert_eq!(address_of(&key.public_hex()), Some(key.address()));
+    }
+}
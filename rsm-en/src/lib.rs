@@ -1,6 +1,28 @@
 
 pub mod balances;
 pub mod system;
+pub mod runtime;
+pub mod staking;
+pub mod assets;
+pub mod governance;
+pub mod multisig;
+pub mod vesting;
+pub mod vm;
+pub mod contracts;
+pub mod keys;
+pub mod utxo;
+pub mod error;
+pub mod storage;
+pub mod snapshot;
+pub mod sync;
+pub mod p2p;
+pub mod simulator;
+pub mod rpc;
+pub mod client;
+pub mod explorer;
+pub mod stream;
+pub mod genesis;
+pub mod events;
 pub mod merkle;
 pub mod transaction;
 pub mod blockchain;
@@ -1,13 +1,52 @@
 
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 
+use crate::error::ErrorCode;
 use crate::orderbook_market::OrderBookMarket;
 use crate::market::OrderSide;
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LiveTradingError {
+    SessionNotFound(String),
+    InvalidPrice(f64),
+}
+
+impl fmt::Display for LiveTradingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiveTradingError::SessionNotFound(id) => write!(f, "Session not found: {}", id),
+            LiveTradingError::InvalidPrice(price) => write!(f, "Invalid trade price: {}", price),
+        }
+    }
+}
+
+impl std::error::Error for LiveTradingError {}
+
+impl ErrorCode for LiveTradingError {
+    fn code(&self) -> u16 {
+        match self {
+            LiveTradingError::SessionNotFound(_) => 5001,
+            LiveTradingError::InvalidPrice(_) => 5002,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "live_trading"
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            LiveTradingError::SessionNotFound(_) => 404,
+            LiveTradingError::InvalidPrice(_) => 400,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradingSession {
     pub session_id: String,
@@ -207,6 +246,12 @@ pub struct LiveTradingEngine {
     sessions: Arc<Mutex<HashMap<String, TradingSession>>>,
 }
 
+impl Default for LiveTradingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LiveTradingEngine {
     pub fn new() -> Self {
         Self {
@@ -224,25 +269,25 @@ impl LiveTradingEngine {
         session_id
     }
 
-    pub fn start_session(&self, session_id: &str) -> Result<(), String> {
+    pub fn start_session(&self, session_id: &str) -> Result<(), LiveTradingError> {
         let mut sessions = self.sessions.lock().unwrap();
         
         if let Some(session) = sessions.get_mut(session_id) {
             session.is_active = true;
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(LiveTradingError::SessionNotFound(session_id.to_string()))
         }
     }
 
-    pub fn stop_session(&self, session_id: &str) -> Result<(), String> {
+    pub fn stop_session(&self, session_id: &str) -> Result<(), LiveTradingError> {
         let mut sessions = self.sessions.lock().unwrap();
         
         if let Some(session) = sessions.get_mut(session_id) {
             session.is_active = false;
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(LiveTradingError::SessionNotFound(session_id.to_string()))
         }
     }
 
@@ -251,14 +296,14 @@ impl LiveTradingEngine {
         sessions.get(session_id).cloned()
     }
 
-    pub fn update_parameters(&self, session_id: &str, params: StrategyParams) -> Result<(), String> {
+    pub fn update_parameters(&self, session_id: &str, params: StrategyParams) -> Result<(), LiveTradingError> {
         let mut sessions = self.sessions.lock().unwrap();
         
         if let Some(session) = sessions.get_mut(session_id) {
             session.strategy_params = params;
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(LiveTradingError::SessionNotFound(session_id.to_string()))
         }
     }
 
@@ -358,7 +403,10 @@ impl LiveTradingEngine {
         });
     }
     
-    pub fn execute_manual_trade(&self, session_id: &str, side: OrderSide, price: f64) -> Result<TradeRecord, String> {
+    pub fn execute_manual_trade(&self, session_id: &str, side: OrderSide, price: f64) -> Result<TradeRecord, LiveTradingError> {
+        if !price.is_finite() || price <= 0.0 {
+            return Err(LiveTradingError::InvalidPrice(price));
+        }
         let mut sessions = self.sessions.lock().unwrap();
         
         if let Some(session) = sessions.get_mut(session_id) {
@@ -366,7 +414,7 @@ impl LiveTradingEngine {
             let trade = session.execute_trade(side, quantity, price, 0);
             Ok(trade)
         } else {
-            Err("Session not found".to_string())
+            Err(LiveTradingError::SessionNotFound(session_id.to_string()))
         }
     }
 }
@@ -1,19 +1,72 @@
-use crate::blockchain::Blockchain;
-use crate::transaction::Transaction;
-
-pub mod balances;
-pub mod system;
-pub mod merkle;
-pub mod transaction;
-pub mod blockchain;
-pub mod rps_mining;
+use rsm_en::blockchain::Blockchain;
+use rsm_en::client::{http_request, DEFAULT_NODE};
+use rsm_en::snapshot::SyncBundle;
+use rsm_en::transaction::Transaction;
+use rsm_en::vm;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = run_command(&args) {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     println!("PhlopChain - Fast Merkle Tree Blockchain Implementation");
     println!("{}", "=".repeat(60));
     run_cli_demo();
 }
 
+/// Commands against a running web node (`PHLOP_NODE`, default
+/// 127.0.0.1:3030):
+///   proposals                (propose and vote with the wallet)
+///   rpc <method> [params]    params as a JSON array or object
+///   snapshot export <file>
+/// and ones that run locally:
+///   snapshot import <file>   verify a bundle by syncing from it
+///   assemble <file>          print a contract's bytecode as hex
+fn run_command(args: &[String]) -> Result<(), String> {
+    let node = std::env::var("PHLOP_NODE").unwrap_or_else(|_| DEFAULT_NODE.to_string());
+    let response = match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["proposals"] => http_request(&node, "GET", "/api/governance/proposals", "")?,
+        ["rpc", method, params @ ..] if params.len() <= 1 => {
+            let params: serde_json::Value = match params.first() {
+                Some(params) => serde_json::from_str(params).map_err(|e| format!("Invalid params '{}': {}", params, e))?,
+                None => serde_json::json!([]),
+            };
+            let body = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+            http_request(&node, "POST", "/rpc", &body.to_string())?
+        }
+        ["snapshot", "export", file] => {
+            let bundle = SyncBundle::from_json(&http_request(&node, "GET", "/api/snapshot", "")?).map_err(|e| e.to_string())?;
+            std::fs::write(file, serde_json::to_string(&bundle).unwrap()).map_err(|e| format!("Cannot write {}: {}", file, e))?;
+            format!("Exported snapshot at block #{} with {} blocks to {}", bundle.snapshot.height, bundle.blocks.len(), file)
+        }
+        ["snapshot", "import", file] => {
+            let json = std::fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+            let bundle = SyncBundle::from_json(&json).map_err(|e| e.to_string())?;
+            let height = bundle.snapshot.height;
+            let blockchain = Blockchain::from_snapshot(bundle).map_err(|e| e.to_string())?;
+            let tip = blockchain.get_latest_block();
+            format!(
+                "Snapshot at block #{} verified; replayed to block #{} ({}). Start a node from it with PHLOP_SNAPSHOT={}",
+                height, tip.index, tip.hash, file
+            )
+        }
+        ["assemble", file] => {
+            let source = std::fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+            hex::encode(vm::assemble(&source).map_err(|e| e.to_string())?)
+        }
+        _ => {
+            return Err("Usage: blockchain [proposals | rpc <method> [params] | snapshot export <file> | snapshot import <file> | assemble <file>]".to_string());
+        }
+    };
+    println!("{}", response);
+    Ok(())
+}
+
 fn run_cli_demo() {
     let mut blockchain = Blockchain::new();
     println!("Blockchain initialized with genesis block");
@@ -74,7 +127,8 @@ fn run_cli_demo() {
                 println!("  - Rounds played: {}", rps_result.rounds);
                 println!("  - Total games: {}", rps_result.total_games);
                 println!("  - Mining time: {} ms", rps_result.mining_time_ms);
-                println!("  - Players who achieved required wins: {}", rps_result.winning_players.len());
+                println!("  - Players who achieved required wins: {}", rps_result.player_count);
+                println!("  - Player outcomes root: {}", rps_result.outcomes_root);
                 
 
                 let difficulty_info = blockchain.get_rps_difficulty_info();
@@ -117,8 +171,11 @@ fn run_cli_demo() {
     println!("\n📋 Transaction History for Alice:");
     let alice_history = blockchain.get_transaction_history(&"alice".to_string());
     for (i, tx) in alice_history.iter().enumerate() {
-        println!("{}. {} -> {} ({} tokens) [{}]", 
-                 i + 1, tx.from, tx.to, tx.amount, tx.hash.to_hex()[..8].to_string());
+        match tx.transfer_details() {
+            Some((to, amount)) => println!("{}. {} -> {} ({} tokens) [{}]", 
+                                           i + 1, tx.from, to, amount, &tx.hash.to_hex()[..8]),
+            None => println!("{}. {} {:?} [{}]", i + 1, tx.from, tx.call, &tx.hash.to_hex()[..8]),
+        }
     }
     if let Some(state_root) = blockchain.get_state_root() {
         println!("\nCurrent State Root: {}", state_root);
@@ -169,11 +226,11 @@ fn run_cli_demo() {
     
 
     for (i, block) in blockchain.chain.iter().enumerate() {
-        println!("\nBlock {}: {}", i, block.hash.to_hex()[..16].to_string());
+        println!("\nBlock {}: {}", i, &block.hash.to_hex()[..16]);
         println!("  Transactions: {}", block.transactions.len());
         println!("  Timestamp: {}", block.timestamp);
         if i > 0 {
-            println!("  Previous: {}", block.previous_hash.to_hex()[..16].to_string());
+            println!("  Previous: {}", &block.previous_hash.to_hex()[..16]);
             if let Some(ref rps_result) = block.rps_mining_result {
                 println!("  RPS: {} rounds, {} games, {} ms", 
                          rps_result.rounds, rps_result.total_games, rps_result.mining_time_ms);
@@ -1,7 +1,47 @@
+use crate::error::ErrorCode;
 use crate::merkle::Hash;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderBookError {
+    SymbolMismatch { expected: String, got: String },
+    InvalidPrice,
+    InvalidQuantity,
+}
+
+impl fmt::Display for OrderBookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderBookError::SymbolMismatch { expected, got } => write!(f, "Order for {} sent to the {} book", got, expected),
+            OrderBookError::InvalidPrice => write!(f, "Price must be positive and finite"),
+            OrderBookError::InvalidQuantity => write!(f, "Quantity must be positive and finite"),
+        }
+    }
+}
+
+impl std::error::Error for OrderBookError {}
+
+impl ErrorCode for OrderBookError {
+    fn code(&self) -> u16 {
+        match self {
+            OrderBookError::SymbolMismatch { .. } => 3001,
+            OrderBookError::InvalidPrice => 3002,
+            OrderBookError::InvalidQuantity => 3003,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "orderbook"
+    }
+
+    fn http_status(&self) -> u16 {
+        400
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderSide {
     Buy,
@@ -85,6 +125,7 @@ pub struct Trade {
 }
 
 impl Trade {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: u64,
         symbol: String,
@@ -183,7 +224,16 @@ impl OrderBook {
         (price * 10000.0) as i64
     }
 
-    pub fn add_order(&mut self, mut order: Order) -> Vec<Trade> {
+    pub fn add_order(&mut self, mut order: Order) -> Result<Vec<Trade>, OrderBookError> {
+        if order.symbol != self.symbol {
+            return Err(OrderBookError::SymbolMismatch { expected: self.symbol.clone(), got: order.symbol });
+        }
+        if !order.price.is_finite() || order.price <= 0.0 {
+            return Err(OrderBookError::InvalidPrice);
+        }
+        if !order.quantity.is_finite() || order.quantity <= 0.0 {
+            return Err(OrderBookError::InvalidQuantity);
+        }
         let mut new_trades = Vec::new();
 
         match order.side {
@@ -287,7 +337,7 @@ impl OrderBook {
             }
         }
 
-        new_trades
+        Ok(new_trades)
     }
 
     pub fn get_best_bid(&self) -> Option<(f64, f64)> {
@@ -405,10 +455,10 @@ mod tests {
         let mut book = OrderBook::new("BTC/USD".to_string());
         
         let buy_order = Order::new(1, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Buy, 50000.0, 1.0);
-        book.add_order(buy_order);
+        book.add_order(buy_order).unwrap();
         
         let sell_order = Order::new(2, "bob".to_string(), "BTC/USD".to_string(), OrderSide::Sell, 50000.0, 1.0);
-        let trades = book.add_order(sell_order);
+        let trades = book.add_order(sell_order).unwrap();
         
         assert_eq!(trades.len(), 1);
         assert_eq!(trades[0].price, 50000.0);
@@ -419,10 +469,10 @@ mod tests {
     fn test_orderbook_depth() {
         let mut book = OrderBook::new("BTC/USD".to_string());
         
-        book.add_order(Order::new(1, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Buy, 49900.0, 1.0));
-        book.add_order(Order::new(2, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Buy, 49800.0, 2.0));
-        book.add_order(Order::new(3, "bob".to_string(), "BTC/USD".to_string(), OrderSide::Sell, 50100.0, 1.0));
-        book.add_order(Order::new(4, "bob".to_string(), "BTC/USD".to_string(), OrderSide::Sell, 50200.0, 2.0));
+        book.add_order(Order::new(1, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Buy, 49900.0, 1.0)).unwrap();
+        book.add_order(Order::new(2, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Buy, 49800.0, 2.0)).unwrap();
+        book.add_order(Order::new(3, "bob".to_string(), "BTC/USD".to_string(), OrderSide::Sell, 50100.0, 1.0)).unwrap();
+        book.add_order(Order::new(4, "bob".to_string(), "BTC/USD".to_string(), OrderSide::Sell, 50200.0, 2.0)).unwrap();
         
         let bid_depth = book.get_bid_depth(2);
         let ask_depth = book.get_ask_depth(2);
@@ -432,4 +482,17 @@ mod tests {
         assert_eq!(bid_depth[0].0, 49900.0);
         assert_eq!(ask_depth[0].0, 50100.0);
     }
+
+    #[test]
+    fn test_rejects_invalid_orders() {
+        let mut book = OrderBook::new("BTC/USD".to_string());
+
+        let wrong_symbol = Order::new(1, "alice".to_string(), "ETH/USD".to_string(), OrderSide::Buy, 100.0, 1.0);
+        assert_eq!(book.add_order(wrong_symbol).unwrap_err().code(), 3001);
+        let bad_price = Order::new(2, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Buy, f64::NAN, 1.0);
+        assert_eq!(book.add_order(bad_price).unwrap_err(), OrderBookError::InvalidPrice);
+        let bad_quantity = Order::new(3, "alice".to_string(), "BTC/USD".to_string(), OrderSide::Sell, 100.0, 0.0);
+        assert_eq!(book.add_order(bad_quantity).unwrap_err(), OrderBookError::InvalidQuantity);
+        assert!(book.bids.is_empty() && book.asks.is_empty());
+    }
 }
@@ -26,8 +26,8 @@ impl Hash {
 
     pub fn combine(&self, other: &Hash) -> Hash {
         let mut hasher = Sha256::new();
-        hasher.update(&self.0);
-        hasher.update(&other.0);
+        hasher.update(self.0);
+        hasher.update(other.0);
         let result = hasher.finalize();
         let mut hash = [0u8; 32];
         hash.copy_from_slice(&result);
@@ -42,6 +42,10 @@ impl Hash {
     pub fn to_hex(&self) -> String {
         hex::encode(self.0)
     }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        hex::decode(hex).ok()?.try_into().ok().map(Self)
+    }
 }
 
 impl fmt::Display for Hash {
@@ -57,6 +61,12 @@ pub struct FastMerkleTree {
     root: Option<Hash>,
 }
 
+impl Default for FastMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FastMerkleTree {
     pub fn new() -> Self {
         Self {
@@ -125,12 +135,12 @@ impl FastMerkleTree {
 
         let mut proof = Vec::new();
         let mut current_index = index;
-        for level in &self.nodes {
+        for level in &self.nodes[..self.nodes.len() - 1] {
             if current_index >= level.len() {
                 break;
             }
 
-            let sibling_index = if current_index % 2 == 0 {
+            let sibling_index = if current_index.is_multiple_of(2) {
                 current_index + 1
             } else {
                 current_index - 1
@@ -152,19 +162,23 @@ impl FastMerkleTree {
     #[allow(dead_code)]
     pub fn verify_proof(&self, leaf: &Hash, proof: &[Hash], index: usize) -> bool {
         if let Some(root) = &self.root {
-            let calculated_root = self.calculate_root_from_proof(leaf, proof, index);
-            calculated_root == *root
+            Self::verify_against_root(root, leaf, proof, index)
         } else {
             false
         }
     }
 
-    #[allow(dead_code)]
-    fn calculate_root_from_proof(&self, leaf: &Hash, proof: &[Hash], mut index: usize) -> Hash {
+    /// Checks a proof against a root that was committed elsewhere, e.g. in a
+    /// block header, without rebuilding the tree.
+    pub fn verify_against_root(root: &Hash, leaf: &Hash, proof: &[Hash], index: usize) -> bool {
+        Self::calculate_root_from_proof(leaf, proof, index) == *root
+    }
+
+    fn calculate_root_from_proof(leaf: &Hash, proof: &[Hash], mut index: usize) -> Hash {
         let mut current_hash = leaf.clone();
 
         for proof_hash in proof {
-            current_hash = if index % 2 == 0 {
+            current_hash = if index.is_multiple_of(2) {
                 current_hash.combine(proof_hash)
             } else {
                 proof_hash.combine(&current_hash)
@@ -222,4 +236,16 @@ mod tests {
         let proof = tree.get_proof(0).unwrap();
         assert!(tree.verify_proof(&leaf, &proof, 0));
     }
+
+    #[test]
+    fn test_merkle_proof_odd_leaves() {
+        let data = vec!["leaf1".to_string(), "leaf2".to_string(), "leaf3".to_string()];
+        let tree = FastMerkleTree::from_data(data);
+        let root = tree.get_root().unwrap().clone();
+
+        let leaf = Hash::from_string("leaf3");
+        let proof = tree.get_proof(2).unwrap();
+        assert!(FastMerkleTree::verify_against_root(&root, &leaf, &proof, 2));
+        assert!(!FastMerkleTree::verify_against_root(&root, &leaf, &proof, 1));
+    }
 }
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::balances::{self, Pallet as BalancesPallet};
+use crate::merkle::Hash;
+use crate::runtime::{self, DefaultConfig};
+use crate::error::LedgerError;
+
+pub const MAX_SIGNATORIES: usize = 16;
+
+/// Chains using multisig must say how a derived address is written as an
+/// account id.
+pub trait Config: balances::Config {
+    fn account_from_hash(hash: &Hash) -> Self::AccountId;
+}
+
+/// `C` is the call being approved. The runtime plugs in its own `Call`, so a
+/// multisig can do anything a single signer can.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "C: Serialize", deserialize = "C: DeserializeOwned"))]
+pub enum Call<T: Config = DefaultConfig, C = runtime::Call> {
+    /// Opens a multisig operation with the caller as first approval.
+    AsMulti { other_signatories: Vec<T::AccountId>, threshold: u16, call: Box<C> },
+    Approve { other_signatories: Vec<T::AccountId>, threshold: u16, call_hash: Hash },
+    Cancel { other_signatories: Vec<T::AccountId>, threshold: u16, call_hash: Hash },
+}
+
+impl<T: Config, C> Call<T, C> {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::AsMulti { other_signatories, .. } => 30 + other_signatories.len() as u64,
+            Call::Approve { other_signatories, .. } | Call::Cancel { other_signatories, .. } => {
+                15 + other_signatories.len() as u64
+            }
+        }
+    }
+
+    pub fn other_signatories(&self) -> &Vec<T::AccountId> {
+        match self {
+            Call::AsMulti { other_signatories, .. }
+            | Call::Approve { other_signatories, .. }
+            | Call::Cancel { other_signatories, .. } => other_signatories,
+        }
+    }
+}
+
+/// An open operation waiting for approvals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "C: Serialize", deserialize = "C: DeserializeOwned"))]
+pub struct Multisig<T: Config = DefaultConfig, C = runtime::Call> {
+    pub call: C,
+    pub when: T::BlockNumber,
+    pub depositor: T::AccountId,
+    pub deposit: T::Balance,
+    pub approvals: Vec<T::AccountId>,
+}
+
+/// What the caller should do after a multisig call succeeds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Approval<T: Config = DefaultConfig, C = runtime::Call> {
+    Recorded,
+    /// The threshold is met. Dispatch `call` as `account`, then call
+    /// `complete` so the operation is closed only if the call succeeded.
+    Execute { account: T::AccountId, call_hash: Hash, call: C },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "C: Serialize", deserialize = "C: DeserializeOwned"))]
+pub struct Pallet<T: Config = DefaultConfig, C = runtime::Call> {
+    /// Open operations by multisig account and hex call hash.
+    pub multisigs: BTreeMap<T::AccountId, BTreeMap<String, Multisig<T, C>>>,
+    /// Reserved from whoever opens an operation until it closes.
+    pub deposit: T::Balance,
+}
+
+impl<T: Config, C: Clone + Serialize> Pallet<T, C> {
+
+    pub fn new(deposit: T::Balance) -> Self {
+
+        Self {
+            multisigs: BTreeMap::new(),
+            deposit,
+        }
+    }
+
+    /// Address controlled by `threshold` of `signatories`. Order and
+    /// duplicates do not matter.
+    pub fn multi_account_id(signatories: &[T::AccountId], threshold: u16) -> T::AccountId {
+
+        let mut sorted = signatories.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        T::account_from_hash(&Hash::from_string(&format!("multisig:{:?}:{}", sorted, threshold)))
+    }
+
+    pub fn call_hash(call: &C) -> Hash {
+
+        Hash::from_string(&serde_json::to_string(call).unwrap_or_default())
+    }
+
+    pub fn get(&self, account: &T::AccountId, call_hash: &Hash) -> Option<&Multisig<T, C>> {
+
+        self.multisigs.get(account).and_then(|ops| ops.get(&call_hash.to_hex()))
+    }
+
+    pub fn as_multi(&mut self, who: &T::AccountId, other_signatories: &[T::AccountId], threshold: u16, call: C, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> Result<Approval<T, C>, LedgerError> {
+
+        let account = Self::checked_account(who, other_signatories, threshold)?;
+        let call_hash = Self::call_hash(&call);
+        if threshold == 1 {
+            return Ok(Approval::Execute { account, call_hash, call });
+        }
+        if self.get(&account, &call_hash).is_some() {
+            return Err(LedgerError::MultisigAlreadyOpen);
+        }
+
+        balances.reserve(who, self.deposit)?;
+        self.multisigs.entry(account).or_default().insert(call_hash.to_hex(), Multisig {
+            call,
+            when: now,
+            depositor: who.clone(),
+            deposit: self.deposit,
+            approvals: vec![who.clone()],
+        });
+        Ok(Approval::Recorded)
+    }
+
+    /// Adds an approval. The approval that reaches the threshold is not
+    /// recorded; it is returned as `Execute` for the runtime to dispatch.
+    pub fn approve(&mut self, who: &T::AccountId, other_signatories: &[T::AccountId], threshold: u16, call_hash: &Hash) -> Result<Approval<T, C>, LedgerError> {
+
+        let account = Self::checked_account(who, other_signatories, threshold)?;
+        let op = self.multisigs
+            .get_mut(&account)
+            .and_then(|ops| ops.get_mut(&call_hash.to_hex()))
+            .ok_or(LedgerError::UnknownMultisig)?;
+        if op.approvals.contains(who) {
+            return Err(LedgerError::AlreadyApproved);
+        }
+
+        if op.approvals.len() + 1 >= threshold as usize {
+            return Ok(Approval::Execute { account, call_hash: call_hash.clone(), call: op.call.clone() });
+        }
+        op.approvals.push(who.clone());
+        Ok(Approval::Recorded)
+    }
+
+    /// Closes an executed operation and returns the deposit.
+    pub fn complete(&mut self, account: &T::AccountId, call_hash: &Hash, balances: &mut BalancesPallet<T>) {
+
+        if let Some(op) = self.remove(account, call_hash) {
+            balances.unreserve(&op.depositor, op.deposit);
+        }
+    }
+
+    /// Only whoever opened the operation may cancel it.
+    pub fn cancel(&mut self, who: &T::AccountId, other_signatories: &[T::AccountId], threshold: u16, call_hash: &Hash, balances: &mut BalancesPallet<T>) -> Result<(), LedgerError> {
+
+        let account = Self::checked_account(who, other_signatories, threshold)?;
+        let op = self.get(&account, call_hash).ok_or(LedgerError::UnknownMultisig)?;
+        if op.depositor != *who {
+            return Err(LedgerError::NotDepositor);
+        }
+        self.complete(&account, call_hash, balances);
+        Ok(())
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call<T, C>, balances: &mut BalancesPallet<T>, now: T::BlockNumber) -> Result<Approval<T, C>, LedgerError> {
+
+        match call {
+            Call::AsMulti { other_signatories, threshold, call } => {
+                self.as_multi(caller, other_signatories, *threshold, (**call).clone(), now, balances)
+            }
+            Call::Approve { other_signatories, threshold, call_hash } => {
+                self.approve(caller, other_signatories, *threshold, call_hash)
+            }
+            Call::Cancel { other_signatories, threshold, call_hash } => {
+                self.cancel(caller, other_signatories, *threshold, call_hash, balances).map(|_| Approval::Recorded)
+            }
+        }
+    }
+
+    fn checked_account(who: &T::AccountId, other_signatories: &[T::AccountId], threshold: u16) -> Result<T::AccountId, LedgerError> {
+
+        if other_signatories.contains(who) {
+            return Err(LedgerError::CallerInSignatories);
+        }
+        let mut signatories = other_signatories.to_vec();
+        signatories.push(who.clone());
+        signatories.sort();
+        signatories.dedup();
+        if signatories.len() != other_signatories.len() + 1 {
+            return Err(LedgerError::DuplicateSignatories);
+        }
+        if signatories.len() < 2 || signatories.len() > MAX_SIGNATORIES {
+            return Err(LedgerError::InvalidSignatoryCount);
+        }
+        if threshold == 0 || threshold as usize > signatories.len() {
+            return Err(LedgerError::InvalidThreshold);
+        }
+        Ok(Self::multi_account_id(&signatories, threshold))
+    }
+
+    fn remove(&mut self, account: &T::AccountId, call_hash: &Hash) -> Option<Multisig<T, C>> {
+
+        let ops = self.multisigs.get_mut(account)?;
+        let op = ops.remove(&call_hash.to_hex());
+        if ops.is_empty() {
+            self.multisigs.remove(account);
+        }
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system;
+
+    type TestPallet = Pallet<DefaultConfig, system::Call>;
+
+    fn remark() -> system::Call {
+        system::Call::Remark { remark: "pay the auditors".to_string() }
+    }
+
+    fn accounts() -> (String, String, String) {
+        ("alice".to_string(), "bob".to_string(), "charlie".to_string())
+    }
+
+    #[test]
+    fn test_account_id_ignores_order() {
+        let (alice, bob, charlie) = accounts();
+        let a = TestPallet::multi_account_id(&[alice.clone(), bob.clone(), charlie.clone()], 2);
+        let b = TestPallet::multi_account_id(&[charlie.clone(), alice.clone(), bob.clone()], 2);
+        assert_eq!(a, b);
+        assert_ne!(a, TestPallet::multi_account_id(&[alice, bob, charlie], 3));
+    }
+
+    #[test]
+    fn test_two_of_three_executes_on_second_approval() {
+        let (alice, bob, charlie) = accounts();
+        let mut balances = BalancesPallet::new();
+        balances.set_balance(&alice, 100);
+        let mut multisig = TestPallet::new(20);
+
+        let approval = multisig.as_multi(&alice, &[bob.clone(), charlie.clone()], 2, remark(), 1, &mut balances).unwrap();
+        assert_eq!(approval, Approval::Recorded);
+        assert_eq!(balances.reserved_balance(&alice), 20);
+
+        let call_hash = TestPallet::call_hash(&remark());
+        assert_eq!(multisig.approve(&alice, &[bob.clone(), charlie.clone()], 2, &call_hash), Err(LedgerError::AlreadyApproved));
+        match multisig.approve(&charlie, &[alice.clone(), bob.clone()], 2, &call_hash).unwrap() {
+            Approval::Execute { account, call, .. } => {
+                assert_eq!(account, TestPallet::multi_account_id(&[alice.clone(), bob, charlie], 2));
+                assert_eq!(call, remark());
+                multisig.complete(&account, &call_hash, &mut balances);
+            }
+            Approval::Recorded => panic!("threshold should be met"),
+        }
+        assert!(multisig.multisigs.is_empty());
+        assert_eq!(balances.reserved_balance(&alice), 0);
+    }
+
+    #[test]
+    fn test_cancel_only_by_depositor() {
+        let (alice, bob, charlie) = accounts();
+        let mut balances = BalancesPallet::new();
+        balances.set_balance(&alice, 100);
+        let mut multisig = TestPallet::new(20);
+        multisig.as_multi(&alice, &[bob.clone(), charlie.clone()], 3, remark(), 1, &mut balances).unwrap();
+        let call_hash = TestPallet::call_hash(&remark());
+
+        assert!(multisig.cancel(&bob, &[alice.clone(), charlie.clone()], 3, &call_hash, &mut balances).is_err());
+        multisig.cancel(&alice, &[bob, charlie], 3, &call_hash, &mut balances).unwrap();
+        assert!(multisig.multisigs.is_empty());
+        assert_eq!(balances.get_balance(&alice), 100);
+    }
+
+    #[test]
+    fn test_invalid_signatory_sets() {
+        let (alice, bob, _) = accounts();
+        let mut balances = BalancesPallet::new();
+        let mut multisig = TestPallet::new(20);
+
+        assert!(multisig.as_multi(&alice, &[alice.clone(), bob.clone()], 2, remark(), 1, &mut balances).is_err());
+        assert!(multisig.as_multi(&alice, &[bob.clone(), bob.clone()], 2, remark(), 1, &mut balances).is_err());
+        assert!(multisig.as_multi(&alice, &[bob], 3, remark(), 1, &mut balances).is_err());
+        assert!(multisig.as_multi(&alice, &[], 1, remark(), 1, &mut balances).is_err());
+    }
+}
@@ -47,6 +47,8 @@ impl OrderBookMarket {
     pub fn initialize_depth(&mut self, levels: usize, base_spread_bps: f64, quantity_per_level: f64) {
         let spread = self.fair_value * base_spread_bps / 10000.0;
         let mid_price = self.fair_value;
+        // Quotes that would fall to or below zero are rejected by the book
+        // and simply not placed.
         for i in 0..levels {
             let offset = spread * (1.0 + i as f64 * 0.5);
             let quantity = quantity_per_level * (0.8f64).powi(i as i32);
@@ -59,7 +61,7 @@ impl OrderBookMarket {
                 bid_price,
                 quantity,
             );
-            self.orderbook.add_order(bid_order);
+            let _ = self.orderbook.add_order(bid_order);
             let ask_price = mid_price + offset;
             let ask_order = Order::new(
                 self.next_order_id(),
@@ -69,7 +71,7 @@ impl OrderBookMarket {
                 ask_price,
                 quantity,
             );
-            self.orderbook.add_order(ask_order);
+            let _ = self.orderbook.add_order(ask_order);
         }
     }
     fn replenish_depth(&mut self, base_spread_bps: f64) {
@@ -91,7 +93,7 @@ impl OrderBookMarket {
                     new_bid_price,
                     1.0 + self.generate_random() * 2.0,
                 );
-                self.orderbook.add_order(bid_order);
+                let _ = self.orderbook.add_order(bid_order);
 
                 let ask_order = Order::new(
                     self.next_order_id(),
@@ -101,7 +103,7 @@ impl OrderBookMarket {
                     new_ask_price,
                     1.0 + self.generate_random() * 2.0,
                 );
-                self.orderbook.add_order(ask_order);
+                let _ = self.orderbook.add_order(ask_order);
             }
         } else {
 
@@ -126,8 +128,7 @@ impl OrderBookMarket {
             
             if let Some(trader_type) = trader_type {
                 let order = self.generate_order_for_trader_type(trader_type);
-                if let Some(order) = order {
-                    let trades = self.orderbook.add_order(order);
+                if let Some(Ok(trades)) = order.map(|order| self.orderbook.add_order(order)) {
                     if let Some(last_trade) = trades.last() {
                         self.last_trade_price = last_trade.price;
                     }
@@ -238,9 +239,7 @@ impl OrderBookMarket {
         self.current_time += 1;
         self.update_fair_value();
         self.replenish_depth(10.0);
-        let trades = self.generate_trader_orders();
-
-        trades
+        self.generate_trader_orders()
     }
     pub fn simulate_session(&mut self, num_bars: usize) -> Vec<MarketBar> {
         let mut bars = Vec::new();
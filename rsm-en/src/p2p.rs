@@ -0,0 +1,946 @@
+//! Node-to-node networking over TCP. Peers exchange newline-delimited JSON
+//! messages: a handshake with chain ID, genesis hash and best height, then
+//! gossip of transactions, blocks and peer addresses. Nodes follow the
+//! longest chain, catching up headers-first (see `sync`); a peer that sends
+//! invalid data collects ban score and is dropped once it reaches
+//! `BAN_THRESHOLD`.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::Blockchain;
+use crate::error::{ErrorCode, LedgerError};
+use crate::genesis::GenesisSpec;
+use crate::rps_mining::MiningError;
+use crate::sync::{HeaderSync, SyncProgress, SyncStatus, MAX_BODIES_PER_REQUEST, MAX_HEADERS_PER_MESSAGE, REQUEST_TIMEOUT};
+use crate::transaction::{Block, BlockHeader, Transaction};
+use crate::utxo::UtxoTransaction;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const BAN_THRESHOLD: u32 = 100;
+pub const MAX_BLOCKS_PER_MESSAGE: usize = 500;
+const MAX_MESSAGE_BYTES: u64 = 16 * 1024 * 1024;
+const MAX_SHARED_PEERS: usize = 100;
+const SEEN_CAPACITY: usize = 10_000;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const SYNC_TICK: Duration = Duration::from_millis(500);
+
+/// Ban score added per offence.
+const PENALTY_WRONG_CHAIN: u32 = BAN_THRESHOLD;
+const PENALTY_INVALID_BLOCK: u32 = 50;
+const PENALTY_MALFORMED: u32 = 20;
+const PENALTY_INVALID_TRANSACTION: u32 = 10;
+
+pub type SharedBlockchain = Arc<Mutex<Blockchain>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+    Hello {
+        version: u32,
+        node_id: u64,
+        chain_id: u64,
+        /// Hash of the genesis spec the node runs.
+        genesis_hash: String,
+        best_height: u32,
+        listen_port: u16,
+    },
+    /// Asks for the blocks after the first of `locator` (hex hashes, tip
+    /// first) that the receiver has.
+    GetBlocks { locator: Vec<String> },
+    Blocks { blocks: Vec<Block> },
+    /// Like `GetBlocks`, for headers only.
+    GetHeaders { locator: Vec<String> },
+    Headers { headers: Vec<BlockHeader> },
+    GetBodies { hashes: Vec<String> },
+    Bodies { blocks: Vec<Block> },
+    NewBlock { block: Block },
+    NewTransaction { transaction: Transaction },
+    NewUtxoTransaction { transaction: UtxoTransaction },
+    GetPeers,
+    Peers { addresses: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    Io(String),
+    InvalidAddress(String),
+    Banned(String),
+    AlreadyConnected(String),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(detail) => write!(f, "Network I/O failed: {}", detail),
+            NetworkError::InvalidAddress(address) => write!(f, "Invalid peer address '{}'", address),
+            NetworkError::Banned(address) => write!(f, "Peer {} is banned", address),
+            NetworkError::AlreadyConnected(address) => write!(f, "Already connected to {}", address),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl ErrorCode for NetworkError {
+    fn code(&self) -> u16 {
+        match self {
+            NetworkError::Io(_) => 11001,
+            NetworkError::InvalidAddress(_) => 11002,
+            NetworkError::Banned(_) => 11003,
+            NetworkError::AlreadyConnected(_) => 11004,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "network"
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            NetworkError::Io(_) => 503,
+            NetworkError::InvalidAddress(_) => 400,
+            NetworkError::Banned(_) => 403,
+            NetworkError::AlreadyConnected(_) => 409,
+        }
+    }
+}
+
+pub struct NodeConfig {
+    /// Address to accept peers on; port 0 picks a free one.
+    pub listen: String,
+    /// Peers to dial at startup.
+    pub bootstrap: Vec<String>,
+    pub max_peers: usize,
+    /// The spec the chain was built from, to rebuild state on a reorg.
+    pub genesis: GenesisSpec,
+    /// File to keep catch-up progress in. A sync interrupted by a restart
+    /// picks up from it, provided the chain it had applied was kept too.
+    pub sync_state: Option<PathBuf>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:0".to_string(),
+            bootstrap: Vec::new(),
+            max_peers: 8,
+            genesis: GenesisSpec::default(),
+            sync_state: None,
+        }
+    }
+}
+
+/// A connected peer as reported by `Node::peers`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerInfo {
+    pub address: String,
+    pub node_id: u64,
+    pub outbound: bool,
+    pub best_height: u32,
+    pub ban_score: u32,
+}
+
+struct Peer {
+    conn_id: u64,
+    address: String,
+    outbound: bool,
+    /// Node that opened the connection; of two connections between the
+    /// same nodes, both sides keep the one opened by the lower ID.
+    initiator: u64,
+    best_height: u32,
+    sender: Sender<Message>,
+    stream: TcpStream,
+}
+
+#[derive(Default)]
+struct PeerTable {
+    connected: BTreeMap<u64, Peer>,
+    known: BTreeSet<String>,
+    ban_scores: BTreeMap<String, u32>,
+}
+
+impl PeerTable {
+    fn is_banned(&self, address: &str) -> bool {
+        self.ban_scores.get(address).is_some_and(|score| *score >= BAN_THRESHOLD)
+    }
+
+    fn is_connected(&self, address: &str) -> bool {
+        self.connected.values().any(|peer| peer.address == address)
+    }
+}
+
+/// Recently relayed hashes, so gossip does not echo between peers.
+#[derive(Default)]
+struct SeenSet {
+    hashes: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenSet {
+    /// Records `hash`; false if it was already seen.
+    fn insert(&mut self, hash: String) -> bool {
+        if !self.hashes.insert(hash.clone()) {
+            return false;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// A catch-up in progress.
+struct Download {
+    sync: HeaderSync,
+    /// Peer last asked for headers and when.
+    header_request: Option<(u64, Instant)>,
+    /// Address of the peer whose headers were last accepted, blamed if
+    /// their bodies turn out not to apply.
+    header_source: Option<String>,
+}
+
+struct Shared {
+    node_id: u64,
+    local_addr: SocketAddr,
+    max_peers: usize,
+    genesis: GenesisSpec,
+    sync_state: Option<PathBuf>,
+    blockchain: SharedBlockchain,
+    peers: Mutex<PeerTable>,
+    seen: Mutex<SeenSet>,
+    download: Mutex<Option<Download>>,
+    next_conn: AtomicU64,
+}
+
+/// A running P2P node around a shared chain. Clones are handles to the same
+/// node.
+#[derive(Clone)]
+pub struct Node {
+    shared: Arc<Shared>,
+}
+
+impl Node {
+    /// Binds the listener, starts accepting peers and dials the bootstrap
+    /// peers. Bootstrap peers that cannot be reached are skipped. Blocks
+    /// saved by an interrupted sync are applied first.
+    pub fn start(blockchain: SharedBlockchain, config: NodeConfig) -> Result<Node, NetworkError> {
+        let listener = TcpListener::bind(&config.listen).map_err(|e| NetworkError::Io(e.to_string()))?;
+        let local_addr = listener.local_addr().map_err(|e| NetworkError::Io(e.to_string()))?;
+        let node = Node {
+            shared: Arc::new(Shared {
+                node_id: RandomState::new().build_hasher().finish(),
+                local_addr,
+                max_peers: config.max_peers,
+                genesis: config.genesis,
+                sync_state: config.sync_state,
+                blockchain,
+                peers: Mutex::new(PeerTable::default()),
+                seen: Mutex::new(SeenSet::default()),
+                download: Mutex::new(None),
+                next_conn: AtomicU64::new(0),
+            }),
+        };
+        node.resume_sync();
+
+        let acceptor = node.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let node = acceptor.clone();
+                thread::spawn(move || node.run_connection(stream, false));
+            }
+        });
+        let ticker = node.clone();
+        thread::spawn(move || loop {
+            thread::sleep(SYNC_TICK);
+            ticker.drive_sync();
+        });
+        for address in &config.bootstrap {
+            if let Err(e) = node.connect(address) {
+                println!("p2p: cannot reach bootstrap peer {}: {}", address, e);
+            }
+        }
+        Ok(node)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shared.local_addr
+    }
+
+    pub fn blockchain(&self) -> &SharedBlockchain {
+        &self.shared.blockchain
+    }
+
+    /// Dials `address`; the handshake runs in the background.
+    pub fn connect(&self, address: &str) -> Result<(), NetworkError> {
+        let socket_addr = address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| NetworkError::InvalidAddress(address.to_string()))?;
+        let key = socket_addr.to_string();
+        {
+            let peers = self.shared.peers.lock().unwrap();
+            if peers.is_banned(&key) {
+                return Err(NetworkError::Banned(key));
+            }
+            if peers.is_connected(&key) {
+                return Err(NetworkError::AlreadyConnected(key));
+            }
+        }
+        let stream = TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT).map_err(|e| NetworkError::Io(e.to_string()))?;
+        let node = self.clone();
+        thread::spawn(move || node.run_connection(stream, true));
+        Ok(())
+    }
+
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let peers = self.shared.peers.lock().unwrap();
+        peers.connected
+            .iter()
+            .map(|(node_id, peer)| PeerInfo {
+                address: peer.address.clone(),
+                node_id: *node_id,
+                outbound: peer.outbound,
+                best_height: peer.best_height,
+                ban_score: peers.ban_scores.get(&peer.address).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Addresses heard of through the peer lists, connected or not.
+    pub fn known_peers(&self) -> Vec<String> {
+        self.shared.peers.lock().unwrap().known.iter().cloned().collect()
+    }
+
+    pub fn ban_score(&self, address: &str) -> u32 {
+        self.shared.peers.lock().unwrap().ban_scores.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn is_banned(&self, address: &str) -> bool {
+        self.shared.peers.lock().unwrap().is_banned(address)
+    }
+
+    /// Progress of the catch-up under way, if any.
+    pub fn sync_status(&self) -> Option<SyncStatus> {
+        self.shared.download.lock().unwrap().as_ref().map(|download| download.sync.status())
+    }
+
+    /// Adds a transaction to the local pool and gossips it.
+    pub fn submit_transaction(&self, transaction: Transaction) -> Result<(), LedgerError> {
+        self.shared.blockchain.lock().unwrap().add_transaction(transaction.clone())?;
+        self.mark_seen(transaction.hash.to_hex());
+        self.broadcast(&Message::NewTransaction { transaction }, None);
+        Ok(())
+    }
+
+    pub fn submit_utxo_transaction(&self, transaction: UtxoTransaction) -> Result<(), LedgerError> {
+        self.shared.blockchain.lock().unwrap().add_utxo_transaction(transaction.clone())?;
+        self.mark_seen(transaction.hash.to_hex());
+        self.broadcast(&Message::NewUtxoTransaction { transaction }, None);
+        Ok(())
+    }
+
+    /// Mines the pending transactions and gossips the block.
+    pub fn mine(&self, author: String) -> Result<Block, MiningError> {
+        let block = self.shared.blockchain.lock().unwrap().mine_pending_transactions(author)?;
+        self.announce_block(&block);
+        Ok(block)
+    }
+
+    /// Gossips a block that was appended to the local chain.
+    pub fn announce_block(&self, block: &Block) {
+        self.mark_seen(block.hash.to_hex());
+        self.broadcast(&Message::NewBlock { block: block.clone() }, None);
+    }
+
+    fn mark_seen(&self, hash: String) -> bool {
+        self.shared.seen.lock().unwrap().insert(hash)
+    }
+
+    /// Sends `message` to every connected peer but `except`.
+    fn broadcast(&self, message: &Message, except: Option<u64>) {
+        let peers = self.shared.peers.lock().unwrap();
+        for (node_id, peer) in &peers.connected {
+            if Some(*node_id) != except {
+                let _ = peer.sender.send(message.clone());
+            }
+        }
+    }
+
+    fn send_to(&self, node_id: u64, message: Message) {
+        if let Some(peer) = self.shared.peers.lock().unwrap().connected.get(&node_id) {
+            let _ = peer.sender.send(message);
+        }
+    }
+
+    fn hello(&self) -> Message {
+        let blockchain = self.shared.blockchain.lock().unwrap();
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            node_id: self.shared.node_id,
+            chain_id: blockchain.chain_id,
+            genesis_hash: self.shared.genesis.hash().to_hex(),
+            best_height: blockchain.get_latest_block().index,
+            listen_port: self.shared.local_addr.port(),
+        }
+    }
+
+    fn locator(&self) -> Vec<String> {
+        let blockchain = self.shared.blockchain.lock().unwrap();
+        chain_locator(&blockchain)
+    }
+
+    /// Adds to `address`'s ban score and drops it once it is banned.
+    fn misbehaved(&self, address: &str, penalty: u32, reason: &str) {
+        let mut peers = self.shared.peers.lock().unwrap();
+        let score = peers.ban_scores.entry(address.to_string()).or_insert(0);
+        *score = score.saturating_add(penalty);
+        println!("p2p: {} misbehaved ({}), ban score {}", address, reason, score);
+        if *score >= BAN_THRESHOLD {
+            peers.known.remove(address);
+            for peer in peers.connected.values().filter(|peer| peer.address == address) {
+                let _ = peer.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    fn run_connection(&self, stream: TcpStream, outbound: bool) {
+        let Ok(remote) = stream.peer_addr() else { return };
+        let (Ok(write_half), Ok(control)) = (stream.try_clone(), stream.try_clone()) else { return };
+        let _ = stream.set_nodelay(true);
+        let (sender, receiver) = mpsc::channel::<Message>();
+        thread::spawn(move || write_messages(write_half, receiver));
+        let _ = sender.send(self.hello());
+
+        let mut reader = BufReader::new(stream);
+        let _ = reader.get_ref().set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        let Some(Ok(Message::Hello { version, node_id, chain_id, genesis_hash, best_height, listen_port })) =
+            read_message(&mut reader)
+        else {
+            return;
+        };
+        let address = SocketAddr::new(remote.ip(), listen_port).to_string();
+        let our_genesis = self.shared.genesis.hash().to_hex();
+        let our_chain_id = self.shared.blockchain.lock().unwrap().chain_id;
+        // Returning drops `sender`, which closes the connection once our
+        // handshake is written.
+        if node_id == self.shared.node_id {
+            return;
+        }
+        if version != PROTOCOL_VERSION || chain_id != our_chain_id || genesis_hash != our_genesis {
+            self.misbehaved(&address, PENALTY_WRONG_CHAIN, "handshake for another chain");
+            return;
+        }
+
+        let conn_id = self.shared.next_conn.fetch_add(1, Ordering::Relaxed);
+        let initiator = if outbound { self.shared.node_id } else { node_id };
+        {
+            let mut peers = self.shared.peers.lock().unwrap();
+            let full = peers.connected.len() >= self.shared.max_peers && !peers.connected.contains_key(&node_id);
+            let keep_existing = peers.connected.get(&node_id).is_some_and(|existing| existing.initiator <= initiator);
+            if peers.is_banned(&address) || full || keep_existing {
+                return;
+            }
+            let peer = Peer { conn_id, address: address.clone(), outbound, initiator, best_height, sender, stream: control };
+            if let Some(replaced) = peers.connected.insert(node_id, peer) {
+                let _ = replaced.stream.shutdown(Shutdown::Both);
+            }
+            peers.known.insert(address.clone());
+        }
+        let _ = reader.get_ref().set_read_timeout(None);
+
+        self.send_to(node_id, Message::GetPeers);
+        self.drive_sync();
+        while let Some(message) = read_message(&mut reader) {
+            match message {
+                Ok(message) => self.handle(node_id, &address, message),
+                Err(reason) => self.misbehaved(&address, PENALTY_MALFORMED, &reason),
+            }
+        }
+
+        {
+            let mut peers = self.shared.peers.lock().unwrap();
+            if peers.connected.get(&node_id).is_some_and(|peer| peer.conn_id == conn_id) {
+                if let Some(peer) = peers.connected.remove(&node_id) {
+                    let _ = peer.stream.shutdown(Shutdown::Both);
+                }
+            }
+        }
+        if let Some(download) = self.shared.download.lock().unwrap().as_mut() {
+            download.sync.peer_lost(node_id);
+            if download.header_request.is_some_and(|(peer, _)| peer == node_id) {
+                download.header_request = None;
+            }
+        }
+        self.drive_sync();
+    }
+
+    fn handle(&self, from: u64, address: &str, message: Message) {
+        match message {
+            Message::Hello { .. } => self.misbehaved(address, PENALTY_MALFORMED, "repeated handshake"),
+            Message::GetBlocks { locator } => {
+                let blocks = {
+                    let blockchain = self.shared.blockchain.lock().unwrap();
+                    let start = after_locator(&blockchain, &locator);
+                    blockchain.chain.iter().skip(start).take(MAX_BLOCKS_PER_MESSAGE).cloned().collect()
+                };
+                self.send_to(from, Message::Blocks { blocks });
+            }
+            Message::Blocks { blocks } => self.receive_blocks(from, address, blocks),
+            Message::GetHeaders { locator } => {
+                let headers = {
+                    let blockchain = self.shared.blockchain.lock().unwrap();
+                    let start = after_locator(&blockchain, &locator);
+                    blockchain.chain.iter().skip(start).take(MAX_HEADERS_PER_MESSAGE).map(Block::header).collect()
+                };
+                self.send_to(from, Message::Headers { headers });
+            }
+            Message::Headers { headers } => self.receive_headers(from, address, headers),
+            Message::GetBodies { hashes } => {
+                let wanted: HashSet<&String> = hashes.iter().take(MAX_BODIES_PER_REQUEST).collect();
+                let blocks = {
+                    let blockchain = self.shared.blockchain.lock().unwrap();
+                    blockchain.chain.iter().filter(|block| wanted.contains(&block.hash.to_hex())).cloned().collect()
+                };
+                self.send_to(from, Message::Bodies { blocks });
+            }
+            Message::Bodies { blocks } => self.receive_bodies(address, blocks),
+            Message::NewBlock { block } => self.receive_block(from, address, block),
+            Message::NewTransaction { transaction } => {
+                if !self.mark_seen(transaction.hash.to_hex()) {
+                    return;
+                }
+                let added = self.shared.blockchain.lock().unwrap().add_transaction(transaction.clone());
+                self.relay_transaction(from, address, added, Message::NewTransaction { transaction });
+            }
+            Message::NewUtxoTransaction { transaction } => {
+                if !self.mark_seen(transaction.hash.to_hex()) {
+                    return;
+                }
+                let added = self.shared.blockchain.lock().unwrap().add_utxo_transaction(transaction.clone());
+                self.relay_transaction(from, address, added, Message::NewUtxoTransaction { transaction });
+            }
+            Message::GetPeers => {
+                let addresses = {
+                    let peers = self.shared.peers.lock().unwrap();
+                    peers.known.iter().filter(|known| *known != address).take(MAX_SHARED_PEERS).cloned().collect()
+                };
+                self.send_to(from, Message::Peers { addresses });
+            }
+            Message::Peers { addresses } => self.discover(addresses),
+        }
+    }
+
+    /// Relays a transaction the pool took. One that can never be valid
+    /// counts against the sender; others (e.g. a nonce already used) may
+    /// just have raced a block.
+    fn relay_transaction(&self, from: u64, address: &str, added: Result<(), LedgerError>, message: Message) {
+        match added {
+            Ok(()) => self.broadcast(&message, Some(from)),
+            Err(LedgerError::InvalidTransaction | LedgerError::WrongChain) => {
+                self.misbehaved(address, PENALTY_INVALID_TRANSACTION, "invalid transaction");
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn receive_block(&self, from: u64, address: &str, block: Block) {
+        if !self.mark_seen(block.hash.to_hex()) {
+            return;
+        }
+        if let Some(peer) = self.shared.peers.lock().unwrap().connected.get_mut(&from) {
+            peer.best_height = peer.best_height.max(block.index);
+        }
+        let (index, extends_tip) = {
+            let blockchain = self.shared.blockchain.lock().unwrap();
+            let tip = blockchain.get_latest_block();
+            (tip.index, block.index == tip.index + 1 && block.previous_hash == tip.hash)
+        };
+        // A catch-up under way gets there by itself.
+        let syncing = self.shared.download.lock().unwrap().is_some();
+        if extends_tip && !syncing {
+            let imported = self.shared.blockchain.lock().unwrap().import_block(block.clone());
+            match imported {
+                Ok(()) => self.broadcast(&Message::NewBlock { block }, Some(from)),
+                Err(e) => self.misbehaved(address, PENALTY_INVALID_BLOCK, &e.to_string()),
+            }
+        } else if block.index == index + 1 && !syncing {
+            // A competing block at our next height: fetch its fork.
+            self.send_to(from, Message::GetBlocks { locator: self.locator() });
+        } else if block.index > index {
+            self.drive_sync();
+        }
+    }
+
+    /// Appends a batch that extends the tip, or switches to it if it forks
+    /// off lower and ends higher. Asks for more while batches come full.
+    fn receive_blocks(&self, from: u64, address: &str, blocks: Vec<Block>) {
+        let Some(last) = blocks.last().cloned() else { return };
+        let full = blocks.len() == MAX_BLOCKS_PER_MESSAGE;
+        let mut download = self.shared.download.lock().unwrap();
+        let result = {
+            let mut blockchain = self.shared.blockchain.lock().unwrap();
+            if blocks[0].previous_hash == blockchain.get_latest_block().hash {
+                blocks.into_iter().try_for_each(|block| blockchain.import_block(block)).map(|()| true)
+            } else {
+                let switched = blockchain.reorganize(&self.shared.genesis, blocks);
+                // A catch-up started on the old branch no longer applies.
+                if switched == Ok(true) {
+                    *download = None;
+                }
+                switched
+            }
+        };
+        drop(download);
+        match result {
+            Ok(true) => {
+                self.mark_seen(last.hash.to_hex());
+                self.broadcast(&Message::NewBlock { block: last }, Some(from));
+                if full {
+                    self.send_to(from, Message::GetBlocks { locator: self.locator() });
+                }
+            }
+            Ok(false) => {}
+            Err(e) => self.misbehaved(address, PENALTY_INVALID_BLOCK, &e.to_string()),
+        }
+    }
+
+    /// Takes up a sync saved by an earlier run, applying the blocks it had
+    /// already downloaded.
+    fn resume_sync(&self) {
+        let Some(progress) = self.shared.sync_state.as_ref().and_then(SyncProgress::load) else { return };
+        let mut download = self.shared.download.lock().unwrap();
+        let mut blockchain = self.shared.blockchain.lock().unwrap();
+        let mut sync = HeaderSync::resume(&blockchain, progress);
+        match sync.apply_ready(&mut blockchain) {
+            Ok(applied) => {
+                println!("p2p: resumed sync, {} saved blocks applied up to #{}", applied, sync.status().applied_height);
+                *download = Some(Download { sync, header_request: None, header_source: None });
+            }
+            Err(e) => println!("p2p: discarded saved sync: {}", e),
+        }
+    }
+
+    /// Moves the catch-up along: starts one when a peer is ahead, asks for
+    /// more headers and for bodies from every peer that has them, and ends
+    /// it once all is applied. Called on each event and on a timer, so
+    /// requests that went unanswered are sent again.
+    fn drive_sync(&self) {
+        let peers: Vec<(u64, u32)> = {
+            let peers = self.shared.peers.lock().unwrap();
+            peers.connected.iter().map(|(node_id, peer)| (*node_id, peer.best_height)).collect()
+        };
+        let now = Instant::now();
+        let mut requests = Vec::new();
+        let mut finished = None;
+        {
+            let mut download = self.shared.download.lock().unwrap();
+            let blockchain = self.shared.blockchain.lock().unwrap();
+            let tip = blockchain.get_latest_block().index;
+            if download.is_none() {
+                if !peers.iter().any(|(_, height)| *height > tip) {
+                    return;
+                }
+                *download = Some(Download { sync: HeaderSync::new(&blockchain), header_request: None, header_source: None });
+            }
+            let Some(state) = download.as_mut() else { return };
+
+            let (best_height, best_hash) = state.sync.best();
+            let asking = state.header_request.is_some_and(|(_, asked)| now.duration_since(asked) < REQUEST_TIMEOUT);
+            let ahead = peers.iter().filter(|(_, height)| *height > best_height).max_by_key(|(_, height)| *height);
+            match ahead {
+                Some((node_id, _)) if !asking && state.sync.wants_headers() => {
+                    let mut locator = vec![best_hash.to_hex()];
+                    locator.extend(chain_locator(&blockchain));
+                    requests.push((*node_id, Message::GetHeaders { locator }));
+                    state.header_request = Some((*node_id, now));
+                }
+                Some(_) => {}
+                None if state.sync.is_complete() => finished = Some(tip),
+                None => {}
+            }
+            for (node_id, height) in &peers {
+                let hashes = state.sync.next_requests(*node_id, *height, now);
+                if !hashes.is_empty() {
+                    let hashes = hashes.iter().map(|hash| hash.to_hex()).collect();
+                    requests.push((*node_id, Message::GetBodies { hashes }));
+                }
+            }
+            if finished.is_some() {
+                *download = None;
+                if let Some(path) = &self.shared.sync_state {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+
+        for (node_id, message) in requests {
+            self.send_to(node_id, message);
+        }
+        if let Some(height) = finished {
+            println!("p2p: sync complete at #{}", height);
+        }
+    }
+
+    /// Adds headers that continue the catch-up. A batch that forks off
+    /// below our tip is fetched as blocks instead, for a reorg.
+    fn receive_headers(&self, from: u64, address: &str, mut headers: Vec<BlockHeader>) {
+        let mut full = headers.len() == MAX_HEADERS_PER_MESSAGE;
+        let outcome = {
+            let mut download = self.shared.download.lock().unwrap();
+            let Some(state) = download.as_mut() else { return };
+            if state.header_request.is_some_and(|(peer, _)| peer == from) {
+                state.header_request = None;
+            }
+            let best = state.sync.best().0;
+            headers.retain(|header| header.index > best);
+            match headers.first() {
+                None => Ok(Some(best)),
+                Some(first) if state.sync.extends(first) => {
+                    let offered = headers.len();
+                    // A batch the sync cut short still has more behind it.
+                    let added = state.sync.add_headers(headers).map(|added| {
+                        full |= added < offered;
+                        Some(state.sync.best().0)
+                    });
+                    if added.is_ok() {
+                        state.header_source = Some(address.to_string());
+                    }
+                    added
+                }
+                Some(_) => Ok(None),
+            }
+        };
+        match outcome {
+            Ok(Some(best)) => {
+                // A short batch is all the peer has, whatever it claimed.
+                if !full {
+                    if let Some(peer) = self.shared.peers.lock().unwrap().connected.get_mut(&from) {
+                        peer.best_height = peer.best_height.min(best);
+                    }
+                }
+                self.drive_sync();
+            }
+            Ok(None) => self.send_to(from, Message::GetBlocks { locator: self.locator() }),
+            Err(e) => self.misbehaved(address, PENALTY_INVALID_BLOCK, &e.to_string()),
+        }
+    }
+
+    /// Stores downloaded bodies and applies those that are next in line,
+    /// saving progress and reporting it.
+    fn receive_bodies(&self, address: &str, blocks: Vec<Block>) {
+        let blame = {
+            let mut download = self.shared.download.lock().unwrap();
+            let Some(state) = download.as_mut() else { return };
+            match state.sync.add_bodies(blocks) {
+                Err(e) => Some((address.to_string(), e)),
+                Ok(_) => {
+                    let applied = {
+                        let mut blockchain = self.shared.blockchain.lock().unwrap();
+                        state.sync.apply_ready(&mut blockchain)
+                    };
+                    match applied {
+                        Ok(applied) => {
+                            if applied > 0 {
+                                let status = state.sync.status();
+                                println!(
+                                    "p2p: synced to #{} of #{} ({}%), {} bodies from {}",
+                                    status.applied_height, status.header_height, status.percent, applied, address
+                                );
+                            }
+                            if let Some(path) = &self.shared.sync_state {
+                                let _ = state.sync.progress().save(path);
+                            }
+                            None
+                        }
+                        Err(e) => {
+                            let source = state.header_source.clone().unwrap_or_else(|| address.to_string());
+                            *download = None;
+                            Some((source, e))
+                        }
+                    }
+                }
+            }
+        };
+        if let Some((culprit, e)) = blame {
+            self.misbehaved(&culprit, PENALTY_INVALID_BLOCK, &e.to_string());
+        }
+        self.drive_sync();
+    }
+
+    /// Remembers new addresses and dials them while there is room.
+    fn discover(&self, addresses: Vec<String>) {
+        let own = self.shared.local_addr.to_string();
+        let to_dial: Vec<String> = {
+            let mut peers = self.shared.peers.lock().unwrap();
+            let room = self.shared.max_peers.saturating_sub(peers.connected.len());
+            let fresh: Vec<String> = addresses
+                .into_iter()
+                .take(MAX_SHARED_PEERS)
+                .filter(|address| address.parse::<SocketAddr>().is_ok() && *address != own)
+                .filter(|address| !peers.is_banned(address) && !peers.is_connected(address))
+                .collect();
+            peers.known.extend(fresh.iter().cloned());
+            fresh.into_iter().take(room).collect()
+        };
+        for address in to_dial {
+            let _ = self.connect(&address);
+        }
+    }
+}
+
+/// Position in `blockchain` right after the first locator hash it has, or
+/// after the first block if it has none.
+pub(crate) fn after_locator(blockchain: &Blockchain, locator: &[String]) -> usize {
+    locator
+        .iter()
+        .find_map(|hash| blockchain.chain.iter().position(|block| block.hash.to_hex() == *hash))
+        .map_or(1, |position| position + 1)
+}
+
+/// Hashes of `blockchain` from the tip back, thinning out exponentially,
+/// ending at the first block.
+pub(crate) fn chain_locator(blockchain: &Blockchain) -> Vec<String> {
+    let mut locator = Vec::new();
+    let mut position = blockchain.chain.len() - 1;
+    let mut step = 1;
+    loop {
+        locator.push(blockchain.chain[position].hash.to_hex());
+        if position == 0 {
+            return locator;
+        }
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+        position = position.saturating_sub(step);
+    }
+}
+
+fn write_messages(mut stream: TcpStream, receiver: mpsc::Receiver<Message>) {
+    for message in receiver {
+        let mut line = serde_json::to_string(&message).expect("messages are serializable");
+        line.push('\n');
+        if stream.write_all(line.as_bytes()).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Reads one message line. `None` once the connection is closed;
+/// `Some(Err)` for a line that does not decode.
+fn read_message(reader: &mut BufReader<TcpStream>) -> Option<Result<Message, String>> {
+    let mut line = String::new();
+    match reader.by_ref().take(MAX_MESSAGE_BYTES).read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) if !line.ends_with('\n') => None,
+        Ok(_) => Some(serde_json::from_str(&line).map_err(|e| format!("malformed message: {}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn start_node(bootstrap: Vec<String>) -> Node {
+        let blockchain = Arc::new(Mutex::new(Blockchain::new()));
+        Node::start(blockchain, NodeConfig { bootstrap, ..Default::default() }).unwrap()
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn tip(node: &Node) -> String {
+        node.blockchain().lock().unwrap().get_latest_block().hash.to_hex()
+    }
+
+    #[test]
+    fn test_nodes_converge_through_gossip() {
+        let a = start_node(Vec::new());
+        let b = start_node(vec![a.local_addr().to_string()]);
+        let c = start_node(vec![b.local_addr().to_string()]);
+        // c learns about a from b's peer list.
+        wait_until(|| c.peers().len() == 2 && a.peers().len() == 2);
+
+        a.mine("alice".to_string()).unwrap();
+        wait_until(|| tip(&b) == tip(&a) && tip(&c) == tip(&a));
+
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 10, 1);
+        c.submit_transaction(tx).unwrap();
+        wait_until(|| a.blockchain().lock().unwrap().pending_transactions.len() == 1);
+        a.mine("alice".to_string()).unwrap();
+        wait_until(|| tip(&b) == tip(&a) && tip(&c) == tip(&a));
+        assert_eq!(c.blockchain().lock().unwrap().get_balance(&"bob".to_string()), 510);
+    }
+
+    #[test]
+    fn test_late_node_follows_longest_chain() {
+        let a = start_node(Vec::new());
+        let b = start_node(Vec::new());
+        for _ in 0..3 {
+            a.mine("alice".to_string()).unwrap();
+        }
+        b.mine("bob".to_string()).unwrap();
+
+        b.connect(&a.local_addr().to_string()).unwrap();
+        wait_until(|| tip(&b) == tip(&a));
+        assert_eq!(b.blockchain().lock().unwrap().get_chain_length(), 4);
+        assert_eq!(b.blockchain().lock().unwrap().get_balance(&"bob".to_string()), 500);
+    }
+
+    #[test]
+    fn test_new_node_downloads_headers_first_from_several_peers() {
+        let a = start_node(Vec::new());
+        for _ in 0..40 {
+            a.mine("alice".to_string()).unwrap();
+        }
+        let b = start_node(vec![a.local_addr().to_string()]);
+        wait_until(|| tip(&b) == tip(&a));
+
+        let path = std::env::temp_dir().join(format!("phlop-ibd-{}.json", std::process::id()));
+        let blockchain = Arc::new(Mutex::new(Blockchain::new()));
+        let bootstrap = vec![a.local_addr().to_string(), b.local_addr().to_string()];
+        let config = NodeConfig { bootstrap, sync_state: Some(path.clone()), ..Default::default() };
+        let c = Node::start(blockchain, config).unwrap();
+        wait_until(|| tip(&c) == tip(&a) && c.sync_status().is_none());
+        assert_eq!(c.blockchain().lock().unwrap().get_balance(&"alice".to_string()), 1000 + 40 * 100);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_peer_on_other_chain_is_banned() {
+        let a = start_node(Vec::new());
+        let spec = GenesisSpec { chain_id: 7, ..Default::default() };
+        let other = Arc::new(Mutex::new(Blockchain::from_genesis(&spec).unwrap()));
+        let b = Node::start(other, NodeConfig { genesis: spec, ..Default::default() }).unwrap();
+
+        b.connect(&a.local_addr().to_string()).unwrap();
+        let (a_address, b_address) = (a.local_addr().to_string(), b.local_addr().to_string());
+        wait_until(|| a.is_banned(&b_address) && b.is_banned(&a_address));
+        assert!(a.peers().is_empty());
+        assert_eq!(b.connect(&a_address), Err(NetworkError::Banned(a_address.clone())));
+    }
+}
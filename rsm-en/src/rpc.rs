@@ -0,0 +1,385 @@
+//! JSON-RPC 2.0 over the node's query methods. A body holds one request or
+//! a batch of them; notifications (requests without an `id`) run but get
+//! no reply. Params may be positional or named. Hashes are hex strings and
+//! balances decimal strings, since they do not fit a JSON number.
+
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::blockchain::Blockchain;
+use crate::error::{ErrorCode, RequestError};
+use crate::genesis::LedgerMode;
+use crate::merkle::Hash;
+use crate::p2p::Node;
+use crate::transaction::{Block, BlockHeader, Transaction};
+use crate::utxo::UtxoTransaction;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// A module rejected the call. `data` carries its error body, so clients
+/// can branch on the same codes as the HTTP API.
+pub const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into(), data: None }
+    }
+
+    fn invalid_params(detail: impl Into<String>) -> Self {
+        RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", detail.into()))
+    }
+
+    fn invalid_request() -> Self {
+        RpcError::new(INVALID_REQUEST, "Invalid Request")
+    }
+
+    fn server<E: ErrorCode>(error: &E) -> Self {
+        RpcError {
+            code: SERVER_ERROR,
+            message: error.to_string(),
+            data: Some(json!(error.to_body().error)),
+        }
+    }
+}
+
+/// Answers the JSON-RPC body `body`, or returns `None` when there is
+/// nothing to send back because it held only notifications. Transactions
+/// are gossiped through `network` when the node has one.
+pub fn handle(blockchain: &Mutex<Blockchain>, network: Option<&Node>, body: &str) -> Option<String> {
+    let request = match serde_json::from_str::<Value>(body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, format!("Parse error: {}", e));
+            return Some(response(Value::Null, Err(error)).to_string());
+        }
+    };
+    match request {
+        Value::Array(calls) if calls.is_empty() => Some(response(Value::Null, Err(RpcError::invalid_request())).to_string()),
+        Value::Array(calls) => {
+            let replies: Vec<Value> = calls.into_iter().filter_map(|call| handle_call(blockchain, network, call)).collect();
+            (!replies.is_empty()).then(|| Value::Array(replies).to_string())
+        }
+        call => handle_call(blockchain, network, call).map(|reply| reply.to_string()),
+    }
+}
+
+fn handle_call(blockchain: &Mutex<Blockchain>, network: Option<&Node>, call: Value) -> Option<Value> {
+    let Value::Object(mut fields) = call else {
+        return Some(response(Value::Null, Err(RpcError::invalid_request())));
+    };
+    let id = fields.remove("id");
+    if !matches!(id, None | Some(Value::Null | Value::String(_) | Value::Number(_))) {
+        return Some(response(Value::Null, Err(RpcError::invalid_request())));
+    }
+    let params = fields.remove("params").unwrap_or(Value::Null);
+    let result = match (fields.remove("jsonrpc"), fields.remove("method")) {
+        (Some(Value::String(version)), Some(Value::String(method)))
+            if version == "2.0" && matches!(params, Value::Null | Value::Array(_) | Value::Object(_)) =>
+        {
+            dispatch(blockchain, network, &method, &Params(params))
+        }
+        _ => Err(RpcError::invalid_request()),
+    };
+    id.map(|id| response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => json!({ "jsonrpc": "2.0", "error": error, "id": id }),
+    }
+}
+
+/// A request's params, by position or by name.
+struct Params(Value);
+
+impl Params {
+    fn get<T: DeserializeOwned>(&self, position: usize, name: &str) -> Result<T, RpcError> {
+        let value = match &self.0 {
+            Value::Array(values) => values.get(position),
+            Value::Object(values) => values.get(name),
+            _ => None,
+        };
+        let value = value.ok_or_else(|| RpcError::invalid_params(format!("missing {}", name)))?;
+        serde_json::from_value(value.clone()).map_err(|e| RpcError::invalid_params(format!("{}: {}", name, e)))
+    }
+
+    /// Like `get`, but a missing or null param is `None`.
+    fn optional<T: DeserializeOwned>(&self, position: usize, name: &str) -> Result<Option<T>, RpcError> {
+        let present = match &self.0 {
+            Value::Array(values) => values.get(position),
+            Value::Object(values) => values.get(name),
+            _ => None,
+        };
+        match present {
+            None | Some(Value::Null) => Ok(None),
+            Some(_) => self.get(position, name).map(Some),
+        }
+    }
+
+    fn hash(&self, position: usize, name: &str) -> Result<Hash, RpcError> {
+        let hex: String = self.get(position, name)?;
+        Hash::from_hex(&hex).ok_or_else(|| RpcError::invalid_params(format!("{} must be a 32-byte hex hash", name)))
+    }
+}
+
+fn dispatch(blockchain: &Mutex<Blockchain>, network: Option<&Node>, method: &str, params: &Params) -> Result<Value, RpcError> {
+    match method {
+        "chain_getBlock" => {
+            let blockchain = blockchain.lock().unwrap();
+            let block = match params.get::<Value>(0, "block")? {
+                Value::Number(number) => {
+                    let index = number.as_u64().and_then(|index| u32::try_from(index).ok());
+                    let index = index.ok_or_else(|| RpcError::invalid_params("block number out of range"))?;
+                    blockchain.get_block_by_index(index)
+                }
+                Value::String(_) => blockchain.get_block_by_hash(&params.hash(0, "block")?),
+                _ => return Err(RpcError::invalid_params("block must be a number or a hash")),
+            };
+            block.map_or(Ok(Value::Null), block_view)
+        }
+        "chain_getHead" => Ok(header_view(&blockchain.lock().unwrap().get_latest_block().header())),
+        "chain_getChainId" => Ok(json!(blockchain.lock().unwrap().chain_id)),
+        "state_getBalance" => {
+            let account: String = params.get(0, "account")?;
+            let balance = blockchain.lock().unwrap().account_balance(&account);
+            Ok(json!(balance.to_string()))
+        }
+        "state_getNonce" => {
+            let account: String = params.get(0, "account")?;
+            Ok(json!(blockchain.lock().unwrap().next_nonce(&account)))
+        }
+        "state_getVestingInfo" => {
+            let account: String = params.get(0, "account")?;
+            let at: Option<u32> = params.optional(1, "at")?;
+            let info = blockchain.lock().unwrap().get_vesting_info(&account, at);
+            Ok(json!({ "at": info.at, "locked": info.locked.to_string(), "vested": info.vested.to_string() }))
+        }
+        "state_getTransactionHistory" => {
+            let account: String = params.get(0, "account")?;
+            let blockchain = blockchain.lock().unwrap();
+            serde_json::to_value(blockchain.get_transaction_history(&account))
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, format!("Internal error: {}", e)))
+        }
+        "author_submitTransaction" => {
+            let ledger_mode = blockchain.lock().unwrap().ledger_mode;
+            let (hash, queued) = match ledger_mode {
+                LedgerMode::Account => {
+                    let transaction: Transaction = params.get(0, "transaction")?;
+                    let hash = transaction.hash.to_hex();
+                    let queued = match network {
+                        Some(node) => node.submit_transaction(transaction),
+                        None => blockchain.lock().unwrap().add_transaction(transaction),
+                    };
+                    (hash, queued)
+                }
+                LedgerMode::Utxo => {
+                    let transaction: UtxoTransaction = params.get(0, "transaction")?;
+                    let hash = transaction.hash.to_hex();
+                    let queued = match network {
+                        Some(node) => node.submit_utxo_transaction(transaction),
+                        None => blockchain.lock().unwrap().add_utxo_transaction(transaction),
+                    };
+                    (hash, queued)
+                }
+            };
+            queued.map(|()| json!(hash)).map_err(|e| RpcError::server(&e))
+        }
+        "chain_getTransactionProof" => {
+            let tx_hash = params.hash(0, "hash")?;
+            let blockchain = blockchain.lock().unwrap();
+            let Some((proof, tx_index, block_index)) = blockchain.get_transaction_proof(&tx_hash) else {
+                return Ok(Value::Null);
+            };
+            let block = blockchain
+                .get_block_by_index(block_index)
+                .ok_or_else(|| RpcError::server(&RequestError::NotFound("Block".to_string())))?;
+            Ok(json!({
+                "block_index": block_index,
+                "block_hash": block.hash.to_hex(),
+                "merkle_root": block.merkle_root.to_hex(),
+                "tx_index": tx_index,
+                "proof": proof.iter().map(Hash::to_hex).collect::<Vec<_>>(),
+            }))
+        }
+        "mining_getDifficulty" => {
+            let blockchain = blockchain.lock().unwrap();
+            let info = blockchain.get_rps_difficulty_info();
+            let mut distribution: Vec<(u32, u32)> = info.win_distribution.iter().map(|(&wins, &players)| (wins, players)).collect();
+            distribution.sort_unstable();
+            Ok(json!({
+                "block_number": info.block_number,
+                "difficulty": blockchain.difficulty,
+                "score": info.difficulty_score(),
+                "total_players": info.total_players,
+                "total_required_wins": info.total_required_wins,
+                "win_distribution": distribution
+                    .into_iter()
+                    .map(|(wins, players)| json!({ "wins": wins, "players": players }))
+                    .collect::<Vec<_>>(),
+            }))
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+fn header_view(header: &BlockHeader) -> Value {
+    json!({
+        "index": header.index,
+        "hash": header.hash.to_hex(),
+        "previous_hash": header.previous_hash.to_hex(),
+        "timestamp": header.timestamp,
+        "merkle_root": header.merkle_root.to_hex(),
+        "snapshot_hash": header.snapshot_hash.as_ref().map(Hash::to_hex),
+        "seal": header.rps_mining_result.as_ref().map(|seal| json!({
+            "rounds": seal.rounds,
+            "total_games": seal.total_games,
+            "player_count": seal.player_count,
+            "total_wins": seal.total_wins,
+            "outcomes_root": seal.outcomes_root.to_hex(),
+            "final_seed": seal.final_seed,
+        })),
+    })
+}
+
+/// The header plus the block's transactions, in the form
+/// `author_submitTransaction` takes.
+fn block_view(block: &Block) -> Result<Value, RpcError> {
+    let mut view = header_view(&block.header());
+    let internal = |e: serde_json::Error| RpcError::new(INTERNAL_ERROR, format!("Internal error: {}", e));
+    view["transactions"] = serde_json::to_value(&block.transactions).map_err(internal)?;
+    if let Some(body) = &block.utxo {
+        view["utxo_transactions"] = serde_json::to_value(&body.transactions).map_err(internal)?;
+    }
+    Ok(view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(blockchain: &Mutex<Blockchain>, request: Value) -> Value {
+        serde_json::from_str(&handle(blockchain, None, &request.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_queries_by_number_and_hash() {
+        let blockchain = Mutex::new(Blockchain::new());
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 100, 1);
+        blockchain.lock().unwrap().add_transaction(tx.clone()).unwrap();
+        let block = blockchain.lock().unwrap().mine_pending_transactions("miner".to_string()).unwrap();
+
+        let head = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_getHead", "id": 1 }));
+        assert_eq!(head["id"], 1);
+        assert_eq!(head["result"]["hash"], block.hash.to_hex());
+
+        let by_number = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": [1], "id": 2 }));
+        let by_hash = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": { "block": block.hash.to_hex() }, "id": 3 }));
+        assert_eq!(by_number["result"], by_hash["result"]);
+        let listed: Vec<Transaction> = serde_json::from_value(by_number["result"]["transactions"].clone()).unwrap();
+        assert_eq!(listed, block.transactions);
+
+        let proof = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_getTransactionProof", "params": [tx.hash.to_hex()], "id": 4 }));
+        assert_eq!(proof["result"]["block_index"], 1);
+        let hashes: Vec<Hash> = proof["result"]["proof"].as_array().unwrap().iter().map(|hash| Hash::from_hex(hash.as_str().unwrap()).unwrap()).collect();
+        let tx_index = proof["result"]["tx_index"].as_u64().unwrap() as usize;
+        assert!(blockchain.lock().unwrap().verify_transaction_proof(&tx, &hashes, tx_index, 1));
+
+        let history = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "state_getTransactionHistory", "params": ["bob"], "id": 5 }));
+        assert_eq!(history["result"][0]["hash"], json!(tx.hash));
+
+        let missing = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": [99], "id": 5 }));
+        assert_eq!(missing["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_batch_submits_and_skips_notifications() {
+        let blockchain = Mutex::new(Blockchain::new());
+        let balance = blockchain.lock().unwrap().get_balance(&"alice".to_string());
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 100, 1);
+        let replies = call(&blockchain, json!([
+            { "jsonrpc": "2.0", "method": "author_submitTransaction", "params": [tx], "id": "submit" },
+            { "jsonrpc": "2.0", "method": "state_getNonce", "params": ["alice"] },
+            { "jsonrpc": "2.0", "method": "state_getNonce", "params": ["alice"], "id": "nonce" },
+            { "jsonrpc": "2.0", "method": "state_getBalance", "params": { "account": "alice" }, "id": "balance" },
+        ]));
+
+        let replies = replies.as_array().unwrap();
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["result"], tx.hash.to_hex());
+        assert_eq!(replies[1]["result"], 2);
+        assert_eq!(replies[2]["result"], balance.to_string());
+
+        let only_notifications = json!([{ "jsonrpc": "2.0", "method": "chain_getHead" }]).to_string();
+        assert_eq!(handle(&blockchain, None, &only_notifications), None);
+    }
+
+    #[test]
+    fn test_vesting_info() {
+        let mut spec = crate::genesis::GenesisSpec::default();
+        spec.vesting.insert("alice".to_string(), vec![crate::vesting::VestingSchedule::Linear { locked: 600, per_block: 100, starting_block: 0 }]);
+        let blockchain = Mutex::new(Blockchain::from_genesis(&spec).unwrap());
+
+        let now = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "state_getVestingInfo", "params": ["alice"], "id": 1 }));
+        assert_eq!(now["result"], json!({ "at": 0, "locked": "600", "vested": "0" }));
+        let later = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "state_getVestingInfo", "params": { "account": "alice", "at": 4 }, "id": 2 }));
+        assert_eq!(later["result"], json!({ "at": 4, "locked": "200", "vested": "400" }));
+    }
+
+    #[test]
+    fn test_standard_error_codes() {
+        let blockchain = Mutex::new(Blockchain::new());
+        let code = |reply: &Value| reply["error"]["code"].as_i64().unwrap();
+
+        let parse = serde_json::from_str::<Value>(&handle(&blockchain, None, "{").unwrap()).unwrap();
+        assert_eq!(code(&parse), PARSE_ERROR);
+        assert_eq!(parse["id"], Value::Null);
+        assert_eq!(code(&call(&blockchain, json!([]))), INVALID_REQUEST);
+        assert_eq!(code(&call(&blockchain, json!({ "method": "chain_getHead", "id": 1 }))), INVALID_REQUEST);
+        assert_eq!(code(&call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_nope", "id": 1 }))), METHOD_NOT_FOUND);
+        assert_eq!(code(&call(&blockchain, json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": ["zz"], "id": 1 }))), INVALID_PARAMS);
+
+        let overdraft = Transaction::new("alice".to_string(), "bob".to_string(), u128::from(u64::MAX), 1);
+        let rejected = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "author_submitTransaction", "params": [overdraft], "id": 1 }));
+        assert_eq!(code(&rejected), SERVER_ERROR);
+        assert_eq!(rejected["error"]["data"]["module"], "ledger");
+    }
+
+    #[test]
+    fn test_unsigned_transactions_need_dev_accounts() {
+        use crate::error::LedgerError;
+        use crate::keys::KeyPair;
+
+        let key = KeyPair::from_seed([1; 32]);
+        let mut spec = crate::genesis::GenesisSpec::default();
+        spec.balances.insert(key.address().to_hex(), 1000);
+        let blockchain = Mutex::new(Blockchain::from_genesis(&spec).unwrap());
+
+        let unsigned = Transaction::new("alice".to_string(), "bob".to_string(), 10, 1);
+        let rejected = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "author_submitTransaction", "params": [unsigned], "id": 1 }));
+        assert_eq!(rejected["error"]["code"], SERVER_ERROR);
+        assert_eq!(rejected["error"]["data"]["code"], LedgerError::UnsignedTransaction.code());
+
+        let signed = Transaction::new(key.address().to_hex(), "bob".to_string(), 10, 1).signed(&key);
+        let accepted = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "author_submitTransaction", "params": [signed], "id": 2 }));
+        assert!(accepted.get("error").is_none(), "{}", accepted);
+
+        blockchain.lock().unwrap().set_dev_accounts(true);
+        let accepted = call(&blockchain, json!({ "jsonrpc": "2.0", "method": "author_submitTransaction", "params": [unsigned], "id": 3 }));
+        assert!(accepted.get("error").is_none(), "{}", accepted);
+    }
+}
@@ -1,7 +1,66 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use crate::error::ErrorCode;
+use crate::merkle::{FastMerkleTree, Hash};
+
+/// Rounds after which `mine_block` gives up.
+pub const MAX_MINING_ROUNDS: u32 = 1_000_000;
+/// Most RPS players a chain may run with. Every node simulates each one.
+pub const MAX_RPS_PLAYERS: u32 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MiningError {
+    Timeout { rounds: u32 },
+    NoPlayers,
+    InvalidPayoutAddress(String),
+    /// Legacy player ids must run 0..n, as ids are proof leaf indices.
+    InvalidLegacyPlayerIds,
+    /// A legacy block whose transfers or Merkle root do not hash as stored.
+    InvalidLegacyBlock { index: u32 },
+}
+
+impl fmt::Display for MiningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MiningError::Timeout { rounds } => write!(f, "Mining timeout: no winner after {} rounds", rounds),
+            MiningError::NoPlayers => write!(f, "No RPS players configured"),
+            MiningError::InvalidPayoutAddress(address) => write!(f, "Cannot pay a coinbase to '{}'", address),
+            MiningError::InvalidLegacyPlayerIds => write!(f, "Legacy player ids are not 0 to n-1"),
+            MiningError::InvalidLegacyBlock { index } => write!(f, "Legacy block #{} does not match its hashes", index),
+        }
+    }
+}
+
+impl std::error::Error for MiningError {}
+
+impl ErrorCode for MiningError {
+    fn code(&self) -> u16 {
+        match self {
+            MiningError::Timeout { .. } => 2001,
+            MiningError::NoPlayers => 2002,
+            MiningError::InvalidPayoutAddress(_) => 2003,
+            MiningError::InvalidLegacyPlayerIds => 2004,
+            MiningError::InvalidLegacyBlock { .. } => 2005,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "mining"
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            MiningError::Timeout { .. } => 503,
+            MiningError::NoPlayers => 500,
+            MiningError::InvalidPayoutAddress(_)
+            | MiningError::InvalidLegacyPlayerIds
+            | MiningError::InvalidLegacyBlock { .. } => 400,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Move {
@@ -100,6 +159,12 @@ pub struct RPSMiningConfig {
     pub blocks_mined: u32,
 }
 
+impl Default for RPSMiningConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RPSMiningConfig {
     pub fn new() -> Self {
         Self {
@@ -111,19 +176,18 @@ impl RPSMiningConfig {
     pub fn get_win_requirements(&self) -> Vec<u32> {
         let mut requirements = Vec::new();
         let blocks = self.blocks_mined;
+        let total = self.total_players;
         
         if blocks == 0 {
 
-            requirements.resize(100, 1);
+            requirements.resize(total as usize, 1);
         } else {
 
-            let players_with_extra_wins = std::cmp::min(blocks, 100);
-            let players_with_one_win = 100 - players_with_extra_wins;
+            let players_with_extra_wins = std::cmp::min(blocks, total);
+            let players_with_one_win = total - players_with_extra_wins;
             
 
-            for _ in 0..players_with_one_win {
-                requirements.push(1);
-            }
+            requirements.resize(players_with_one_win as usize, 1);
             
 
             for i in 0..players_with_extra_wins {
@@ -149,7 +213,12 @@ impl RPSMiner {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_nanos() as u64;
+        Self::with_seed(config, blockchain_seed)
+    }
 
+    /// A miner whose players are seeded from `blockchain_seed`. Nodes of
+    /// one chain share the seed so their miner state agrees.
+    pub fn with_seed(config: RPSMiningConfig, blockchain_seed: u64) -> Self {
         let win_requirements = config.get_win_requirements();
         let mut players = Vec::new();
         
@@ -165,8 +234,23 @@ impl RPSMiner {
         }
     }
 
-    pub fn mine_block(&mut self, block_data: &str) -> Result<RPSMiningResult, String> {
+    /// Resizes the player pool, keeping the seed and blocks mined so the
+    /// difficulty curve carries on from where it was.
+    pub fn set_total_players(&mut self, total_players: u32) {
+        self.config.total_players = total_players;
+        self.players = self.config
+            .get_win_requirements()
+            .iter()
+            .enumerate()
+            .map(|(i, &required_wins)| Player::new(i as u32, required_wins, self.blockchain_seed))
+            .collect();
+    }
 
+    pub fn mine_block(&mut self, block_data: &str) -> Result<(RPSMiningResult, Vec<PlayerOutcome>), MiningError> {
+
+        if self.players.is_empty() {
+            return Err(MiningError::NoPlayers);
+        }
         let mut hasher = Sha256::new();
         hasher.update(block_data.as_bytes());
         hasher.update(self.blockchain_seed.to_be_bytes());
@@ -210,7 +294,7 @@ impl RPSMiner {
             }
 
             total_games += round_games;
-            self.games_played += round_games as u64;
+            self.games_played += round_games;
 
             if all_players_won {
                 let mining_time = SystemTime::now()
@@ -218,31 +302,63 @@ impl RPSMiner {
                     .unwrap()
                     .as_millis();
 
+                let outcomes: Vec<PlayerOutcome> = self.players.iter().map(PlayerOutcome::from).collect();
                 let result = RPSMiningResult {
                     success: true,
                     rounds: round,
                     total_games,
                     mining_time_ms: mining_time,
-                    winning_players: self.players.clone(),
+                    player_count: outcomes.len() as u32,
+                    total_wins: outcomes.iter().map(|o| o.wins as u64).sum(),
+                    outcomes_root: outcomes_root(&outcomes),
                     final_seed: block_seed,
                 };
-                for player in &mut self.players {
-                    player.reset();
-                }
-                self.config.blocks_mined += 1;
-                
-
-                let new_requirements = self.config.get_win_requirements();
-                for (i, &required_wins) in new_requirements.iter().enumerate() {
-                    if let Some(player) = self.players.get_mut(i) {
-                        player.required_wins = required_wins;
-                    }
-                }
+                self.complete_block();
 
-                return Ok(result);
+                return Ok((result, outcomes));
+            }
+            if round > MAX_MINING_ROUNDS {
+                return Err(MiningError::Timeout { rounds: round });
             }
-            if round > 1000000 {
-                return Err("Mining timeout: too many rounds".to_string());
+        }
+    }
+
+    /// Replays the game for `block_data` from the current state and checks
+    /// that it ends as `result` claims. The miner itself is left unchanged.
+    pub fn verify_seal(&self, block_data: &str, result: &RPSMiningResult) -> bool {
+        self.replay_seal(block_data, result).is_some()
+    }
+
+    /// Like `verify_seal`, but returns the per-player outcomes the replay
+    /// produced, for serving outcome proofs.
+    pub fn replay_seal(&self, block_data: &str, result: &RPSMiningResult) -> Option<Vec<PlayerOutcome>> {
+        let (replayed, outcomes) = self.clone().mine_block(block_data).ok()?;
+        let matches = replayed.rounds == result.rounds
+            && replayed.total_games == result.total_games
+            && replayed.player_count == result.player_count
+            && replayed.total_wins == result.total_wins
+            && replayed.outcomes_root == result.outcomes_root
+            && replayed.final_seed == result.final_seed;
+        matches.then_some(outcomes)
+    }
+
+    /// Moves the miner past a block mined elsewhere, leaving it in the same
+    /// state as the node that mined it.
+    pub fn advance(&mut self, result: &RPSMiningResult) {
+        self.games_played += result.total_games;
+        self.complete_block();
+    }
+
+    fn complete_block(&mut self) {
+        for player in &mut self.players {
+            player.reset();
+        }
+        self.config.blocks_mined += 1;
+
+        let new_requirements = self.config.get_win_requirements();
+        for (i, &required_wins) in new_requirements.iter().enumerate() {
+            if let Some(player) = self.players.get_mut(i) {
+                player.required_wins = required_wins;
             }
         }
     }
@@ -265,8 +381,88 @@ impl RPSMiner {
     }
 }
 
+/// A single player's result for one mined block. These are the leaves of
+/// the block's `outcomes_root`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerOutcome {
+    pub id: u32,
+    pub required_wins: u32,
+    pub wins: u32,
+    pub games: u32,
+}
+
+impl PlayerOutcome {
+    pub fn leaf_hash(&self) -> Hash {
+        Hash::from_string(&format!(
+            "{}:{}:{}:{}",
+            self.id, self.required_wins, self.wins, self.games
+        ))
+    }
+}
+
+impl From<&Player> for PlayerOutcome {
+    fn from(player: &Player) -> Self {
+        Self {
+            id: player.id,
+            required_wins: player.required_wins,
+            wins: player.current_wins,
+            games: player.games_played,
+        }
+    }
+}
+
+fn outcomes_tree(outcomes: &[PlayerOutcome]) -> FastMerkleTree {
+    let mut tree = FastMerkleTree::new();
+    for outcome in outcomes {
+        tree.add_leaf(outcome.leaf_hash());
+    }
+    tree.build();
+    tree
+}
+
+pub fn outcomes_root(outcomes: &[PlayerOutcome]) -> Hash {
+    outcomes_tree(outcomes)
+        .get_root()
+        .cloned()
+        .unwrap_or_else(|| Hash::from_string("empty"))
+}
+
+/// Builds the inclusion proof for `player_id`. Outcomes are ordered by
+/// player id, so the id doubles as the leaf index.
+pub fn outcome_proof(outcomes: &[PlayerOutcome], player_id: u32) -> Option<(PlayerOutcome, Vec<Hash>)> {
+    let outcome = outcomes.get(player_id as usize)?;
+    let proof = outcomes_tree(outcomes).get_proof(player_id as usize)?;
+    Some((outcome.clone(), proof))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RPSMiningResult {
+    pub success: bool,
+    pub rounds: u32,
+    pub total_games: u64,
+    pub mining_time_ms: u128,
+    pub player_count: u32,
+    pub total_wins: u64,
+    pub outcomes_root: Hash,
+    pub final_seed: u64,
+}
+
+impl RPSMiningResult {
+    pub fn verify_player_outcome(&self, outcome: &PlayerOutcome, proof: &[Hash]) -> bool {
+        outcome.id < self.player_count
+            && FastMerkleTree::verify_against_root(
+                &self.outcomes_root,
+                &outcome.leaf_hash(),
+                proof,
+                outcome.id as usize,
+            )
+    }
+}
+
+/// Mining result as stored by blocks before outcomes were committed by root,
+/// with a full copy of every player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyRPSMiningResult {
     pub success: bool,
     pub rounds: u32,
     pub total_games: u64,
@@ -275,6 +471,34 @@ pub struct RPSMiningResult {
     pub final_seed: u64,
 }
 
+impl LegacyRPSMiningResult {
+    /// Outcomes ordered by player id. `None` unless the ids are exactly
+    /// 0..n, since proofs use the id as the leaf index.
+    pub fn outcomes(&self) -> Option<Vec<PlayerOutcome>> {
+        let mut outcomes: Vec<PlayerOutcome> = self.winning_players.iter().map(PlayerOutcome::from).collect();
+        outcomes.sort_by_key(|o| o.id);
+        outcomes.iter().enumerate().all(|(index, o)| o.id as usize == index).then_some(outcomes)
+    }
+}
+
+impl TryFrom<LegacyRPSMiningResult> for RPSMiningResult {
+    type Error = MiningError;
+
+    fn try_from(legacy: LegacyRPSMiningResult) -> Result<Self, MiningError> {
+        let outcomes = legacy.outcomes().ok_or(MiningError::InvalidLegacyPlayerIds)?;
+        Ok(Self {
+            success: legacy.success,
+            rounds: legacy.rounds,
+            total_games: legacy.total_games,
+            mining_time_ms: legacy.mining_time_ms,
+            player_count: outcomes.len() as u32,
+            total_wins: outcomes.iter().map(|o| o.wins as u64).sum(),
+            outcomes_root: outcomes_root(&outcomes),
+            final_seed: legacy.final_seed,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DifficultyInfo {
     #[allow(dead_code)]
@@ -363,4 +587,48 @@ mod tests {
         assert_eq!(miner.players.len(), 100);
         assert!(miner.players.iter().all(|p| p.required_wins == 1));
     }
+
+    #[test]
+    fn test_outcome_proofs() {
+        let mut miner = RPSMiner::new(RPSMiningConfig::new());
+        let (result, outcomes) = miner.mine_block("block").unwrap();
+        assert_eq!(result.player_count, 100);
+        assert_eq!(result.total_games, outcomes.iter().map(|o| o.games as u64).sum::<u64>());
+
+        let (outcome, proof) = outcome_proof(&outcomes, 42).unwrap();
+        assert!(result.verify_player_outcome(&outcome, &proof));
+
+        let mut forged = outcome.clone();
+        forged.wins += 1;
+        assert!(!result.verify_player_outcome(&forged, &proof));
+    }
+
+    #[test]
+    fn test_legacy_result_conversion() {
+        let mut miner = RPSMiner::new(RPSMiningConfig::new());
+        let (result, outcomes) = miner.mine_block("block").unwrap();
+        let players = outcomes.iter().map(|o| Player {
+            id: o.id,
+            required_wins: o.required_wins,
+            current_wins: o.wins,
+            games_played: o.games,
+            seed: 0,
+        }).collect();
+        let legacy = LegacyRPSMiningResult {
+            success: result.success,
+            rounds: result.rounds,
+            total_games: result.total_games,
+            mining_time_ms: result.mining_time_ms,
+            winning_players: players,
+            final_seed: result.final_seed,
+        };
+
+        let converted = RPSMiningResult::try_from(legacy.clone()).unwrap();
+        assert_eq!(converted.outcomes_root, result.outcomes_root);
+        assert_eq!(converted.total_wins, result.total_wins);
+
+        let mut gapped = legacy;
+        gapped.winning_players.retain(|player| player.id != 1);
+        assert_eq!(RPSMiningResult::try_from(gapped).unwrap_err(), MiningError::InvalidLegacyPlayerIds);
+    }
 }
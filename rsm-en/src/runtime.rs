@@ -0,0 +1,623 @@
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{self, Pallet as AssetsPallet};
+use crate::balances::{self, Pallet as BalancesPallet};
+use crate::contracts::{self, Pallet as ContractsPallet};
+use crate::events::Event;
+use crate::governance::{self, ParameterChange, Pallet as GovernancePallet};
+use crate::merkle::Hash;
+use crate::multisig::{self, Approval, Pallet as MultisigPallet};
+use crate::staking::{self, Pallet as StakingPallet};
+use crate::storage::{Storage, StorageError};
+use crate::system::{self, Pallet as SystemPallet};
+use crate::transaction::Transaction;
+use crate::utxo::UtxoSet;
+use crate::vesting::{self, Pallet as VestingPallet};
+use crate::vm::Word;
+use crate::error::LedgerError;
+
+pub type DispatchResult = Result<(), LedgerError>;
+
+/// Human-readable account names. This is the configuration the chain runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefaultConfig;
+
+impl system::Config for DefaultConfig {
+    type AccountId = String;
+    type BlockNumber = u32;
+}
+
+impl balances::Config for DefaultConfig {
+    type Balance = u128;
+}
+
+impl multisig::Config for DefaultConfig {
+    fn account_from_hash(hash: &Hash) -> String {
+        format!("ms_{}", &hash.to_hex()[..40])
+    }
+}
+
+impl contracts::Config for DefaultConfig {
+    fn contract_account(hash: &Hash) -> String {
+        format!("contract_{}", &hash.to_hex()[..40])
+    }
+
+    fn account_word(who: &String) -> Word {
+        word_from_bytes(Hash::from_string(who).as_bytes())
+    }
+}
+
+/// Fixed 32-byte addresses and 64-bit block numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactConfig;
+
+impl system::Config for CompactConfig {
+    type AccountId = [u8; 32];
+    type BlockNumber = u64;
+}
+
+impl balances::Config for CompactConfig {
+    type Balance = u128;
+}
+
+impl multisig::Config for CompactConfig {
+    fn account_from_hash(hash: &Hash) -> [u8; 32] {
+        *hash.as_bytes()
+    }
+}
+
+impl contracts::Config for CompactConfig {
+    fn contract_account(hash: &Hash) -> [u8; 32] {
+        *hash.as_bytes()
+    }
+
+    fn account_word(who: &[u8; 32]) -> Word {
+        word_from_bytes(who)
+    }
+}
+
+/// The first 16 bytes of an address, as a VM word.
+fn word_from_bytes(bytes: &[u8; 32]) -> Word {
+    let mut word = [0; 16];
+    word.copy_from_slice(&bytes[..16]);
+    Word::from_be_bytes(word)
+}
+
+/// Key prefix of runtime state in storage. Each top-level field of
+/// `Runtime` (one per pallet, plus the fee rate) is stored under
+/// `runtime/<field>` as JSON.
+pub const STATE_PREFIX: &[u8] = b"runtime/";
+
+/// Accounts that exist for bookkeeping only and can never sign.
+pub const RESERVED_ACCOUNTS: [&str; 1] = ["network"];
+
+/// Everything a transaction can ask the chain to do, routed by pallet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Call {
+    Balances(balances::Call),
+    System(system::Call),
+    Staking(staking::Call),
+    Assets(assets::Call),
+    Governance(governance::Call),
+    Multisig(multisig::Call),
+    Vesting(vesting::Call),
+    Contracts(contracts::Call),
+}
+
+impl Call {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Balances(call) => call.weight(),
+            Call::System(call) => call.weight(),
+            Call::Staking(call) => call.weight(),
+            Call::Assets(call) => call.weight(),
+            Call::Governance(call) => call.weight(),
+            Call::Multisig(call) => call.weight(),
+            Call::Vesting(call) => call.weight(),
+            Call::Contracts(call) => call.weight(),
+        }
+    }
+
+    /// Accounts other than the signer that this call touches. Used for
+    /// history lookups.
+    pub fn counterparties(&self) -> Vec<&String> {
+        match self {
+            Call::Balances(balances::Call::Transfer { to, .. }) => vec![to],
+            Call::Staking(staking::Call::Nominate { targets }) => targets.iter().collect(),
+            Call::Assets(call) => call.counterparties(),
+            Call::Multisig(call) => call.other_signatories().iter().collect(),
+            Call::Vesting(vesting::Call::VestedTransfer { target, .. }) => vec![target],
+            Call::Contracts(contracts::Call::Call { contract, .. }) => vec![contract],
+            Call::Contracts(contracts::Call::Deploy { .. }) => Vec::new(),
+            Call::System(_) | Call::Staking(_) | Call::Governance(_) => Vec::new(),
+        }
+    }
+
+    pub fn asset_id(&self) -> Option<assets::AssetId> {
+        match self {
+            Call::Assets(call) => Some(call.asset_id()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Runtime {
+    pub system: SystemPallet,
+    pub balances: BalancesPallet,
+    pub staking: StakingPallet,
+    pub assets: AssetsPallet,
+    #[serde(default = "default_governance")]
+    pub governance: GovernancePallet,
+    #[serde(default = "default_multisig")]
+    pub multisig: MultisigPallet,
+    #[serde(default = "default_vesting")]
+    pub vesting: VestingPallet,
+    #[serde(default = "default_contracts")]
+    pub contracts: ContractsPallet,
+    /// Unspent outputs. Only used in UTXO mode.
+    #[serde(default)]
+    pub utxo: UtxoSet,
+    pub fee_per_weight: u128,
+    /// Events in emission order. Pallets queue their own; they are moved
+    /// here after every step that can emit them.
+    #[serde(skip)]
+    events: Vec<Event>,
+}
+
+fn default_governance() -> GovernancePallet {
+    GovernancePallet::new(5, 2, 50, 10)
+}
+
+fn default_multisig() -> MultisigPallet {
+    MultisigPallet::new(20)
+}
+
+fn default_vesting() -> VestingPallet {
+    VestingPallet::new(10, 8)
+}
+
+fn default_contracts() -> ContractsPallet {
+    ContractsPallet::new(4096, 100_000)
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Self {
+            system: SystemPallet::new(),
+            balances: BalancesPallet::new(),
+            staking: StakingPallet::new(10, 10),
+            assets: AssetsPallet::new(),
+            governance: default_governance(),
+            multisig: default_multisig(),
+            vesting: default_vesting(),
+            contracts: default_contracts(),
+            utxo: UtxoSet::new(),
+            fee_per_weight: 1,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn fee_for(&self, call: &Call) -> u128 {
+        call.weight() as u128 * self.fee_per_weight
+    }
+
+    pub fn ensure_signed(who: &str) -> DispatchResult {
+        if who.is_empty() {
+            return Err(LedgerError::MissingSigner);
+        }
+        if RESERVED_ACCOUNTS.contains(&who) {
+            return Err(LedgerError::ReservedSigner);
+        }
+        Ok(())
+    }
+
+    /// Pool-side checks. `pending_from_signer` is the number of transactions
+    /// from the same signer already queued, so nonces can be chained.
+    pub fn validate_transaction(&self, tx: &Transaction, pending_from_signer: u32) -> DispatchResult {
+        Self::ensure_signed(&tx.from)?;
+
+        let expected_nonce = self.system.get_nonce(&tx.from) + pending_from_signer + 1;
+        if tx.nonce != expected_nonce {
+            return Err(LedgerError::InvalidNonce);
+        }
+
+        let fee = self.fee_for(&tx.call);
+        let spend = match &tx.call {
+            Call::Balances(balances::Call::Transfer { amount, .. }) => *amount,
+            Call::Staking(staking::Call::Bond { amount }) => *amount,
+            Call::Governance(governance::Call::Propose { .. }) => self.governance.proposal_deposit,
+            Call::Multisig(multisig::Call::AsMulti { threshold, .. }) if *threshold > 1 => self.multisig.deposit,
+            Call::Vesting(vesting::Call::VestedTransfer { schedule, .. }) => schedule.locked(),
+            Call::Contracts(contracts::Call::Call { value, gas_limit, .. }) => {
+                value.saturating_add((*gas_limit as u128).saturating_mul(self.fee_per_weight))
+            }
+            _ => 0,
+        };
+        if self.balances.get_balance(&tx.from) < fee.saturating_add(spend) {
+            return Err(LedgerError::InsufficientBalance);
+        }
+        if self.balances.usable_balance(&tx.from) < spend {
+            return Err(LedgerError::BalanceLocked);
+        }
+        Ok(())
+    }
+
+    /// Applies a transaction inside a block: checks the signer and nonce,
+    /// charges the fee, then routes the call to its pallet. A failed call
+    /// leaves state untouched, including calls run by a multisig on its
+    /// final approval. Returns the fee charged, gas included.
+    pub fn apply_extrinsic(&mut self, tx: &Transaction) -> Result<u128, LedgerError> {
+        Self::ensure_signed(&tx.from)?;
+
+        if tx.nonce != self.system.get_nonce(&tx.from) + 1 {
+            return Err(LedgerError::InvalidNonce);
+        }
+
+        let fee = self.fee_for(&tx.call);
+        self.balances.withdraw_fee(&tx.from, fee)?;
+
+        let gas_fee = match &tx.call {
+            Call::Contracts(call) => self.dispatch_contracts(&tx.from, call),
+            call => self.dispatch(&tx.from, call).map(|_| 0),
+        };
+        let gas_fee = match gas_fee {
+            Ok(gas_fee) => gas_fee,
+            Err(e) => {
+                self.balances.deposit(&tx.from, fee);
+                return Err(e);
+            }
+        };
+        self.collect_events();
+
+        self.system.inc_nonce(&tx.from);
+        Ok(fee + gas_fee)
+    }
+
+    /// Contract calls pay for gas at the fee rate. Returns the gas fee.
+    fn dispatch_contracts(&mut self, caller: &String, call: &contracts::Call) -> Result<u128, LedgerError> {
+        self.contracts.dispatch(caller, call, self.fee_per_weight, &mut self.balances)
+    }
+
+    pub fn dispatch(&mut self, caller: &String, call: &Call) -> DispatchResult {
+        match call {
+            Call::Balances(call) => self.balances.dispatch(caller, call),
+            Call::System(call) => self.system.dispatch(caller, call),
+            Call::Staking(call) => {
+                let now = self.system.get_block_number();
+                self.staking.dispatch(caller, call, &mut self.balances, now)
+            }
+            Call::Assets(call) => self.assets.dispatch(caller, call),
+            Call::Governance(call) => {
+                let now = self.system.get_block_number();
+                self.governance.dispatch(caller, call, &mut self.balances, now)
+            }
+            Call::Multisig(call) => {
+                let now = self.system.get_block_number();
+                match self.multisig.dispatch(caller, call, &mut self.balances, now)? {
+                    Approval::Recorded => Ok(()),
+                    Approval::Execute { account, call_hash, call } => {
+                        // The inner call may touch several pallets before it
+                        // fails, so run it against a checkpoint and roll the
+                        // whole runtime back on error. The multisig stays open.
+                        let checkpoint = self.clone();
+                        if let Err(e) = self.dispatch(&account, &call) {
+                            *self = checkpoint;
+                            return Err(e);
+                        }
+                        self.multisig.complete(&account, &call_hash, &mut self.balances);
+                        Ok(())
+                    }
+                }
+            }
+            Call::Vesting(call) => {
+                let now = self.system.get_block_number();
+                self.vesting.dispatch(caller, call, &mut self.balances, now)
+            }
+            // Gas paid here is burned; through `apply_extrinsic` it goes to
+            // the block author with the fee.
+            Call::Contracts(call) => self.dispatch_contracts(caller, call).map(|_| ()),
+        }
+    }
+
+    /// Pays the block author, rewards stakers, advances the block number and
+    /// enacts due governance changes. Returns every change enacted; the
+    /// ones the runtime does not own are left for the caller to apply.
+    pub fn finalize_block(&mut self, author: &String, payout: u128) -> Vec<ParameterChange> {
+        self.collect_events();
+        if payout > 0 {
+            let paid = self.balances.deposit(author, payout);
+            self.events.push(Event::RewardPaid { who: author.clone(), amount: paid });
+        }
+        self.staking.reward(self.staking.reward_per_block, &mut self.balances);
+        self.system.inc_block_number(author);
+
+        let now = self.system.get_block_number();
+        self.balances.on_finalize(now);
+        self.vesting.on_finalize(now, &mut self.balances);
+        let enacted = self.governance.on_finalize(now, &mut self.balances);
+        for change in &enacted {
+            self.apply_parameter_change(change);
+        }
+        enacted
+    }
+
+    /// Writes the state into `storage`, one entry per field. Unchanged
+    /// entries are not rewritten, so an overlay only records what moved.
+    pub fn store<S: Storage + ?Sized>(&self, storage: &mut S) {
+        let state = serde_json::to_value(self).expect("runtime state is serializable");
+        if let serde_json::Value::Object(fields) = state {
+            for (field, value) in fields {
+                let key = [STATE_PREFIX, field.as_bytes()].concat();
+                let value = serde_json::to_vec(&value).expect("runtime state is serializable");
+                if storage.get(&key).as_ref() != Some(&value) {
+                    storage.put(&key, value);
+                }
+            }
+        }
+    }
+
+    /// Reads back what `store` wrote. `None` if `storage` holds no runtime.
+    pub fn load<S: Storage + ?Sized>(storage: &S) -> Result<Option<Self>, StorageError> {
+        let entries = storage.scan_prefix(STATE_PREFIX);
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let mut fields = serde_json::Map::new();
+        for (key, value) in entries {
+            let field = String::from_utf8(key[STATE_PREFIX.len()..].to_vec())
+                .map_err(|e| StorageError::Corrupt(e.to_string()))?;
+            let value = serde_json::from_slice(&value).map_err(|e| StorageError::Corrupt(e.to_string()))?;
+            fields.insert(field, value);
+        }
+        serde_json::from_value(serde_json::Value::Object(fields))
+            .map(Some)
+            .map_err(|e| StorageError::Corrupt(e.to_string()))
+    }
+
+    /// Drains events emitted since the last call, in emission order.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.collect_events();
+        std::mem::take(&mut self.events)
+    }
+
+    /// Moves the events pallets queued during the last step onto the
+    /// runtime's queue. A step emits from one pallet before handing back to
+    /// the caller, and a contract's transfers happen before it reports its
+    /// result, so taking balances, then system, then contracts keeps the
+    /// order they happened in.
+    fn collect_events(&mut self) {
+        let transfers = self.balances.take_events().into_iter().map(|event| match event {
+            balances::Event::Transfer { from, to, amount } => Event::Transfer { from, to, amount },
+        });
+        let nonces = self.system.take_events().into_iter().map(|event| match event {
+            system::Event::NonceIncremented { who, nonce } => Event::NonceIncremented { who, nonce },
+        });
+        let contracts = self.contracts.take_events().into_iter().map(|event| match event {
+            contracts::Event::Deployed { contract, deployer } => Event::ContractDeployed { contract, deployer },
+            contracts::Event::Executed { contract, caller, gas_used, output } => {
+                Event::ContractExecuted { contract, caller, gas_used, output }
+            }
+            contracts::Event::Reverted { contract, caller, gas_used, reason } => {
+                Event::ContractReverted { contract, caller, gas_used, reason }
+            }
+        });
+        let collected: Vec<Event> = transfers.chain(nonces).chain(contracts).collect();
+        self.events.extend(collected);
+    }
+
+    fn apply_parameter_change(&mut self, change: &ParameterChange) {
+        match change {
+            ParameterChange::FeePerWeight(fee) => self.fee_per_weight = *fee,
+            ParameterChange::ExistentialDeposit(amount) => self.balances.existential_deposit = *amount,
+            ParameterChange::StakingRewardPerBlock(reward) => self.staking.reward_per_block = *reward,
+            ParameterChange::BondingDuration(blocks) => self.staking.bonding_duration = *blocks,
+            ParameterChange::MiningReward(_)
+            | ParameterChange::Difficulty(_)
+            | ParameterChange::RpsTotalPlayers(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime() -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.balances.set_balance(&"alice".to_string(), 1000);
+        runtime
+    }
+
+    #[test]
+    fn test_transfer_charges_fee() {
+        let mut runtime = runtime();
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 100, 1);
+
+        let fee = runtime.apply_extrinsic(&tx).unwrap();
+        assert_eq!(fee, 10);
+        assert_eq!(runtime.take_events(), vec![
+            Event::Transfer { from: "alice".to_string(), to: "bob".to_string(), amount: 100 },
+            Event::NonceIncremented { who: "alice".to_string(), nonce: 1 },
+        ]);
+        assert_eq!(runtime.balances.get_balance(&"alice".to_string()), 890);
+        assert_eq!(runtime.balances.get_balance(&"bob".to_string()), 100);
+        assert_eq!(runtime.system.get_nonce(&"alice".to_string()), 1);
+    }
+
+    #[test]
+    fn test_failed_call_refunds_fee() {
+        let mut runtime = runtime();
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 995, 1);
+
+        assert!(runtime.apply_extrinsic(&tx).is_err());
+        assert_eq!(runtime.balances.get_balance(&"alice".to_string()), 1000);
+        assert_eq!(runtime.system.get_nonce(&"alice".to_string()), 0);
+    }
+
+    #[test]
+    fn test_signer_and_nonce_checks() {
+        let mut runtime = runtime();
+        let reserved = Transaction::new("network".to_string(), "bob".to_string(), 1, 1);
+        assert_eq!(runtime.apply_extrinsic(&reserved), Err(LedgerError::ReservedSigner));
+
+        let stale = Transaction::new("alice".to_string(), "bob".to_string(), 1, 2);
+        assert_eq!(runtime.apply_extrinsic(&stale), Err(LedgerError::InvalidNonce));
+        assert!(runtime.validate_transaction(&stale, 1).is_ok());
+    }
+
+    #[test]
+    fn test_remark_dispatch() {
+        let mut runtime = runtime();
+        let call = Call::System(system::Call::Remark { remark: "hello".to_string() });
+        let tx = Transaction::new_call("alice".to_string(), call, 1);
+
+        assert_eq!(runtime.apply_extrinsic(&tx), Ok(1));
+        assert_eq!(runtime.balances.get_balance(&"alice".to_string()), 999);
+    }
+
+    #[test]
+    fn test_staking_calls_and_block_reward() {
+        let mut runtime = runtime();
+        let bond = Call::Staking(staking::Call::Bond { amount: 500 });
+        runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), bond, 1)).unwrap();
+        runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), Call::Staking(staking::Call::Validate), 2)).unwrap();
+        assert_eq!(runtime.balances.get_balance(&"alice".to_string()), 470);
+
+        runtime.finalize_block(&"miner".to_string(), 0);
+        assert_eq!(runtime.balances.get_balance(&"alice".to_string()), 480);
+        assert_eq!(runtime.balances.reserved_balance(&"alice".to_string()), 500);
+    }
+
+    #[test]
+    fn test_governance_enacts_runtime_parameter() {
+        let mut runtime = runtime();
+        let propose = Call::Governance(governance::Call::Propose { change: ParameterChange::FeePerWeight(2) });
+        runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), propose, 1)).unwrap();
+        let vote = Call::Governance(governance::Call::Vote { proposal_id: 0, aye: true });
+        runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), vote, 2)).unwrap();
+
+        let mut enacted = Vec::new();
+        for _ in 0..7 {
+            enacted.extend(runtime.finalize_block(&"miner".to_string(), 0));
+        }
+        assert_eq!(enacted, vec![ParameterChange::FeePerWeight(2)]);
+        assert_eq!(runtime.fee_per_weight, 2);
+    }
+
+    #[test]
+    fn test_multisig_treasury_transfer() {
+        let mut runtime = runtime();
+        let bob = "bob".to_string();
+        runtime.balances.set_balance(&bob, 100);
+        let signatories = vec!["alice".to_string(), bob.clone(), "charlie".to_string()];
+        let treasury = <MultisigPallet>::multi_account_id(&signatories, 2);
+        runtime.balances.set_balance(&treasury, 500);
+
+        let payout = Call::Balances(balances::Call::Transfer { to: "dave".to_string(), amount: 300 });
+        let call_hash = <MultisigPallet>::call_hash(&payout);
+        let open = Call::Multisig(multisig::Call::AsMulti {
+            other_signatories: vec![bob.clone(), "charlie".to_string()],
+            threshold: 2,
+            call: Box::new(payout),
+        });
+        runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), open, 1)).unwrap();
+        runtime.finalize_block(&"miner".to_string(), 0);
+        assert_eq!(runtime.balances.get_balance(&treasury), 500);
+
+        let approve = Call::Multisig(multisig::Call::Approve {
+            other_signatories: vec!["alice".to_string(), "charlie".to_string()],
+            threshold: 2,
+            call_hash,
+        });
+        runtime.apply_extrinsic(&Transaction::new_call(bob.clone(), approve, 1)).unwrap();
+        assert_eq!(runtime.balances.get_balance(&treasury), 200);
+        assert_eq!(runtime.balances.get_balance(&"dave".to_string()), 300);
+        assert_eq!(runtime.balances.reserved_balance(&"alice".to_string()), 0);
+    }
+
+    #[test]
+    fn test_failed_multisig_execution_keeps_operation_open() {
+        let mut runtime = runtime();
+        let bob = "bob".to_string();
+        runtime.balances.set_balance(&bob, 100);
+        let signatories = vec!["alice".to_string(), bob.clone(), "charlie".to_string()];
+        let treasury = <MultisigPallet>::multi_account_id(&signatories, 2);
+        runtime.balances.set_balance(&treasury, 500);
+
+        let payout = Call::Balances(balances::Call::Transfer { to: "dave".to_string(), amount: 800 });
+        let call_hash = <MultisigPallet>::call_hash(&payout);
+        let open = Call::Multisig(multisig::Call::AsMulti {
+            other_signatories: vec![bob.clone(), "charlie".to_string()],
+            threshold: 2,
+            call: Box::new(payout),
+        });
+        runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), open, 1)).unwrap();
+        let reserved = runtime.balances.reserved_balance(&"alice".to_string());
+
+        let approve = Call::Multisig(multisig::Call::Approve {
+            other_signatories: vec!["alice".to_string(), "charlie".to_string()],
+            threshold: 2,
+            call_hash: call_hash.clone(),
+        });
+        let result = runtime.apply_extrinsic(&Transaction::new_call(bob.clone(), approve, 1));
+        assert_eq!(result, Err(LedgerError::InsufficientBalance));
+
+        let op = runtime.multisig.get(&treasury, &call_hash).unwrap();
+        assert_eq!(op.approvals, vec!["alice".to_string()]);
+        assert_eq!(runtime.balances.reserved_balance(&"alice".to_string()), reserved);
+        assert_eq!(runtime.balances.get_balance(&treasury), 500);
+        assert_eq!(runtime.balances.get_balance(&bob), 100);
+        assert_eq!(runtime.system.get_nonce(&bob), 0);
+    }
+
+    #[test]
+    fn test_contract_gas_is_charged_as_fee() {
+        let mut runtime = runtime();
+        let code = crate::vm::assemble(crate::vm::examples::COUNTER).unwrap();
+        let deploy = Call::Contracts(contracts::Call::Deploy { code });
+        let deploy_fee = runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), deploy, 1)).unwrap();
+        let counter = runtime.contracts.contracts.keys().next().unwrap().clone();
+
+        let bump = Call::Contracts(contracts::Call::Call {
+            contract: counter.clone(),
+            args: vec![contracts::Arg::Int(1), contracts::Arg::Int(4)],
+            value: 0,
+            gas_limit: 500,
+        });
+        let call_fee = runtime.apply_extrinsic(&Transaction::new_call("alice".to_string(), bump, 2)).unwrap();
+        assert!(call_fee > 10 && call_fee < 510);
+        assert_eq!(runtime.balances.get_balance(&"alice".to_string()), 1000 - deploy_fee - call_fee);
+        assert_eq!(runtime.contracts.storage_at(&counter, 0), 4);
+        assert!(matches!(runtime.take_events().as_slice(), [
+            Event::ContractDeployed { .. },
+            Event::NonceIncremented { nonce: 1, .. },
+            Event::ContractExecuted { output: Some(4), .. },
+            Event::NonceIncremented { nonce: 2, .. },
+        ]));
+    }
+
+    #[test]
+    fn test_compact_config_pallets() {
+        let alice = [1u8; 32];
+        let bob = [2u8; 32];
+        let mut balances: BalancesPallet<CompactConfig> = BalancesPallet::new();
+        let mut system: SystemPallet<CompactConfig> = SystemPallet::new();
+
+        balances.set_balance(&alice, 100);
+        let call = balances::Call::Transfer { to: bob, amount: 40 };
+        balances.dispatch(&alice, &call).unwrap();
+        system.inc_nonce(&alice);
+        system.inc_block_number(&alice);
+
+        assert_eq!(balances.get_balance(&bob), 40);
+        assert_eq!(system.get_nonce(&alice), 1);
+        assert_eq!(system.get_block_number(), 1u64);
+    }
+}
@@ -0,0 +1,401 @@
+//! Deterministic multi-node simulation. `Blockchain` nodes run in one
+//! process and exchange `p2p::Message`s over a virtual network with
+//! latency, packet loss and partitions. Time is virtual, blocks are
+//! stamped with it, and every random choice comes from one seeded
+//! generator, so a script with the same seed schedules the same deliveries
+//! and builds the same chains. Nodes also pull from a random peer every
+//! `sync_interval`, which repairs lost messages and healed partitions.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::blockchain::Blockchain;
+use crate::error::LedgerError;
+use crate::genesis::{GenesisError, GenesisSpec};
+use crate::merkle::Hash;
+use crate::p2p::{after_locator, chain_locator, Message, MAX_BLOCKS_PER_MESSAGE};
+use crate::rps_mining::MiningError;
+use crate::transaction::{Block, Transaction};
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub seed: u64,
+    /// Delivery delay in virtual milliseconds, drawn uniformly from
+    /// `min_latency..=max_latency`.
+    pub min_latency: u64,
+    pub max_latency: u64,
+    /// Chance that a message is dropped, from 0.0 to 1.0.
+    pub loss: f64,
+    /// Virtual milliseconds between pulls. Zero turns them off.
+    pub sync_interval: u64,
+    pub genesis: GenesisSpec,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            seed: 0,
+            min_latency: 10,
+            max_latency: 100,
+            loss: 0.0,
+            sync_interval: 1_000,
+            genesis: GenesisSpec::default(),
+        }
+    }
+}
+
+/// Message counts since the start, for asserting on propagation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimStats {
+    pub sent: u64,
+    pub delivered: u64,
+    /// Lost to packet loss or a partition.
+    pub dropped: u64,
+}
+
+/// SplitMix64: small, seedable and good enough for scheduling.
+#[derive(Debug, Clone)]
+struct SimRng(u64);
+
+impl SimRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `min..=max`.
+    fn between(&mut self, min: u64, max: u64) -> u64 {
+        min + self.next_u64() % (max.saturating_sub(min) + 1)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[derive(Debug)]
+struct SimNode {
+    blockchain: Blockchain,
+    /// Gossip already handled, by hex hash.
+    seen: HashSet<String>,
+}
+
+#[derive(Debug)]
+struct Envelope {
+    from: usize,
+    to: usize,
+    message: Message,
+}
+
+#[derive(Debug)]
+pub struct Simulator {
+    config: SimConfig,
+    rng: SimRng,
+    now: u64,
+    nodes: Vec<SimNode>,
+    /// Messages in flight by (delivery time, send order).
+    in_flight: BTreeMap<(u64, u64), Envelope>,
+    sequence: u64,
+    /// Partition group of each node; nodes only reach their own group.
+    groups: Vec<usize>,
+    next_sync: u64,
+    stats: SimStats,
+}
+
+impl Simulator {
+    /// `nodes` fully connected nodes, all at `config.genesis`.
+    pub fn new(nodes: usize, config: SimConfig) -> Result<Self, GenesisError> {
+        let nodes = (0..nodes)
+            .map(|_| {
+                let mut blockchain = Blockchain::from_genesis(&config.genesis)?;
+                blockchain.set_dev_accounts(true);
+                Ok(SimNode { blockchain, seen: HashSet::new() })
+            })
+            .collect::<Result<Vec<_>, GenesisError>>()?;
+        Ok(Simulator {
+            rng: SimRng(config.seed),
+            now: 0,
+            groups: vec![0; nodes.len()],
+            nodes,
+            in_flight: BTreeMap::new(),
+            sequence: 0,
+            next_sync: config.sync_interval,
+            stats: SimStats::default(),
+            config,
+        })
+    }
+
+    pub fn node(&self, index: usize) -> &Blockchain {
+        &self.nodes[index].blockchain
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Virtual milliseconds since the start.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn stats(&self) -> SimStats {
+        self.stats
+    }
+
+    /// Height and hash of every node's tip, by node.
+    pub fn tips(&self) -> Vec<(u32, Hash)> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                let tip = node.blockchain.get_latest_block();
+                (tip.index, tip.hash.clone())
+            })
+            .collect()
+    }
+
+    /// Whether every node has the same tip.
+    pub fn converged(&self) -> bool {
+        self.tips().windows(2).all(|pair| pair[0] == pair[1])
+    }
+
+    /// Splits the network into `groups`; nodes left out are isolated.
+    /// Messages across the split are lost, including ones already in
+    /// flight that arrive before `heal`.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut assigned: Vec<usize> = (0..self.nodes.len()).map(|index| groups.len() + index).collect();
+        for (group, members) in groups.iter().enumerate() {
+            for &member in *members {
+                assigned[member] = group;
+            }
+        }
+        self.groups = assigned;
+    }
+
+    /// Reconnects every node. Like peers that redial, each asks the others
+    /// for what it missed.
+    pub fn heal(&mut self) {
+        self.groups = vec![0; self.nodes.len()];
+        for from in 0..self.nodes.len() {
+            let locator = chain_locator(&self.nodes[from].blockchain);
+            for to in (0..self.nodes.len()).filter(|&to| to != from) {
+                self.send(from, to, Message::GetBlocks { locator: locator.clone() });
+            }
+        }
+    }
+
+    pub fn can_reach(&self, from: usize, to: usize) -> bool {
+        self.groups[from] == self.groups[to]
+    }
+
+    /// Queues a transaction at `node` and gossips it.
+    pub fn submit_transaction(&mut self, node: usize, transaction: Transaction) -> Result<(), LedgerError> {
+        self.nodes[node].blockchain.add_transaction(transaction.clone())?;
+        self.nodes[node].seen.insert(transaction.hash.to_hex());
+        self.broadcast(node, None, Message::NewTransaction { transaction });
+        Ok(())
+    }
+
+    /// Mines `node`'s pending transactions, paying `node-<index>`, and
+    /// gossips the block.
+    pub fn mine(&mut self, node: usize) -> Result<Block, MiningError> {
+        let now = self.config.genesis.timestamp + self.now / 1_000;
+        self.nodes[node].blockchain.set_clock(now);
+        let block = self.nodes[node].blockchain.mine_pending_transactions(format!("node-{}", node))?;
+        self.nodes[node].seen.insert(block.hash.to_hex());
+        self.broadcast(node, None, Message::NewBlock { block: block.clone() });
+        Ok(block)
+    }
+
+    /// Advances virtual time by `duration` milliseconds, delivering
+    /// everything due on the way.
+    pub fn run_for(&mut self, duration: u64) {
+        let until = self.now + duration;
+        loop {
+            let next_delivery = self.in_flight.keys().next().map(|&(at, _)| at);
+            let next_sync = (self.config.sync_interval > 0).then_some(self.next_sync);
+            let Some(at) = [next_delivery, next_sync].into_iter().flatten().min().filter(|&at| at <= until) else {
+                break;
+            };
+            self.now = at;
+            if next_sync == Some(at) {
+                self.pull_sync();
+            } else {
+                let (_, envelope) = self.in_flight.pop_first().expect("a delivery is due");
+                self.deliver(envelope);
+            }
+        }
+        self.now = until;
+    }
+
+    /// Runs until every node has the same tip, for at most `timeout`
+    /// milliseconds. Returns whether they converged.
+    pub fn run_until_converged(&mut self, timeout: u64) -> bool {
+        const STEP: u64 = 10;
+        let deadline = self.now + timeout;
+        while !self.converged() {
+            if self.now >= deadline {
+                return self.converged();
+            }
+            self.run_for(STEP.min(deadline - self.now));
+        }
+        true
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message) {
+        self.stats.sent += 1;
+        if !self.can_reach(from, to) || self.rng.chance(self.config.loss) {
+            self.stats.dropped += 1;
+            return;
+        }
+        let at = self.now + self.rng.between(self.config.min_latency, self.config.max_latency);
+        self.in_flight.insert((at, self.sequence), Envelope { from, to, message });
+        self.sequence += 1;
+    }
+
+    fn broadcast(&mut self, from: usize, except: Option<usize>, message: Message) {
+        for to in 0..self.nodes.len() {
+            if to != from && Some(to) != except {
+                self.send(from, to, message.clone());
+            }
+        }
+    }
+
+    /// Every node asks one reachable peer, picked at random, for blocks
+    /// past its tip.
+    fn pull_sync(&mut self) {
+        self.next_sync = self.now + self.config.sync_interval;
+        for from in 0..self.nodes.len() {
+            let peers: Vec<usize> = (0..self.nodes.len()).filter(|&to| to != from && self.can_reach(from, to)).collect();
+            if peers.is_empty() {
+                continue;
+            }
+            let to = peers[self.rng.between(0, peers.len() as u64 - 1) as usize];
+            let locator = chain_locator(&self.nodes[from].blockchain);
+            self.send(from, to, Message::GetBlocks { locator });
+        }
+    }
+
+    /// Handles a message the way a `p2p::Node` would, minus ban scores and
+    /// headers-first download.
+    fn deliver(&mut self, Envelope { from, to, message }: Envelope) {
+        if !self.can_reach(from, to) {
+            self.stats.dropped += 1;
+            return;
+        }
+        self.stats.delivered += 1;
+        match message {
+            Message::GetBlocks { locator } => {
+                let blockchain = &self.nodes[to].blockchain;
+                let start = after_locator(blockchain, &locator);
+                let blocks = blockchain.chain.iter().skip(start).take(MAX_BLOCKS_PER_MESSAGE).cloned().collect();
+                self.send(to, from, Message::Blocks { blocks });
+            }
+            Message::Blocks { blocks } => self.receive_blocks(from, to, blocks),
+            Message::NewBlock { block } => self.receive_block(from, to, block),
+            Message::NewTransaction { transaction } => {
+                if !self.nodes[to].seen.insert(transaction.hash.to_hex()) {
+                    return;
+                }
+                if self.nodes[to].blockchain.add_transaction(transaction.clone()).is_ok() {
+                    self.broadcast(to, Some(from), Message::NewTransaction { transaction });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn receive_block(&mut self, from: usize, to: usize, block: Block) {
+        if !self.nodes[to].seen.insert(block.hash.to_hex()) {
+            return;
+        }
+        let blockchain = &mut self.nodes[to].blockchain;
+        let tip = blockchain.get_latest_block();
+        if block.index == tip.index + 1 && block.previous_hash == tip.hash {
+            if blockchain.import_block(block.clone()).is_ok() {
+                self.broadcast(to, Some(from), Message::NewBlock { block });
+            }
+        } else if block.index > tip.index {
+            // A gap or a competing fork: fetch from where we part.
+            let locator = chain_locator(blockchain);
+            self.send(to, from, Message::GetBlocks { locator });
+        }
+    }
+
+    fn receive_blocks(&mut self, from: usize, to: usize, blocks: Vec<Block>) {
+        let Some(last) = blocks.last().cloned() else { return };
+        let full = blocks.len() == MAX_BLOCKS_PER_MESSAGE;
+        let blockchain = &mut self.nodes[to].blockchain;
+        let switched = if blocks[0].previous_hash == blockchain.get_latest_block().hash {
+            blocks.into_iter().try_for_each(|block| blockchain.import_block(block)).is_ok()
+        } else {
+            blockchain.reorganize(&self.config.genesis, blocks) == Ok(true)
+        };
+        if switched {
+            self.nodes[to].seen.insert(last.hash.to_hex());
+            self.broadcast(to, Some(from), Message::NewBlock { block: last });
+            if full {
+                let locator = chain_locator(&self.nodes[to].blockchain);
+                self.send(to, from, Message::GetBlocks { locator });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partitioned_network_converges_after_heal() {
+        let mut sim = Simulator::new(5, SimConfig { seed: 7, ..Default::default() }).unwrap();
+        sim.mine(0).unwrap();
+        assert!(sim.run_until_converged(5_000));
+
+        sim.partition(&[&[0, 1], &[2, 3, 4]]);
+        sim.submit_transaction(0, Transaction::new("alice".to_string(), "bob".to_string(), 10, 1)).unwrap();
+        sim.mine(0).unwrap();
+        for _ in 0..2 {
+            sim.mine(3).unwrap();
+        }
+        sim.run_for(2_000);
+        assert_eq!(sim.node(1).get_latest_block().index, 2);
+        assert_eq!(sim.node(2).get_latest_block().index, 3);
+        assert!(!sim.converged());
+
+        sim.heal();
+        assert!(sim.run_until_converged(10_000));
+        assert_eq!(sim.node(0).get_latest_block().hash, sim.node(4).get_latest_block().hash);
+        assert_eq!(sim.node(0).get_balance(&"node-0".to_string()), sim.node(0).block_reward(1));
+        // The transfer left with the dropped block and is pending again.
+        assert_eq!(sim.node(0).pending_transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_lossy_network_converges_through_pulls() {
+        let config = SimConfig { seed: 42, loss: 0.3, min_latency: 50, max_latency: 400, ..Default::default() };
+        let mut sim = Simulator::new(4, config).unwrap();
+        for round in 0..6 {
+            sim.mine(round % 4).unwrap();
+            sim.run_until_converged(20_000);
+        }
+        assert!(sim.converged());
+        assert_eq!(sim.node(3).get_latest_block().index, 6);
+        assert!(sim.stats().dropped > 0);
+    }
+
+    #[test]
+    fn test_same_seed_schedules_same_deliveries() {
+        let run = |seed| {
+            let config = SimConfig { seed, loss: 0.2, ..Default::default() };
+            let mut sim = Simulator::new(3, config).unwrap();
+            sim.mine(0).unwrap();
+            sim.run_for(300);
+            sim.mine(1).unwrap();
+            sim.run_for(3_000);
+            (sim.stats(), sim.tips())
+        };
+        assert_eq!(run(9), run(9));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::ErrorCode;
+use crate::genesis::LedgerMode;
+use crate::merkle::Hash;
+use crate::rps_mining::RPSMiner;
+use crate::runtime::Runtime;
+use crate::transaction::Block;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Blocks between state snapshots.
+pub const DEFAULT_SNAPSHOT_INTERVAL: u32 = 10;
+
+/// The state block `height` executes on: the runtime (balances, nonces and
+/// every other pallet), the RPS miner and the chain parameters governance
+/// can change. Block `height` commits `hash()` in its header.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub height: u32,
+    /// Hash of block `height - 1`.
+    pub parent_hash: Hash,
+    pub chain_name: String,
+    pub chain_id: u64,
+    pub ledger_mode: LedgerMode,
+    pub runtime: Runtime,
+    pub rps_miner: RPSMiner,
+    pub mining_reward: u128,
+    pub halving_interval: u32,
+    pub difficulty: usize,
+}
+
+impl StateSnapshot {
+    pub fn hash(&self) -> Hash {
+        Hash::from_string(&serde_json::to_string(self).expect("snapshot is serializable"))
+    }
+}
+
+/// What a node needs to fast sync: a snapshot and every block from the
+/// snapshot height on, oldest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncBundle {
+    pub snapshot: StateSnapshot,
+    pub blocks: Vec<Block>,
+}
+
+impl SyncBundle {
+    pub fn from_json(json: &str) -> Result<Self, SyncError> {
+        serde_json::from_str(json).map_err(|e| SyncError::Decode(e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncError {
+    MissingSnapshotBlock { height: u32 },
+    SnapshotMismatch { height: u32 },
+    InvalidBlock { index: u32 },
+    ReplayFailed { index: u32, reason: String },
+    Decode(String),
+    InvalidHeader { index: u32 },
+    InvalidSeal { index: u32 },
+    BodyMismatch { index: u32 },
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::MissingSnapshotBlock { height } => write!(f, "Bundle does not start with block {}", height),
+            SyncError::SnapshotMismatch { height } => write!(f, "Snapshot does not match the hash committed in block {}", height),
+            SyncError::InvalidBlock { index } => write!(f, "Block {} is invalid", index),
+            SyncError::ReplayFailed { index, reason } => write!(f, "Replaying block {} failed: {}", index, reason),
+            SyncError::Decode(detail) => write!(f, "Cannot decode sync bundle: {}", detail),
+            SyncError::InvalidHeader { index } => write!(f, "Header {} does not extend the chain", index),
+            SyncError::InvalidSeal { index } => write!(f, "RPS seal of block {} does not verify", index),
+            SyncError::BodyMismatch { index } => write!(f, "Body of block {} does not match its header", index),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl ErrorCode for SyncError {
+    fn code(&self) -> u16 {
+        match self {
+            SyncError::MissingSnapshotBlock { .. } => 7001,
+            SyncError::SnapshotMismatch { .. } => 7002,
+            SyncError::InvalidBlock { .. } => 7003,
+            SyncError::ReplayFailed { .. } => 7004,
+            SyncError::Decode(_) => 7005,
+            SyncError::InvalidHeader { .. } => 7006,
+            SyncError::InvalidSeal { .. } => 7007,
+            SyncError::BodyMismatch { .. } => 7008,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "sync"
+    }
+
+    fn http_status(&self) -> u16 {
+        match self {
+            SyncError::Decode(_) => 400,
+            _ => 422,
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, BTreeSet};
+use num_traits::{CheckedAdd, CheckedMul, CheckedSub, NumCast, Saturating, Zero};
+use serde::{Deserialize, Serialize};
+use crate::balances::{self, Pallet as BalancesPallet};
+use crate::runtime::{DefaultConfig, DispatchResult};
+use crate::error::LedgerError;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Call<T: balances::Config = DefaultConfig> {
+    Bond { amount: T::Balance },
+    Unbond { amount: T::Balance },
+    WithdrawUnbonded,
+    Validate,
+    Nominate { targets: Vec<T::AccountId> },
+    Chill,
+}
+
+impl<T: balances::Config> Call<T> {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Bond { .. } | Call::Unbond { .. } => 20,
+            Call::WithdrawUnbonded => 20,
+            Call::Validate | Call::Chill => 10,
+            Call::Nominate { targets } => 10 + targets.len() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct UnlockChunk<T: balances::Config = DefaultConfig> {
+    pub value: T::Balance,
+    pub unlock_at: T::BlockNumber,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct StakingLedger<T: balances::Config = DefaultConfig> {
+    pub active: T::Balance,
+    pub unlocking: Vec<UnlockChunk<T>>,
+}
+
+impl<T: balances::Config> StakingLedger<T> {
+    pub fn total(&self) -> T::Balance {
+        self.unlocking
+            .iter()
+            .fold(self.active, |acc, chunk| acc.saturating_add(chunk.value))
+    }
+}
+
+/// Bonded funds stay on the staker's account as reserved balance and are
+/// tracked per staker here. Consensus and governance drive the pallet
+/// through `reward`, `slash` and `slash_validator`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Pallet<T: balances::Config = DefaultConfig> {
+    pub ledgers: BTreeMap<T::AccountId, StakingLedger<T>>,
+    pub validators: BTreeSet<T::AccountId>,
+    pub nominators: BTreeMap<T::AccountId, Vec<T::AccountId>>,
+    pub bonding_duration: T::BlockNumber,
+    pub reward_per_block: T::Balance,
+}
+
+impl<T: balances::Config> Pallet<T> {
+
+    pub fn new(bonding_duration: T::BlockNumber, reward_per_block: T::Balance) -> Self {
+
+        Self {
+            ledgers: BTreeMap::new(),
+            validators: BTreeSet::new(),
+            nominators: BTreeMap::new(),
+            bonding_duration,
+            reward_per_block,
+        }
+    }
+
+    pub fn bonded(&self, who: &T::AccountId) -> T::Balance {
+
+        self.ledgers.get(who).map(|l| l.active).unwrap_or_else(T::Balance::zero)
+    }
+
+    pub fn bond(&mut self, who: &T::AccountId, amount: T::Balance, balances: &mut BalancesPallet<T>) -> DispatchResult {
+
+        if amount.is_zero() {
+            return Err(LedgerError::BondZero);
+        }
+        balances.reserve(who, amount)?;
+
+        let ledger = self.ledgers.entry(who.clone()).or_insert_with(|| StakingLedger {
+            active: T::Balance::zero(),
+            unlocking: Vec::new(),
+        });
+        ledger.active = ledger.active.saturating_add(amount);
+        Ok(())
+    }
+
+    pub fn unbond(&mut self, who: &T::AccountId, amount: T::Balance, now: T::BlockNumber) -> DispatchResult {
+
+        let unlock_at = now.checked_add(&self.bonding_duration).ok_or(LedgerError::Arithmetic)?;
+        let ledger = self.ledgers.get_mut(who).ok_or(LedgerError::NotBonded)?;
+        ledger.active = ledger.active.checked_sub(&amount).ok_or(LedgerError::InsufficientBonded)?;
+        ledger.unlocking.push(UnlockChunk { value: amount, unlock_at });
+
+        if ledger.active.is_zero() {
+            self.validators.remove(who);
+            self.nominators.remove(who);
+        }
+        Ok(())
+    }
+
+    /// Returns every chunk whose unbonding period has passed to the
+    /// staker's free balance.
+    pub fn withdraw_unbonded(&mut self, who: &T::AccountId, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> Result<T::Balance, LedgerError> {
+
+        let ledger = self.ledgers.get_mut(who).ok_or(LedgerError::NotBonded)?;
+        let mut released = T::Balance::zero();
+        ledger.unlocking.retain(|chunk| {
+            if chunk.unlock_at <= now {
+                released = released.saturating_add(chunk.value);
+                false
+            } else {
+                true
+            }
+        });
+        if ledger.active.is_zero() && ledger.unlocking.is_empty() {
+            self.ledgers.remove(who);
+        }
+
+        balances.unreserve(who, released);
+        Ok(released)
+    }
+
+    pub fn validate(&mut self, who: &T::AccountId) -> DispatchResult {
+
+        if self.bonded(who).is_zero() {
+            return Err(LedgerError::NotBonded);
+        }
+        self.nominators.remove(who);
+        self.validators.insert(who.clone());
+        Ok(())
+    }
+
+    pub fn nominate(&mut self, who: &T::AccountId, targets: Vec<T::AccountId>) -> DispatchResult {
+
+        if self.bonded(who).is_zero() {
+            return Err(LedgerError::NotBonded);
+        }
+        if targets.is_empty() {
+            return Err(LedgerError::NoNominationTargets);
+        }
+        if targets.iter().any(|t| !self.validators.contains(t)) {
+            return Err(LedgerError::NotValidator);
+        }
+        self.validators.remove(who);
+        self.nominators.insert(who.clone(), targets);
+        Ok(())
+    }
+
+    pub fn chill(&mut self, who: &T::AccountId) -> DispatchResult {
+
+        self.validators.remove(who);
+        self.nominators.remove(who);
+        Ok(())
+    }
+
+    /// Active stake that counts for rewards: validators with their own
+    /// bond, and nominators backing at least one current validator.
+    pub fn exposures(&self) -> Vec<(T::AccountId, T::Balance)> {
+
+        let validators = self.validators.iter().map(|v| (v.clone(), self.bonded(v)));
+        let nominators = self.nominators
+            .iter()
+            .filter(|(_, targets)| targets.iter().any(|t| self.validators.contains(t)))
+            .map(|(n, _)| (n.clone(), self.bonded(n)));
+
+        validators.chain(nominators).filter(|(_, stake)| !stake.is_zero()).collect()
+    }
+
+    /// Mints `reward` and pays it out pro rata to active stake. Returns the
+    /// amount actually minted, which is zero when nobody is staking.
+    pub fn reward(&self, reward: T::Balance, balances: &mut BalancesPallet<T>) -> T::Balance {
+
+        let exposures = self.exposures();
+        let total = exposures
+            .iter()
+            .fold(T::Balance::zero(), |acc, (_, stake)| acc.saturating_add(*stake));
+        if total.is_zero() {
+            return T::Balance::zero();
+        }
+
+        let mut minted = T::Balance::zero();
+        for (who, stake) in exposures {
+            let share = Self::pro_rata(reward, stake, total);
+            minted = minted.saturating_add(balances.deposit(&who, share));
+        }
+        minted
+    }
+
+    /// Removes up to `amount` from a staker, active stake first and then
+    /// unlocking chunks, and burns it from the reserve. Returns what was
+    /// slashed.
+    pub fn slash(&mut self, who: &T::AccountId, amount: T::Balance, balances: &mut BalancesPallet<T>) -> T::Balance {
+
+        let Some(ledger) = self.ledgers.get_mut(who) else {
+            return T::Balance::zero();
+        };
+
+        let mut remaining = amount;
+        let from_active = remaining.min(ledger.active);
+        ledger.active = ledger.active - from_active;
+        remaining = remaining - from_active;
+
+        for chunk in ledger.unlocking.iter_mut().rev() {
+            if remaining.is_zero() {
+                break;
+            }
+            let from_chunk = remaining.min(chunk.value);
+            chunk.value = chunk.value - from_chunk;
+            remaining = remaining - from_chunk;
+        }
+        ledger.unlocking.retain(|chunk| !chunk.value.is_zero());
+
+        balances.slash_reserved(who, amount - remaining)
+    }
+
+    /// Slashes a validator and everyone nominating it by `percent` of
+    /// their active stake, then removes the validator from the set.
+    pub fn slash_validator(&mut self, validator: &T::AccountId, percent: u8, balances: &mut BalancesPallet<T>) -> T::Balance {
+
+        let percent: T::Balance = NumCast::from(percent.min(100)).unwrap_or_else(T::Balance::zero);
+        let hundred: T::Balance = NumCast::from(100u8).unwrap_or_else(T::Balance::zero);
+
+        let mut offenders = vec![validator.clone()];
+        offenders.extend(
+            self.nominators
+                .iter()
+                .filter(|(_, targets)| targets.contains(validator))
+                .map(|(n, _)| n.clone()),
+        );
+
+        let mut total = T::Balance::zero();
+        for offender in offenders {
+            let amount = Self::pro_rata(percent, self.bonded(&offender), hundred);
+            total = total.saturating_add(self.slash(&offender, amount, balances));
+        }
+        self.validators.remove(validator);
+        total
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call<T>, balances: &mut BalancesPallet<T>, now: T::BlockNumber) -> DispatchResult {
+
+        match call {
+            Call::Bond { amount } => self.bond(caller, *amount, balances),
+            Call::Unbond { amount } => self.unbond(caller, *amount, now),
+            Call::WithdrawUnbonded => self.withdraw_unbonded(caller, now, balances).map(|_| ()),
+            Call::Validate => self.validate(caller),
+            Call::Nominate { targets } => self.nominate(caller, targets.clone()),
+            Call::Chill => self.chill(caller),
+        }
+    }
+
+    fn pro_rata(amount: T::Balance, part: T::Balance, total: T::Balance) -> T::Balance {
+
+        match amount.checked_mul(&part) {
+            Some(product) => product / total,
+            None => amount / total * part,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Pallet, BalancesPallet) {
+        let mut balances = BalancesPallet::new();
+        balances.set_balance(&"alice".to_string(), 1000);
+        balances.set_balance(&"bob".to_string(), 1000);
+        (Pallet::new(5, 100), balances)
+    }
+
+    #[test]
+    fn test_bond_unbond_withdraw() {
+        let (mut staking, mut balances) = setup();
+        let alice = "alice".to_string();
+
+        staking.bond(&alice, 400, &mut balances).unwrap();
+        assert_eq!(balances.get_balance(&alice), 600);
+        assert_eq!(balances.reserved_balance(&alice), 400);
+        assert_eq!(staking.bonded(&alice), 400);
+
+        staking.unbond(&alice, 150, 10).unwrap();
+        assert_eq!(staking.withdraw_unbonded(&alice, 14, &mut balances), Ok(0));
+        assert_eq!(staking.withdraw_unbonded(&alice, 15, &mut balances), Ok(150));
+        assert_eq!(balances.get_balance(&alice), 750);
+        assert_eq!(staking.bonded(&alice), 250);
+    }
+
+    #[test]
+    fn test_rewards_split_by_stake() {
+        let (mut staking, mut balances) = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        staking.bond(&alice, 300, &mut balances).unwrap();
+        staking.bond(&bob, 100, &mut balances).unwrap();
+        staking.validate(&alice).unwrap();
+        staking.nominate(&bob, vec![alice.clone()]).unwrap();
+
+        assert_eq!(staking.reward(100, &mut balances), 100);
+        assert_eq!(balances.get_balance(&alice), 775);
+        assert_eq!(balances.get_balance(&bob), 925);
+    }
+
+    #[test]
+    fn test_slash_validator_and_nominators() {
+        let (mut staking, mut balances) = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        staking.bond(&alice, 500, &mut balances).unwrap();
+        staking.bond(&bob, 200, &mut balances).unwrap();
+        staking.validate(&alice).unwrap();
+        staking.nominate(&bob, vec![alice.clone()]).unwrap();
+
+        let slashed = staking.slash_validator(&alice, 10, &mut balances);
+        assert_eq!(slashed, 70);
+        assert_eq!(staking.bonded(&alice), 450);
+        assert_eq!(staking.bonded(&bob), 180);
+        assert_eq!(balances.reserved_balance(&alice), 450);
+        assert_eq!(balances.total_issuance, 1930);
+        assert!(!staking.validators.contains(&alice));
+    }
+
+    #[test]
+    fn test_nominate_requires_validator() {
+        let (mut staking, mut balances) = setup();
+        let bob = "bob".to_string();
+
+        staking.bond(&bob, 100, &mut balances).unwrap();
+        assert!(staking.nominate(&bob, vec!["alice".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+use crate::error::ErrorCode;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    NoTransaction,
+    Io(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NoTransaction => write!(f, "No storage transaction is open"),
+            StorageError::Io(detail) => write!(f, "Storage I/O failed: {}", detail),
+            StorageError::Corrupt(detail) => write!(f, "Stored data is corrupt: {}", detail),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e.to_string())
+    }
+}
+
+impl ErrorCode for StorageError {
+    fn code(&self) -> u16 {
+        match self {
+            StorageError::NoTransaction => 6001,
+            StorageError::Io(_) => 6002,
+            StorageError::Corrupt(_) => 6003,
+        }
+    }
+
+    fn module(&self) -> &'static str {
+        "storage"
+    }
+
+    fn http_status(&self) -> u16 {
+        500
+    }
+}
+
+/// Byte-keyed state store. Writes are infallible; backends that persist
+/// do so on `flush`.
+pub trait Storage: fmt::Debug {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+    fn put(&mut self, key: &[u8], value: Vec<u8>);
+    fn delete(&mut self, key: &[u8]);
+
+    /// Every entry whose key starts with `prefix`, in key order.
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)>;
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        (**self).get(key)
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) {
+        (**self).put(key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        (**self).delete(key)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (**self).scan_prefix(prefix)
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        (**self).flush()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.get(key).cloned()
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) {
+        self.entries.insert(key.to_vec(), value);
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        self.entries.remove(key);
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.entries
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// Entries held in memory and written to a JSON file of hex-encoded pairs
+/// on `flush`. The file is replaced atomically, so a crash mid-write keeps
+/// the previous contents.
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+    memory: MemoryStorage,
+    dirty: bool,
+}
+
+impl FileStorage {
+    /// Opens `path`, loading its entries if the file exists.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let mut memory = MemoryStorage::new();
+        if path.exists() {
+            let contents = fs::read_to_string(&path)?;
+            let encoded: BTreeMap<String, String> = serde_json::from_str(&contents)
+                .map_err(|e| StorageError::Corrupt(e.to_string()))?;
+            for (key, value) in encoded {
+                let key = hex::decode(&key).map_err(|e| StorageError::Corrupt(e.to_string()))?;
+                let value = hex::decode(&value).map_err(|e| StorageError::Corrupt(e.to_string()))?;
+                memory.put(&key, value);
+            }
+        }
+        Ok(Self { path, memory, dirty: false })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.memory.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) {
+        self.memory.put(key, value);
+        self.dirty = true;
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        self.memory.delete(key);
+        self.dirty = true;
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.memory.scan_prefix(prefix)
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let encoded: BTreeMap<String, String> = self.memory.entries
+            .iter()
+            .map(|(key, value)| (hex::encode(key), hex::encode(value)))
+            .collect();
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&encoded).unwrap())?;
+        fs::rename(&tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Uncommitted writes in a layer. `None` marks a deletion.
+type Changes = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Stacks nested transactions on top of a backend. Reads see the innermost
+/// layer first. Committing folds a layer into its parent, or into the
+/// backend for the outermost one; rolling back discards it. With no
+/// transaction open, writes go straight to the backend.
+#[derive(Debug)]
+pub struct OverlayedStorage<S: Storage> {
+    backend: S,
+    layers: Vec<Changes>,
+}
+
+impl<S: Storage> OverlayedStorage<S> {
+    pub fn new(backend: S) -> Self {
+        Self { backend, layers: Vec::new() }
+    }
+
+    pub fn backend(&self) -> &S {
+        &self.backend
+    }
+
+    pub fn transaction_depth(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn start_transaction(&mut self) {
+        self.layers.push(Changes::new());
+    }
+
+    pub fn commit_transaction(&mut self) -> Result<(), StorageError> {
+        let changes = self.layers.pop().ok_or(StorageError::NoTransaction)?;
+        match self.layers.last_mut() {
+            Some(parent) => parent.extend(changes),
+            None => {
+                for (key, value) in changes {
+                    match value {
+                        Some(value) => self.backend.put(&key, value),
+                        None => self.backend.delete(&key),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rollback_transaction(&mut self) -> Result<(), StorageError> {
+        self.layers.pop().map(|_| ()).ok_or(StorageError::NoTransaction)
+    }
+}
+
+impl<S: Storage> Storage for OverlayedStorage<S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        for layer in self.layers.iter().rev() {
+            if let Some(value) = layer.get(key) {
+                return value.clone();
+            }
+        }
+        self.backend.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) {
+        match self.layers.last_mut() {
+            Some(layer) => {
+                layer.insert(key.to_vec(), Some(value));
+            }
+            None => self.backend.put(key, value),
+        }
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        match self.layers.last_mut() {
+            Some(layer) => {
+                layer.insert(key.to_vec(), None);
+            }
+            None => self.backend.delete(key),
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut merged: BTreeMap<Vec<u8>, Option<Vec<u8>>> = self.backend
+            .scan_prefix(prefix)
+            .into_iter()
+            .map(|(key, value)| (key, Some(value)))
+            .collect();
+        for layer in &self.layers {
+            for (key, value) in layer.range(prefix.to_vec()..).take_while(|(key, _)| key.starts_with(prefix)) {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+        merged.into_iter().filter_map(|(key, value)| value.map(|value| (key, value))).collect()
+    }
+
+    /// Flushes the backend. Open transactions are not included.
+    fn flush(&mut self) -> Result<(), StorageError> {
+        self.backend.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage_prefix_scan() {
+        let mut storage = MemoryStorage::new();
+        storage.put(b"a/1", vec![1]);
+        storage.put(b"a/2", vec![2]);
+        storage.put(b"b/1", vec![3]);
+        storage.delete(b"a/1");
+
+        assert_eq!(storage.scan_prefix(b"a/"), vec![(b"a/2".to_vec(), vec![2])]);
+        assert_eq!(storage.get(b"b/1"), Some(vec![3]));
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[test]
+    fn test_nested_overlays() {
+        let mut storage = OverlayedStorage::new(MemoryStorage::new());
+        storage.put(b"k", vec![0]);
+
+        storage.start_transaction();
+        storage.put(b"k", vec![1]);
+        storage.start_transaction();
+        storage.delete(b"k");
+        storage.put(b"j", vec![2]);
+        assert_eq!(storage.get(b"k"), None);
+        storage.rollback_transaction().unwrap();
+
+        assert_eq!(storage.get(b"k"), Some(vec![1]));
+        assert_eq!(storage.get(b"j"), None);
+        assert_eq!(storage.backend().get(b"k"), Some(vec![0]));
+
+        storage.start_transaction();
+        storage.put(b"j", vec![3]);
+        storage.commit_transaction().unwrap();
+        storage.commit_transaction().unwrap();
+        assert_eq!(storage.backend().scan_prefix(b""), vec![(b"j".to_vec(), vec![3]), (b"k".to_vec(), vec![1])]);
+        assert_eq!(storage.commit_transaction(), Err(StorageError::NoTransaction));
+    }
+
+    #[test]
+    fn test_file_storage_round_trip() {
+        let path = std::env::temp_dir().join(format!("phlop_storage_{}.json", std::process::id()));
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.put(b"runtime/fee_per_weight", b"2".to_vec());
+        storage.flush().unwrap();
+
+        let reopened = FileStorage::open(&path).unwrap();
+        assert_eq!(reopened.get(b"runtime/fee_per_weight"), Some(b"2".to_vec()));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! Server-sent event streams that clients can resume. A stream numbers its
+//! events and keeps the latest `capacity` of them. Each event goes out with
+//! a resume token; a client that reconnects with the last token it saw
+//! (as `Last-Event-ID`, which browsers send by themselves, or `?resume=`)
+//! gets every event it missed. If those were already dropped, or the token
+//! is from before a server restart, it gets a `reset` event and should
+//! reload its state.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::error::RequestError;
+
+pub const DEFAULT_CAPACITY: usize = 1024;
+/// How long a quiet stream waits before sending a keep-alive comment.
+pub const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// Reconnection delay suggested to browsers.
+const RETRY_MS: u64 = 3000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEvent {
+    pub id: u64,
+    pub kind: String,
+    /// JSON payload.
+    pub data: String,
+}
+
+#[derive(Debug)]
+struct Buffer {
+    next_id: u64,
+    events: VecDeque<StreamEvent>,
+    capacity: usize,
+}
+
+/// A shared, cloneable stream. Publishers and subscribers may be on any
+/// thread.
+#[derive(Debug, Clone)]
+pub struct EventStream {
+    /// Tells this run's tokens from an earlier run's.
+    epoch: u64,
+    shared: Arc<(Mutex<Buffer>, Condvar)>,
+}
+
+impl EventStream {
+    pub fn new(capacity: usize) -> Self {
+        let epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        EventStream {
+            epoch,
+            shared: Arc::new((
+                Mutex::new(Buffer { next_id: 1, events: VecDeque::new(), capacity: capacity.max(1) }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Appends an event and wakes subscribers. Returns its ID.
+    pub fn publish(&self, kind: &str, data: &impl Serialize) -> u64 {
+        let data = serde_json::to_string(data).expect("stream events are serializable");
+        let (buffer, published) = &*self.shared;
+        let mut buffer = buffer.lock().unwrap();
+        let id = buffer.next_id;
+        buffer.next_id += 1;
+        if buffer.events.len() == buffer.capacity {
+            buffer.events.pop_front();
+        }
+        buffer.events.push_back(StreamEvent { id, kind: kind.to_string(), data });
+        published.notify_all();
+        id
+    }
+
+    pub fn token(&self, id: u64) -> String {
+        format!("{:x}-{}", self.epoch, id)
+    }
+
+    /// Follows the stream from after the event `token` names, or from the
+    /// next event without one. A malformed token is an error; a stale one
+    /// makes the first frame a `reset`.
+    pub fn subscribe(&self, token: Option<&str>) -> Result<Subscription, RequestError> {
+        let buffer = self.shared.0.lock().unwrap();
+        let latest = buffer.next_id - 1;
+        let Some(token) = token.filter(|token| !token.is_empty()) else {
+            return Ok(Subscription { stream: self.clone(), after: latest, reset: false, started: false });
+        };
+        let (epoch, id) = token
+            .split_once('-')
+            .and_then(|(epoch, id)| Some((u64::from_str_radix(epoch, 16).ok()?, id.parse::<u64>().ok()?)))
+            .ok_or_else(|| RequestError::InvalidQuery(format!("invalid resume token '{}'", token)))?;
+        // Still resumable if nothing after `id` has been dropped.
+        let oldest = buffer.events.front().map_or(buffer.next_id, |event| event.id);
+        let resumable = epoch == self.epoch && id <= latest && id + 1 >= oldest;
+        Ok(Subscription {
+            stream: self.clone(),
+            after: if resumable { id } else { latest },
+            reset: !resumable,
+            started: false,
+        })
+    }
+}
+
+/// A client's place in a stream.
+#[derive(Debug)]
+pub struct Subscription {
+    stream: EventStream,
+    after: u64,
+    reset: bool,
+    started: bool,
+}
+
+impl Subscription {
+    /// The next `text/event-stream` text to send: events after the
+    /// client's place, waiting up to `timeout` for some, else a keep-alive
+    /// comment. The first frame also carries the retry delay and any
+    /// `reset`.
+    pub fn next_frame(&mut self, timeout: Duration) -> String {
+        let mut frame = String::new();
+        if !self.started {
+            self.started = true;
+            frame.push_str(&format!("retry: {}\n\n", RETRY_MS));
+        }
+        if self.reset {
+            self.reset = false;
+            frame.push_str(&format!("id: {}\nevent: reset\ndata: {{}}\n\n", self.stream.token(self.after)));
+            return frame;
+        }
+
+        let (buffer, published) = &*self.stream.shared;
+        let deadline = Instant::now() + timeout;
+        let mut buffer = buffer.lock().unwrap();
+        while buffer.next_id - 1 <= self.after {
+            let now = Instant::now();
+            if now >= deadline {
+                frame.push_str(": keep-alive\n\n");
+                return frame;
+            }
+            buffer = published.wait_timeout(buffer, deadline - now).unwrap().0;
+        }
+        // Fell behind by more than the buffer holds while connected.
+        if buffer.events.front().is_some_and(|event| event.id > self.after + 1) {
+            self.after = buffer.next_id - 1;
+            frame.push_str(&format!("id: {}\nevent: reset\ndata: {{}}\n\n", self.stream.token(self.after)));
+            return frame;
+        }
+        let after = self.after;
+        for event in buffer.events.iter().filter(|event| event.id > after) {
+            frame.push_str(&format!("id: {}\nevent: {}\ndata: {}\n\n", self.stream.token(event.id), event.kind, event.data));
+        }
+        self.after = buffer.next_id - 1;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Duration = Duration::ZERO;
+
+    #[test]
+    fn test_resume_delivers_missed_events() {
+        let stream = EventStream::new(8);
+        let mut live = stream.subscribe(None).unwrap();
+        let first = stream.publish("block", &1);
+        stream.publish("block", &2);
+
+        let frame = live.next_frame(NOW);
+        assert!(frame.starts_with("retry: "));
+        assert!(frame.contains("event: block\ndata: 1\n") && frame.contains("data: 2\n"));
+        assert!(live.next_frame(NOW).starts_with(": keep-alive"));
+
+        let mut resumed = stream.subscribe(Some(&stream.token(first))).unwrap();
+        let frame = resumed.next_frame(NOW);
+        assert!(!frame.contains("data: 1\n"));
+        assert!(frame.contains(&format!("id: {}\nevent: block\ndata: 2\n", stream.token(first + 1))));
+    }
+
+    #[test]
+    fn test_stale_token_resets() {
+        let stream = EventStream::new(2);
+        let first = stream.publish("block", &1);
+        for n in 2..=4 {
+            stream.publish("block", &n);
+        }
+        let mut dropped = stream.subscribe(Some(&stream.token(first))).unwrap();
+        assert!(dropped.next_frame(NOW).contains("event: reset"));
+        assert!(dropped.next_frame(NOW).contains("keep-alive"));
+
+        let restarted = EventStream::new(2);
+        let mut other_run = restarted.subscribe(Some(&stream.token(4))).unwrap();
+        assert!(other_run.next_frame(NOW).contains("event: reset"));
+        assert!(stream.subscribe(Some("not-a-token")).is_err());
+    }
+
+    #[test]
+    fn test_waiting_subscriber_wakes_on_publish() {
+        let stream = EventStream::new(8);
+        let mut subscription = stream.subscribe(None).unwrap();
+        let publisher = stream.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            publisher.publish("mined", &serde_json::json!({ "block": 1 }));
+        });
+        let frame = subscription.next_frame(Duration::from_secs(5));
+        handle.join().unwrap();
+        assert!(frame.contains("event: mined\ndata: {\"block\":1}\n"));
+    }
+}
@@ -0,0 +1,387 @@
+//! Headers-first block download. Headers are checked first: they must link
+//! up and carry an RPS seal that replays. Bodies are then fetched in
+//! batches from several peers at once, matched against their headers and
+//! applied in order with full state execution. Progress can be saved and
+//! picked up again after a restart.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::Blockchain;
+use crate::merkle::Hash;
+use crate::rps_mining::RPSMiner;
+use crate::snapshot::SyncError;
+use crate::transaction::{Block, BlockHeader};
+
+pub const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+pub const MAX_BODIES_PER_REQUEST: usize = 16;
+/// A body request unanswered for this long goes to another peer.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a sync has fetched but not yet applied, to resume from after a
+/// restart. Applied blocks are left out: they are already in the chain, so
+/// resuming needs a chain kept up to where the save was made.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncProgress {
+    pub headers: Vec<BlockHeader>,
+    pub bodies: BTreeMap<u32, Block>,
+}
+
+impl SyncProgress {
+    pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+        let json = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Replaces the file atomically, so an interrupted save keeps the
+    /// previous progress.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string(self).expect("progress is serializable"))?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncStatus {
+    pub start_height: u32,
+    pub applied_height: u32,
+    pub header_height: u32,
+    pub downloaded: usize,
+    pub in_flight: usize,
+    pub percent: u32,
+}
+
+/// Download state for one catch-up. Owns no connections: the caller feeds
+/// it headers and bodies and sends the requests it hands out.
+pub struct HeaderSync {
+    start: (u32, Hash),
+    headers: Vec<BlockHeader>,
+    bodies: BTreeMap<u32, Block>,
+    in_flight: BTreeMap<u32, (u64, Instant)>,
+    applied: u32,
+    /// Miner state after the last accepted header, to replay seals with.
+    verifier: RPSMiner,
+    /// Set when a header changed the player count: no more headers are
+    /// taken until the bodies before it are applied.
+    held: bool,
+}
+
+impl HeaderSync {
+    /// Starts from `blockchain`'s tip.
+    pub fn new(blockchain: &Blockchain) -> Self {
+        let tip = blockchain.get_latest_block();
+        Self {
+            start: (tip.index, tip.hash.clone()),
+            headers: Vec::new(),
+            bodies: BTreeMap::new(),
+            in_flight: BTreeMap::new(),
+            applied: tip.index,
+            verifier: blockchain.rps_miner.clone(),
+            held: false,
+        }
+    }
+
+    /// Starts from `blockchain`'s tip, reusing the part of `progress` that
+    /// extends it. Whatever does not check out again is dropped.
+    pub fn resume(blockchain: &Blockchain, progress: SyncProgress) -> Self {
+        let mut sync = Self::new(blockchain);
+        let (height, hash) = sync.start.clone();
+        let SyncProgress { headers, mut bodies } = progress;
+        if let Some(position) = headers.iter().position(|header| header.index == height + 1 && header.previous_hash == hash) {
+            let mut resumed = headers;
+            resumed.drain(..position);
+            // A batch that no longer checks out is fetched again instead.
+            let _ = sync.add_headers(resumed);
+        }
+        bodies.retain(|index, block| sync.header_at(*index).is_some_and(|header| matches_header(block, header)));
+        sync.bodies = bodies;
+        sync
+    }
+
+    /// Height and hash the next header must follow.
+    pub fn best(&self) -> (u32, Hash) {
+        match self.headers.last() {
+            Some(header) => (header.index, header.hash.clone()),
+            None => self.start.clone(),
+        }
+    }
+
+    /// Whether `header` continues the headers accepted so far.
+    pub fn extends(&self, header: &BlockHeader) -> bool {
+        let (height, hash) = self.best();
+        header.index == height + 1 && header.previous_hash == hash
+    }
+
+    /// Checks and appends a batch of headers: each must link to the one
+    /// before, hash correctly and carry a seal that replays. The whole
+    /// batch is rejected at the first bad header. Returns how many were
+    /// taken, which is fewer than offered when the batch was cut short.
+    ///
+    /// A header's player count is never trusted: seals are replayed with
+    /// the count set locally. A header with another count cuts the batch
+    /// short, and the sync holds until the bodies before it are applied and
+    /// governance has had its say. If the count still differs then, the
+    /// header is rejected.
+    pub fn add_headers(&mut self, mut headers: Vec<BlockHeader>) -> Result<usize, SyncError> {
+        if self.held {
+            return Ok(0);
+        }
+        let mut verifier = self.verifier.clone();
+        let (mut height, mut hash) = self.best();
+        let mut accepted = headers.len();
+        for (position, header) in headers.iter().enumerate() {
+            let index = header.index;
+            if index != height + 1 || header.previous_hash != hash || header.hash != header.calculate_hash() {
+                return Err(SyncError::InvalidHeader { index });
+            }
+            let Some(result) = header.rps_mining_result.as_ref().filter(|result| result.success && result.player_count > 0) else {
+                return Err(SyncError::InvalidSeal { index });
+            };
+            if result.player_count != verifier.config.total_players {
+                if position == 0 && self.applied == height {
+                    return Err(SyncError::InvalidSeal { index });
+                }
+                accepted = position;
+                self.held = true;
+                break;
+            }
+            if !verifier.verify_seal(&header.seal_data(), result) {
+                return Err(SyncError::InvalidSeal { index });
+            }
+            verifier.advance(result);
+            (height, hash) = (index, header.hash.clone());
+        }
+
+        headers.truncate(accepted);
+        self.verifier = verifier;
+        self.headers.extend(headers);
+        Ok(accepted)
+    }
+
+    /// False while held on a change to the player count.
+    pub fn wants_headers(&self) -> bool {
+        !self.held
+    }
+
+    /// Hashes of the next bodies for `peer` to send, at most
+    /// `MAX_BODIES_PER_REQUEST`, lowest first. Requests other peers have
+    /// left unanswered past `REQUEST_TIMEOUT` are handed out again.
+    pub fn next_requests(&mut self, peer: u64, peer_height: u32, now: Instant) -> Vec<Hash> {
+        let wanted: Vec<(u32, Hash)> = self.headers
+            .iter()
+            .filter(|header| header.index > self.applied && header.index <= peer_height)
+            .filter(|header| !self.bodies.contains_key(&header.index))
+            .filter(|header| match self.in_flight.get(&header.index) {
+                Some((_, asked)) => now.duration_since(*asked) >= REQUEST_TIMEOUT,
+                None => true,
+            })
+            .take(MAX_BODIES_PER_REQUEST)
+            .map(|header| (header.index, header.hash.clone()))
+            .collect();
+        for (index, _) in &wanted {
+            self.in_flight.insert(*index, (peer, now));
+        }
+        wanted.into_iter().map(|(_, hash)| hash).collect()
+    }
+
+    /// Stores bodies that match their headers. A body with no header or
+    /// that does not match rejects the batch.
+    pub fn add_bodies(&mut self, blocks: Vec<Block>) -> Result<usize, SyncError> {
+        for block in &blocks {
+            let header = self.header_at(block.index).ok_or(SyncError::BodyMismatch { index: block.index })?;
+            if !matches_header(block, header) {
+                return Err(SyncError::BodyMismatch { index: block.index });
+            }
+        }
+        let added = blocks.len();
+        for block in blocks {
+            self.in_flight.remove(&block.index);
+            self.bodies.insert(block.index, block);
+        }
+        Ok(added)
+    }
+
+    /// Frees the requests `peer` did not answer, for others to take.
+    pub fn peer_lost(&mut self, peer: u64) {
+        self.in_flight.retain(|_, (asked, _)| *asked != peer);
+    }
+
+    /// Imports the downloaded bodies that continue `blockchain`, in order,
+    /// and lets go of them. A body that fails to import means the headers
+    /// were bad; the sync cannot go on and should be dropped.
+    pub fn apply_ready(&mut self, blockchain: &mut Blockchain) -> Result<usize, SyncError> {
+        let mut applied = 0;
+        loop {
+            let next = blockchain.get_latest_block().index + 1;
+            let Some(block) = self.bodies.remove(&next) else { break };
+            blockchain.import_block(block)?;
+            self.applied = next;
+            applied += 1;
+        }
+        // Caught up: go on from the chain's own miner, which has any player
+        // count set by governance.
+        if self.is_complete() {
+            self.verifier = blockchain.rps_miner.clone();
+            self.held = false;
+        }
+        Ok(applied)
+    }
+
+    /// True once every accepted header has been applied.
+    pub fn is_complete(&self) -> bool {
+        self.applied >= self.best().0
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        let header_height = self.best().0;
+        let total = header_height.saturating_sub(self.start.0);
+        let done = self.applied.saturating_sub(self.start.0);
+        SyncStatus {
+            start_height: self.start.0,
+            applied_height: self.applied,
+            header_height,
+            downloaded: self.bodies.range(self.applied + 1..).count(),
+            in_flight: self.in_flight.len(),
+            percent: if total == 0 { 100 } else { (done as u64 * 100 / total as u64) as u32 },
+        }
+    }
+
+    /// The headers and bodies past the applied height.
+    pub fn progress(&self) -> SyncProgress {
+        let headers = self.headers.iter().filter(|header| header.index > self.applied).cloned().collect();
+        let bodies = self.bodies.range(self.applied + 1..).map(|(index, block)| (*index, block.clone())).collect();
+        SyncProgress { headers, bodies }
+    }
+
+    fn header_at(&self, index: u32) -> Option<&BlockHeader> {
+        let first = self.headers.first()?.index;
+        self.headers.get(index.checked_sub(first)? as usize)
+    }
+}
+
+fn matches_header(block: &Block, header: &BlockHeader) -> bool {
+    block.hash == header.hash && block.is_self_consistent()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+
+    fn source_chain(blocks: u32) -> Blockchain {
+        let mut blockchain = Blockchain::new();
+        for nonce in 1..=blocks {
+            blockchain.add_transaction(Transaction::new("alice".to_string(), "bob".to_string(), 10, nonce)).unwrap();
+            blockchain.mine_pending_transactions("miner".to_string()).unwrap();
+        }
+        blockchain
+    }
+
+    fn headers(blockchain: &Blockchain) -> Vec<BlockHeader> {
+        blockchain.chain[1..].iter().map(Block::header).collect()
+    }
+
+    #[test]
+    fn test_headers_first_sync_out_of_order_bodies() {
+        let source = source_chain(20);
+        let mut target = Blockchain::new();
+        let mut sync = HeaderSync::new(&target);
+        assert_eq!(sync.add_headers(headers(&source)).unwrap(), 20);
+
+        let now = Instant::now();
+        let first = sync.next_requests(1, 20, now);
+        let second = sync.next_requests(2, 20, now);
+        assert_eq!((first.len(), second.len()), (16, 4));
+        assert!(sync.next_requests(3, 20, now).is_empty());
+
+        let bodies = |hashes: &[Hash]| -> Vec<Block> {
+            source.chain.iter().filter(|block| hashes.contains(&block.hash)).cloned().collect()
+        };
+        sync.add_bodies(bodies(&second)).unwrap();
+        assert_eq!(sync.apply_ready(&mut target).unwrap(), 0);
+        sync.add_bodies(bodies(&first)).unwrap();
+        assert_eq!(sync.apply_ready(&mut target).unwrap(), 20);
+
+        assert!(sync.is_complete());
+        assert_eq!(sync.status().percent, 100);
+        assert_eq!(target.get_latest_block().hash, source.get_latest_block().hash);
+        assert_eq!(target.get_balance(&"bob".to_string()), source.get_balance(&"bob".to_string()));
+    }
+
+    #[test]
+    fn test_rejects_forged_seal_and_mismatched_body() {
+        let source = source_chain(3);
+        let target = Blockchain::new();
+        let mut sync = HeaderSync::new(&target);
+
+        let mut forged = headers(&source);
+        let result = forged[1].rps_mining_result.as_mut().unwrap();
+        result.rounds += 1;
+        forged[1].hash = forged[1].calculate_hash();
+        forged[2].previous_hash = forged[1].hash.clone();
+        forged[2].hash = forged[2].calculate_hash();
+        assert_eq!(sync.add_headers(forged), Err(SyncError::InvalidSeal { index: 2 }));
+        assert_eq!(sync.best().0, 0);
+
+        sync.add_headers(headers(&source)).unwrap();
+        let mut tampered = source.chain[1].clone();
+        tampered.transactions.pop();
+        assert_eq!(sync.add_bodies(vec![tampered]), Err(SyncError::BodyMismatch { index: 1 }));
+    }
+
+    #[test]
+    fn test_holds_on_player_count_from_header() {
+        let source = source_chain(4);
+        let mut target = Blockchain::new();
+        let mut sync = HeaderSync::new(&target);
+
+        let mut changed = headers(&source);
+        changed[3].rps_mining_result.as_mut().unwrap().player_count = 10;
+        changed[3].hash = changed[3].calculate_hash();
+        assert_eq!(sync.add_headers(changed.clone()).unwrap(), 3);
+        assert!(!sync.wants_headers());
+        assert_eq!(sync.add_headers(changed[3..].to_vec()).unwrap(), 0);
+
+        sync.add_bodies(source.chain[1..=3].to_vec()).unwrap();
+        assert_eq!(sync.apply_ready(&mut target).unwrap(), 3);
+        assert!(sync.wants_headers());
+        assert_eq!(sync.add_headers(changed[3..].to_vec()), Err(SyncError::InvalidSeal { index: 4 }));
+        assert_eq!(sync.add_headers(headers(&source)[3..].to_vec()).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_resume_after_interruption() {
+        let source = source_chain(6);
+        let mut target = Blockchain::new();
+        let mut sync = HeaderSync::new(&target);
+        sync.add_headers(headers(&source)).unwrap();
+        sync.add_bodies(source.chain[1..=2].to_vec()).unwrap();
+        sync.add_bodies(source.chain[4..=4].to_vec()).unwrap();
+        sync.apply_ready(&mut target).unwrap();
+        assert_eq!(sync.status().percent, 33);
+
+        // Only what is not in the chain yet is kept.
+        let progress = sync.progress();
+        assert_eq!(progress.headers.first().map(|header| header.index), Some(3));
+        assert_eq!(progress.bodies.keys().copied().collect::<Vec<_>>(), vec![4]);
+
+        // A restart keeps the chain; the saved headers and bodies past it
+        // are reused rather than fetched again.
+        let path = std::env::temp_dir().join(format!("phlop-sync-{}.json", std::process::id()));
+        progress.save(&path).unwrap();
+        let mut sync = HeaderSync::resume(&target, SyncProgress::load(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sync.best().0, 6);
+        assert_eq!(sync.apply_ready(&mut target).unwrap(), 0);
+
+        let missing = sync.next_requests(1, 6, Instant::now());
+        assert_eq!(missing, vec![source.chain[3].hash.clone(), source.chain[5].hash.clone(), source.chain[6].hash.clone()]);
+        sync.peer_lost(1);
+        assert_eq!(sync.next_requests(2, 6, Instant::now()).len(), 3);
+    }
+}
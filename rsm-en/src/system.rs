@@ -1,44 +1,97 @@
 use std::collections::BTreeMap;
+use std::fmt::Debug;
+use num_traits::{CheckedAdd, One, PrimInt, Unsigned, Zero};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use crate::runtime::{DefaultConfig, DispatchResult};
+
+/// Types shared by every pallet. Implement this on a marker type to pick
+/// how accounts and block numbers are represented on a chain.
+pub trait Config {
+    type AccountId: Ord + Clone + Debug + Serialize + DeserializeOwned;
+    type BlockNumber: PrimInt + Unsigned + Debug + Serialize + DeserializeOwned;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Call {
+    Remark { remark: String },
+}
+
+impl Call {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::Remark { remark } => 1 + remark.len() as u64 / 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Event<T: Config = DefaultConfig> {
+    NonceIncremented { who: T::AccountId, nonce: u32 },
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Pallet {
-    block_number: u32,
-    nonce: BTreeMap<String, u32>
+#[serde(bound = "")]
+pub struct Pallet<T: Config = DefaultConfig> {
+    block_number: T::BlockNumber,
+    nonce: BTreeMap<T::AccountId, u32>,
+    /// Emitted since the runtime last took them. Not part of state.
+    #[serde(skip)]
+    events: Vec<Event<T>>,
 }
 
-impl Pallet{
+impl<T: Config> Default for Pallet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Config> Pallet<T> {
 
     pub fn new() -> Self {
 
         Self {
 
-            block_number: 0,
-            nonce: BTreeMap::new()
+            block_number: T::BlockNumber::zero(),
+            nonce: BTreeMap::new(),
+            events: Vec::new(),
 
         }
     } 
 
-    #[allow(dead_code)]
-    pub fn get_block_number(&self) -> u32 {
+    pub fn get_block_number(&self) -> T::BlockNumber {
 
         self.block_number
     }
 
-    pub fn inc_block_number(&mut self, _who: &String){
+    pub fn inc_block_number(&mut self, _who: &T::AccountId){
+
+        self.block_number = self.block_number.checked_add(&T::BlockNumber::one()).unwrap();
+    }
+
+    pub fn inc_nonce(&mut self, who: &T::AccountId){
 
-        self.block_number = self.block_number.checked_add(1).unwrap();
+        let nonce: u32 = self.nonce.get(who).unwrap_or(&0) + 1;
+        self.nonce.insert(who.clone(), nonce);
+        self.events.push(Event::NonceIncremented { who: who.clone(), nonce });
     }
 
-    pub fn inc_nonce(&mut self, who: &String){
+    pub fn take_events(&mut self) -> Vec<Event<T>> {
 
-        let nonce: &u32 = self.nonce.get(who).unwrap_or(&0);
-        self.nonce.insert(who.clone(), nonce + 1);
+        std::mem::take(&mut self.events)
     }
 
-    pub fn get_nonce(&self, who: &String) -> u32 {
+    pub fn get_nonce(&self, who: &T::AccountId) -> u32 {
 
         *self.nonce.get(who).unwrap_or(&0)
     }
 
-}
\ No newline at end of file
+    pub fn dispatch(&mut self, _caller: &T::AccountId, call: &Call) -> DispatchResult {
+
+        match call {
+            Call::Remark { .. } => Ok(()),
+        }
+    }
+
+}
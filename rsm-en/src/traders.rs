@@ -112,9 +112,7 @@ impl Trader {
 
                     if price_change > 0.01 {
                         OrderSide::Sell
-                    } else if price_change < -0.01 {
-                        OrderSide::Buy
-                    } else if random < 0.5 {
+                    } else if price_change < -0.01 || random < 0.5 {
                         OrderSide::Buy
                     } else {
                         OrderSide::Sell
@@ -131,9 +129,7 @@ impl Trader {
 
                 if self.position > 0.0 {
                     OrderSide::Sell
-                } else if self.position < 0.0 {
-                    OrderSide::Buy
-                } else if random < 0.5 {
+                } else if self.position < 0.0 || random < 0.5 {
                     OrderSide::Buy
                 } else {
                     OrderSide::Sell
@@ -151,9 +147,7 @@ impl Trader {
 
                 if price_change > 0.02 {
                     OrderSide::Sell
-                } else if price_change < -0.02 {
-                    OrderSide::Buy
-                } else if random < 0.5 {
+                } else if price_change < -0.02 || random < 0.5 {
                     OrderSide::Buy
                 } else {
                     OrderSide::Sell
@@ -204,6 +198,12 @@ pub struct TraderPopulation {
     pub total_capital: f64,
 }
 
+impl Default for TraderPopulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TraderPopulation {
     pub fn new() -> Self {
         let mut traders = Vec::new();
@@ -360,7 +360,7 @@ mod tests {
     #[test]
     fn test_population_creation() {
         let pop = TraderPopulation::new();
-        assert!(pop.traders.len() > 0);
+        assert!(!pop.traders.is_empty());
         assert!(pop.total_capital > 0.0);
     }
 
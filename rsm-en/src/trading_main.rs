@@ -63,7 +63,7 @@ fn main() {
     println!("   Spread Width:       50 basis points");
     println!("   Max Inventory:      2.0 units");
     println!("   Inventory Skew:     0.05 (quote adjustment per unit)");
-    println!("");
+    println!();
     println!("   EKF Configuration:");
     println!("     State Vector:     [price, velocity, acceleration]");
     println!("     Observations:     [price, velocity] (no noisy acceleration)");
@@ -71,7 +71,7 @@ fn main() {
     println!("     Measurement R:    0.5 (observation noise)");
     println!("     Nesterov Beta:    0.9 (momentum coefficient)");
     println!("     Nesterov Gamma:   0.999 (lookahead factor)");
-    println!("");
+    println!();
     println!("   Market Making Logic:");
     println!("     - Estimate true price + velocity using EKF");
     println!("     - Post bid below estimate, ask above estimate");
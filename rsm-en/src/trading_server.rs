@@ -1,12 +1,15 @@
 
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
+use std::io::Write;
 use std::thread;
 use tiny_http::{Server, Response, Method, Header};
 use serde::{Deserialize, Serialize};
 
+use rsm_en::error::{ErrorCode, RequestError};
 use rsm_en::live_trading::{LiveTradingEngine, StrategyParams, MarketUpdate};
 use rsm_en::market::OrderSide;
+use rsm_en::stream::{self, EventStream};
 
 #[derive(Debug, Deserialize)]
 struct StartRequest {
@@ -36,6 +39,8 @@ struct PollRequest {
 }
 
 type UpdateQueue = Arc<Mutex<HashMap<String, Vec<MarketUpdate>>>>;
+/// Each session's updates as a resumable event stream.
+type Streams = Arc<Mutex<HashMap<String, EventStream>>>;
 
 fn main() {
     println!("PhlopChain Live Trading Server");
@@ -44,6 +49,7 @@ fn main() {
     let server = Server::http("127.0.0.1:8080").unwrap();
     let engine = Arc::new(LiveTradingEngine::new());
     let updates: UpdateQueue = Arc::new(Mutex::new(HashMap::new()));
+    let streams: Streams = Arc::new(Mutex::new(HashMap::new()));
 
     println!("🚀 Server running on http://127.0.0.1:8080");
     println!("📊 Trading Dashboard: http://127.0.0.1:8080/trading.html");
@@ -53,9 +59,10 @@ fn main() {
     for request in server.incoming_requests() {
         let engine = Arc::clone(&engine);
         let updates = Arc::clone(&updates);
+        let streams = Arc::clone(&streams);
         
         thread::spawn(move || {
-            handle_request(request, engine, updates);
+            handle_request(request, engine, updates, streams);
         });
     }
 }
@@ -64,21 +71,21 @@ fn handle_request(
     mut request: tiny_http::Request,
     engine: Arc<LiveTradingEngine>,
     updates: UpdateQueue,
+    streams: Streams,
 ) {
     let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
     let method = request.method().clone();
     let cors_header = Header::from_bytes(&b"Access-Control-Allow-Origin"[..], &b"*"[..]).unwrap();
     let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
 
-    match (method, url.as_str()) {
+    match (method, path) {
 
         (Method::Get, "/") | (Method::Get, "/index.html") => {
             serve_file(request, "static/index.html");
-            return;
         }
         (Method::Get, "/trading.html") => {
             serve_file(request, "static/trading.html");
-            return;
         }
         
 
@@ -102,9 +109,11 @@ fn handle_request(
                 let engine_clone = Arc::clone(&engine);
                 let updates_clone = Arc::clone(&updates);
                 let session_id_clone = session_id.clone();
+                let events = EventStream::new(stream::DEFAULT_CAPACITY);
+                streams.lock().unwrap().insert(session_id.clone(), events.clone());
                 
                 thread::spawn(move || {
-                    collect_updates(session_id_clone, engine_clone, updates_clone);
+                    collect_updates(session_id_clone, engine_clone, updates_clone, events);
                 });
                 
                 let response = StartResponse {
@@ -118,6 +127,8 @@ fn handle_request(
                     .with_header(cors_header)
                     .with_header(content_type);
                 let _ = request.respond(response);
+            } else {
+                respond_error(request, &RequestError::InvalidBody("expected strategy parameters".to_string()));
             }
         }
         
@@ -126,25 +137,24 @@ fn handle_request(
             let mut content = String::new();
             request.as_reader().read_to_string(&mut content).unwrap();
             
-            if let Ok(req) = serde_json::from_str::<StopRequest>(&content) {
-                let result = engine.stop_session(&req.session_id);
-                
-                let (success, message) = match result {
-                    Ok(_) => (true, "Session stopped".to_string()),
-                    Err(e) => (false, e),
-                };
-                
-                let response = StartResponse {
-                    success,
-                    session_id: None,
-                    message,
-                };
-                
-                let json = serde_json::to_string(&response).unwrap();
-                let response = Response::from_string(json)
-                    .with_header(cors_header)
-                    .with_header(content_type);
-                let _ = request.respond(response);
+            match serde_json::from_str::<StopRequest>(&content) {
+                Ok(req) => match engine.stop_session(&req.session_id) {
+                    Ok(()) => {
+                        let response = StartResponse {
+                            success: true,
+                            session_id: None,
+                            message: "Session stopped".to_string(),
+                        };
+
+                        let json = serde_json::to_string(&response).unwrap();
+                        let response = Response::from_string(json)
+                            .with_header(cors_header)
+                            .with_header(content_type);
+                        let _ = request.respond(response);
+                    }
+                    Err(e) => respond_error(request, &e),
+                },
+                Err(e) => respond_error(request, &RequestError::InvalidBody(e.to_string())),
             }
         }
         
@@ -156,7 +166,7 @@ fn handle_request(
             if let Ok(req) = serde_json::from_str::<PollRequest>(&content) {
 
                 let mut update_list = updates.lock().unwrap();
-                let session_updates = update_list.entry(req.session_id.clone()).or_insert_with(Vec::new);
+                let session_updates = update_list.entry(req.session_id.clone()).or_default();
                 
                 if let Some(update) = session_updates.pop() {
                     let json = serde_json::to_string(&update).unwrap();
@@ -171,10 +181,17 @@ fn handle_request(
                         .with_header(content_type);
                     let _ = request.respond(response);
                 }
+            } else {
+                respond_error(request, &RequestError::InvalidBody("expected {\"session_id\"}".to_string()));
             }
         }
         
 
+        (Method::Get, "/api/trading/stream") => {
+            stream_updates(request, query, &streams);
+        }
+        
+
         (Method::Post, "/api/trading/trade") => {
             let mut content = String::new();
             request.as_reader().read_to_string(&mut content).unwrap();
@@ -201,20 +218,65 @@ fn handle_request(
                             .with_header(content_type);
                         let _ = request.respond(response);
                     }
-                    Err(e) => {
-                        let json = format!("{{\"error\": \"{}\"}}", e);
-                        let response = Response::from_string(json)
-                            .with_header(cors_header)
-                            .with_header(content_type);
-                        let _ = request.respond(response);
-                    }
+                    Err(e) => respond_error(request, &e),
                 }
+            } else {
+                respond_error(request, &RequestError::InvalidBody("expected {\"session_id\", \"side\", \"price\"}".to_string()));
             }
         }
         
-        _ => {
-            let response = Response::from_string("Not Found").with_status_code(404);
-            let _ = request.respond(response);
+        _ => respond_error(request, &RequestError::NotFound("Route".to_string())),
+    }
+}
+
+/// Responds with the error's HTTP status and its JSON error body.
+fn respond_error<E: ErrorCode>(request: tiny_http::Request, error: &E) {
+    let cors_header = Header::from_bytes(&b"Access-Control-Allow-Origin"[..], &b"*"[..]).unwrap();
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let json = serde_json::to_string(&error.to_body()).unwrap();
+    let response = Response::from_string(json)
+        .with_status_code(error.http_status())
+        .with_header(cors_header)
+        .with_header(content_type);
+    let _ = request.respond(response);
+}
+
+/// GET /api/trading/stream?session_id=..: the session's updates as
+/// server-sent `market_update` events. Reconnecting clients resume after
+/// `Last-Event-ID` or `?resume=`.
+fn stream_updates(request: tiny_http::Request, query: &str, streams: &Streams) {
+    let param = |key: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.to_string())
+    };
+    let Some(events) = param("session_id").and_then(|id| streams.lock().unwrap().get(&id).cloned()) else {
+        return respond_error(request, &RequestError::NotFound("Session".to_string()));
+    };
+    let token = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Last-Event-ID"))
+        .map(|header| header.value.to_string())
+        .or_else(|| param("resume"));
+    let mut subscription = match events.subscribe(token.as_deref()) {
+        Ok(subscription) => subscription,
+        Err(e) => return respond_error(request, &e),
+    };
+
+    // Upgrading hands over the raw connection, so frames go out as they
+    // are written instead of waiting in a chunk buffer.
+    let response = Response::empty(200)
+        .with_header(Header::from_bytes(&b"Content-Type"[..], &b"text/event-stream"[..]).unwrap())
+        .with_header(Header::from_bytes(&b"Cache-Control"[..], &b"no-cache"[..]).unwrap())
+        .with_header(Header::from_bytes(&b"Access-Control-Allow-Origin"[..], &b"*"[..]).unwrap());
+    let mut connection = request.upgrade("text/event-stream", response);
+    loop {
+        let frame = subscription.next_frame(stream::KEEP_ALIVE);
+        if connection.write_all(frame.as_bytes()).and_then(|_| connection.flush()).is_err() {
+            return;
         }
     }
 }
@@ -242,10 +304,12 @@ fn collect_updates(
     session_id: String,
     engine: Arc<LiveTradingEngine>,
     updates: UpdateQueue,
+    events: EventStream,
 ) {
     engine.run_trading_loop(session_id.clone(), move |update| {
+        events.publish("market_update", &update);
         let mut update_list = updates.lock().unwrap();
-        let session_updates = update_list.entry(session_id.clone()).or_insert_with(|| Vec::new());
+        let session_updates = update_list.entry(session_id.clone()).or_default();
         session_updates.push(update);
         
 
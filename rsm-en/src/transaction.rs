@@ -1,31 +1,54 @@
+use crate::balances;
+use crate::genesis::DEFAULT_CHAIN_ID;
+use crate::keys::{self, KeyPair};
 use crate::merkle::Hash;
-use crate::rps_mining::{RPSMiningResult};
+use crate::runtime::Call;
+use crate::vesting;
+use crate::utxo::{UtxoBody, Witness};
+use crate::rps_mining::{LegacyRPSMiningResult, MiningError, PlayerOutcome, RPSMiningResult};
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Transaction {
     pub from: String,
-    pub to: String,
-    pub amount: u128,
+    pub call: Call,
     pub nonce: u32,
     pub timestamp: u64,
+    /// Chain the transaction is valid on. Part of the hash, so it cannot be
+    /// replayed on another chain.
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
     pub hash: Hash,
+    /// Signature over `hash` by the key whose address is `from`. Required
+    /// when `from` is a key address; named accounts such as `alice` send
+    /// unsigned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Witness>,
+}
+
+fn default_chain_id() -> u64 {
+    DEFAULT_CHAIN_ID
 }
 
 impl Transaction {
     pub fn new(from: String, to: String, amount: u128, nonce: u32) -> Self {
+        Self::new_call(from, Call::Balances(balances::Call::Transfer { to, amount }), nonce)
+    }
+
+    pub fn new_call(from: String, call: Call, nonce: u32) -> Self {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
         let mut tx = Self {
             from,
-            to,
-            amount,
+            call,
             nonce,
             timestamp,
+            chain_id: DEFAULT_CHAIN_ID,
             hash: Hash::from_string(""),
+            signature: None,
         };
         
 
@@ -33,27 +56,130 @@ impl Transaction {
         tx
     }
 
+    /// Binds the transaction to `chain_id` and rehashes it.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Sets the timestamp and rehashes the transaction.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Signs the hash with `key`, whose address should be `from`. Sign
+    /// last: anything that rehashes the transaction voids the signature.
+    pub fn signed(mut self, key: &KeyPair) -> Self {
+        self.signature = Some(Witness { public_key: key.public_hex(), signature: key.sign(self.hash.as_bytes()) });
+        self
+    }
+
+    /// Hashes a serialized tuple of the signed fields, so no two field
+    /// values run together into the same input.
     pub fn calculate_hash(&self) -> Hash {
-        let data = format!(
-            "{}{}{}{}{}",
-            self.from, self.to, self.amount, self.nonce, self.timestamp
-        );
+        let data = serde_json::to_string(&(self.chain_id, &self.from, &self.call, self.nonce, self.timestamp))
+            .expect("transaction is serializable");
         Hash::from_string(&data)
     }
 
     pub fn is_valid(&self) -> bool {
-        self.hash == self.calculate_hash() && 
-        !self.from.is_empty() && 
-        !self.to.is_empty() &&
-        self.from != self.to
+        if self.hash != self.calculate_hash() || self.from.is_empty() || !self.is_authorized() {
+            return false;
+        }
+        match &self.call {
+            Call::Balances(balances::Call::Transfer { to, .. }) => !to.is_empty() && self.from != *to,
+            Call::System(_) | Call::Staking(_) | Call::Assets(_) | Call::Governance(_) | Call::Multisig(_) | Call::Contracts(_) => true,
+            Call::Vesting(vesting::Call::VestedTransfer { target, .. }) => !target.is_empty() && self.from != *target,
+        }
+    }
+
+    /// A key address must have signed; other accounts may not carry a
+    /// signature at all.
+    fn is_authorized(&self) -> bool {
+        match &self.signature {
+            Some(witness) => {
+                keys::address_of(&witness.public_key).is_some_and(|address| address.to_hex() == self.from)
+                    && keys::verify(&witness.public_key, self.hash.as_bytes(), &witness.signature)
+            }
+            None => Hash::from_hex(&self.from).is_none(),
+        }
+    }
+
+    /// Recipient and amount if this is a native transfer.
+    pub fn transfer_details(&self) -> Option<(&String, u128)> {
+        match &self.call {
+            Call::Balances(balances::Call::Transfer { to, amount }) => Some((to, *amount)),
+            _ => None,
+        }
+    }
+
+    pub fn involves(&self, address: &String) -> bool {
+        self.from == *address || self.call.counterparties().contains(&address)
     }
 
     #[allow(dead_code)]
-    pub fn to_string(&self) -> String {
+    pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_default()
     }
 }
 
+/// A block without its transactions, as exchanged during headers-first
+/// sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub index: u32,
+    pub timestamp: u64,
+    pub previous_hash: Hash,
+    pub merkle_root: Hash,
+    pub hash: Hash,
+    pub rps_mining_result: Option<RPSMiningResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_hash: Option<Hash>,
+    /// Roots of the UTXO transactions and set, in UTXO mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utxo_roots: Option<(Hash, Hash)>,
+}
+
+impl BlockHeader {
+    pub fn calculate_hash(&self) -> Hash {
+        let rps_data = if let Some(ref result) = self.rps_mining_result {
+            format!("{}:{}:{}", result.rounds, result.total_games, result.outcomes_root.to_hex())
+        } else {
+            "pending".to_string()
+        };
+        
+        let mut data = format!(
+            "{}{}{}{}{}",
+            self.index,
+            self.timestamp,
+            self.previous_hash.to_hex(),
+            self.merkle_root.to_hex(),
+            rps_data
+        );
+        if let Some(ref snapshot_hash) = self.snapshot_hash {
+            data.push_str(&format!(":snapshot:{}", snapshot_hash.to_hex()));
+        }
+        if let Some((ref transactions_root, ref set_root)) = self.utxo_roots {
+            data.push_str(&format!(":utxo:{}:{}", transactions_root.to_hex(), set_root.to_hex()));
+        }
+        Hash::from_string(&data)
+    }
+
+    /// What the RPS game that seals the block is seeded with.
+    pub fn seal_data(&self) -> String {
+        format!(
+            "{}{}{}{}",
+            self.index,
+            self.timestamp,
+            self.previous_hash.to_hex(),
+            self.merkle_root.to_hex()
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub index: u32,
@@ -63,6 +189,13 @@ pub struct Block {
     pub merkle_root: Hash,
     pub hash: Hash,
     pub rps_mining_result: Option<RPSMiningResult>,
+    /// Hash of the state snapshot this block executes on, on snapshot
+    /// heights only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_hash: Option<Hash>,
+    /// Transactions and resulting UTXO set root, in UTXO mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utxo: Option<UtxoBody>,
 }
 
 impl Block {
@@ -81,68 +214,86 @@ impl Block {
             merkle_root,
             hash: Hash::from_string(""),
             rps_mining_result: None,
+            snapshot_hash: None,
+            utxo: None,
         };
         
         block.hash = block.calculate_hash();
         block
     }
 
+    /// Commits `snapshot_hash` in the block hash.
+    pub fn with_snapshot_hash(mut self, snapshot_hash: Hash) -> Self {
+        self.snapshot_hash = Some(snapshot_hash);
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Sets the timestamp and rehashes the block.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Commits a UTXO body in the block hash.
+    pub fn with_utxo(mut self, body: UtxoBody) -> Self {
+        self.utxo = Some(body);
+        self.hash = self.calculate_hash();
+        self
+    }
+
     pub fn genesis() -> Self {
         let genesis_hash = Hash::from_string("genesis");
         Self::new(0, Vec::new(), genesis_hash)
     }
 
     pub fn calculate_hash(&self) -> Hash {
-        let rps_data = if let Some(ref result) = self.rps_mining_result {
-            format!("{}:{}", result.rounds, result.total_games)
-        } else {
-            "pending".to_string()
-        };
-        
-        let data = format!(
-            "{}{}{}{}{}",
-            self.index,
-            self.timestamp,
-            self.previous_hash.to_hex(),
-            self.merkle_root.to_hex(),
-            rps_data
-        );
-        Hash::from_string(&data)
+        self.header().calculate_hash()
+    }
+
+    /// Everything but the bodies. The header's hash commits to them through
+    /// the Merkle root and the UTXO roots.
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash.clone(),
+            merkle_root: self.merkle_root.clone(),
+            hash: self.hash.clone(),
+            rps_mining_result: self.rps_mining_result.clone(),
+            snapshot_hash: self.snapshot_hash.clone(),
+            utxo_roots: self.utxo.as_ref().map(|body| (body.transactions_root(), body.set_root.clone())),
+        }
     }
 
     fn calculate_merkle_root(transactions: &[Transaction]) -> Hash {
-        if transactions.is_empty() {
+        Self::merkle_root_of(transactions.iter().map(|tx| tx.hash.clone()))
+    }
+
+    fn merkle_root_of(hashes: impl ExactSizeIterator<Item = Hash>) -> Hash {
+        if hashes.len() == 0 {
             return Hash::from_string("empty");
         }
 
         let mut tree = crate::merkle::FastMerkleTree::new();
-        for tx in transactions {
-            tree.add_leaf(tx.hash.clone());
+        for hash in hashes {
+            tree.add_leaf(hash);
         }
         tree.build();
         
         tree.get_root().cloned().unwrap_or_else(|| Hash::from_string("empty"))
     }
 
-    pub fn mine_block_rps(&mut self, rps_miner: &mut crate::rps_mining::RPSMiner) -> Result<(), String> {
+    pub fn mine_block_rps(&mut self, rps_miner: &mut crate::rps_mining::RPSMiner) -> Result<Vec<PlayerOutcome>, MiningError> {
 
-        let block_data = format!(
-            "{}{}{}{}",
-            self.index,
-            self.timestamp,
-            self.previous_hash.to_hex(),
-            self.merkle_root.to_hex()
-        );
-        
+        let block_data = self.header().seal_data();
 
         match rps_miner.mine_block(&block_data) {
-            Ok(mining_result) => {
+            Ok((mining_result, outcomes)) => {
                 self.rps_mining_result = Some(mining_result.clone());
                 self.hash = self.calculate_hash();
-                
-                println!("Block mined with RPS: {} (Rounds: {}, Games: {})", 
-                         self.hash, mining_result.rounds, mining_result.total_games);
-                Ok(())
+                Ok(outcomes)
             }
             Err(e) => Err(e)
         }
@@ -177,10 +328,7 @@ impl Block {
     #[allow(dead_code)]
     pub fn is_valid(&self, previous_block: Option<&Block>) -> bool {
 
-        if self.hash != self.calculate_hash() {
-            return false;
-        }
-        if self.merkle_root != Self::calculate_merkle_root(&self.transactions) {
+        if !self.is_self_consistent() {
             return false;
         }
         if let Some(prev_block) = previous_block {
@@ -193,15 +341,19 @@ impl Block {
         } else if self.index != 0 {
             return false;
         }
-        for tx in &self.transactions {
-            if !tx.is_valid() {
-                return false;
-            }
-        }
 
         true
     }
 
+    /// Hash, Merkle root and transactions check out, without looking at the
+    /// previous block. Enough for the first block after a snapshot, or to
+    /// match a body to a header with the same hash.
+    pub fn is_self_consistent(&self) -> bool {
+        self.hash == self.calculate_hash()
+            && self.merkle_root == Self::calculate_merkle_root(&self.transactions)
+            && self.transactions.iter().all(|tx| tx.is_valid())
+    }
+
     #[allow(dead_code)]
     pub fn get_transaction_proof(&self, tx_index: usize) -> Option<Vec<Hash>> {
         if tx_index >= self.transactions.len() {
@@ -229,6 +381,87 @@ impl Block {
     }
 }
 
+/// Transfer layout from before calls, chain IDs and signatures.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyTransaction {
+    pub from: String,
+    pub to: String,
+    pub amount: u128,
+    pub nonce: u32,
+    pub timestamp: u64,
+    pub hash: Hash,
+}
+
+impl LegacyTransaction {
+    /// The hash legacy blocks were built with.
+    pub fn calculate_hash(&self) -> Hash {
+        let data = format!("{}{}{}{}{}", self.from, self.to, self.amount, self.nonce, self.timestamp);
+        Hash::from_string(&data)
+    }
+}
+
+impl From<LegacyTransaction> for Transaction {
+    /// A transfer on the default chain, hashed the current way.
+    fn from(legacy: LegacyTransaction) -> Self {
+        Transaction::new(legacy.from, legacy.to, legacy.amount, legacy.nonce).with_timestamp(legacy.timestamp)
+    }
+}
+
+/// Block layout from before RPS outcomes were committed by Merkle root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyBlock {
+    pub index: u32,
+    pub timestamp: u64,
+    pub transactions: Vec<LegacyTransaction>,
+    pub previous_hash: Hash,
+    pub merkle_root: Hash,
+    pub hash: Hash,
+    pub rps_mining_result: Option<LegacyRPSMiningResult>,
+}
+
+impl Block {
+    /// Converts a legacy block, linking it to `previous_hash`. Its transfers
+    /// must match their legacy hashes and Merkle root; they are rehashed
+    /// the current way, and the outcomes root is part of the block hash, so
+    /// both roots and the block hash are recomputed.
+    pub fn from_legacy(legacy: LegacyBlock, previous_hash: Hash) -> Result<Self, MiningError> {
+        let index = legacy.index;
+        let hashes_match = legacy.transactions.iter().all(|tx| tx.hash == tx.calculate_hash())
+            && legacy.merkle_root == Self::merkle_root_of(legacy.transactions.iter().map(|tx| tx.hash.clone()));
+        if !hashes_match {
+            return Err(MiningError::InvalidLegacyBlock { index });
+        }
+        let transactions: Vec<Transaction> = legacy.transactions.into_iter().map(Transaction::from).collect();
+        let mut block = Self {
+            index,
+            timestamp: legacy.timestamp,
+            merkle_root: Self::calculate_merkle_root(&transactions),
+            transactions,
+            previous_hash,
+            hash: Hash::from_string(""),
+            rps_mining_result: legacy.rps_mining_result.map(RPSMiningResult::try_from).transpose()?,
+            snapshot_hash: None,
+            utxo: None,
+        };
+        block.hash = block.calculate_hash();
+        Ok(block)
+    }
+
+    /// Converts a whole legacy chain, re-linking every block to its
+    /// converted predecessor.
+    pub fn from_legacy_chain(legacy_blocks: Vec<LegacyBlock>) -> Result<Vec<Block>, MiningError> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(legacy_blocks.len());
+        for legacy in legacy_blocks {
+            let previous_hash = match blocks.last() {
+                Some(previous) => previous.hash.clone(),
+                None => legacy.previous_hash.clone(),
+            };
+            blocks.push(Self::from_legacy(legacy, previous_hash)?);
+        }
+        Ok(blocks)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,6 +477,48 @@ mod tests {
         assert!(tx.is_valid());
     }
 
+    #[test]
+    fn test_chain_id_is_hashed() {
+        let tx = Transaction::new("alice".to_string(), "bob".to_string(), 100, 1);
+        let other_chain = tx.clone().with_chain_id(7);
+        assert_ne!(tx.hash, other_chain.hash);
+        assert!(other_chain.is_valid());
+
+        let mut replayed = other_chain.clone();
+        replayed.chain_id = tx.chain_id;
+        assert!(!replayed.is_valid());
+    }
+
+    #[test]
+    fn test_key_address_must_sign() {
+        let key = KeyPair::from_seed([3; 32]);
+        let unsigned = Transaction::new(key.address().to_hex(), "bob".to_string(), 100, 1);
+        assert!(!unsigned.is_valid());
+        assert!(unsigned.clone().signed(&key).is_valid());
+        assert!(!unsigned.clone().signed(&KeyPair::from_seed([4; 32])).is_valid());
+
+        let mut tampered = unsigned.signed(&key);
+        tampered.call = Call::Balances(balances::Call::Transfer { to: "mallory".to_string(), amount: 100 });
+        tampered.hash = tampered.calculate_hash();
+        assert!(!tampered.is_valid());
+    }
+
+    #[test]
+    fn test_nonce_and_timestamp_cannot_be_reshuffled() {
+        let key = KeyPair::from_seed([3; 32]);
+        let signed = Transaction::new(key.address().to_hex(), "bob".to_string(), 100, 1)
+            .with_timestamp(1_760_797_000)
+            .signed(&key);
+        assert!(signed.is_valid());
+
+        let mut replayed = signed.clone();
+        replayed.nonce = 11;
+        replayed.timestamp = 760_797_000;
+        replayed.hash = replayed.calculate_hash();
+        assert_ne!(replayed.hash, signed.hash);
+        assert!(!replayed.is_valid());
+    }
+
     #[test]
     fn test_block_creation() {
         let tx = Transaction::new(
@@ -279,4 +554,65 @@ mod tests {
         let block = Block::new(1, vec![tx], genesis.hash.clone());
         assert!(block.is_valid(Some(&genesis)));
     }
+
+    #[test]
+    fn test_legacy_chain_conversion() {
+        let mut miner = crate::rps_mining::RPSMiner::new(crate::rps_mining::RPSMiningConfig::new());
+        let genesis = Block::genesis();
+        let mut block = Block::new(1, Vec::new(), genesis.hash.clone());
+        block.mine_block_rps(&mut miner).unwrap();
+
+        let mut transfer = LegacyTransaction {
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+            amount: 100,
+            nonce: 1,
+            timestamp: 1_700_000_000,
+            hash: Hash::from_string(""),
+        };
+        transfer.hash = transfer.calculate_hash();
+        let legacy_root = Block::merkle_root_of(std::iter::once(transfer.hash.clone()));
+
+        let legacy_json = serde_json::json!({
+            "index": 1,
+            "timestamp": block.timestamp,
+            "transactions": [{
+                "from": transfer.from,
+                "to": transfer.to,
+                "amount": transfer.amount,
+                "nonce": transfer.nonce,
+                "timestamp": transfer.timestamp,
+                "hash": transfer.hash,
+            }],
+            "previous_hash": genesis.hash,
+            "merkle_root": legacy_root,
+            "hash": block.hash,
+            "rps_mining_result": {
+                "success": true,
+                "rounds": 1,
+                "total_games": 3,
+                "mining_time_ms": 0,
+                "winning_players": [
+                    { "id": 1, "required_wins": 1, "current_wins": 1, "games_played": 2, "seed": 9 },
+                    { "id": 0, "required_wins": 1, "current_wins": 1, "games_played": 1, "seed": 5 }
+                ],
+                "final_seed": 7
+            }
+        });
+        let legacy_genesis: LegacyBlock = serde_json::from_value(serde_json::to_value(&genesis).unwrap()).unwrap();
+        let legacy_block: LegacyBlock = serde_json::from_value(legacy_json).unwrap();
+
+        let mut tampered = legacy_block.clone();
+        tampered.transactions[0].amount = 1_000;
+        assert_eq!(Block::from_legacy(tampered, genesis.hash.clone()).unwrap_err(), MiningError::InvalidLegacyBlock { index: 1 });
+
+        let blocks = Block::from_legacy_chain(vec![legacy_genesis, legacy_block]).unwrap();
+        assert!(blocks[1].is_valid(Some(&blocks[0])));
+        assert_eq!(blocks[1].transactions[0].transfer_details(), Some((&"bob".to_string(), 100)));
+        assert_eq!(blocks[1].transactions[0].timestamp, 1_700_000_000);
+
+        let result = blocks[1].rps_mining_result.as_ref().unwrap();
+        assert_eq!(result.player_count, 2);
+        assert_eq!(result.total_wins, 2);
+    }
 }
@@ -0,0 +1,406 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::error::LedgerError;
+use crate::keys::{self, KeyPair};
+use crate::merkle::{FastMerkleTree, Hash};
+
+/// Most public keys a multisig script can list.
+pub const MAX_SCRIPT_KEYS: usize = 16;
+
+/// Conditions an output is locked with. Written as text:
+///   p2pkh <address>
+///   after <height> <script>
+///   multisig <threshold> <public key>...
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Script {
+    /// A signature from the key whose hash is the address.
+    PayToPubkeyHash(Hash),
+    /// `script`, from block `height` on.
+    After { height: u32, script: Box<Script> },
+    /// Signatures from `threshold` of the listed hex public keys.
+    Multisig { threshold: u8, public_keys: Vec<String> },
+}
+
+impl Script {
+    /// Pay-to-pubkey-hash for a hex address.
+    pub fn for_address(address: &str) -> Option<Script> {
+        Hash::from_hex(address).map(Script::PayToPubkeyHash)
+    }
+
+    pub fn validate(&self) -> Result<(), LedgerError> {
+        match self {
+            Script::PayToPubkeyHash(_) => Ok(()),
+            Script::After { script, .. } => script.validate(),
+            Script::Multisig { threshold, public_keys } => {
+                let distinct: BTreeSet<&String> = public_keys.iter().collect();
+                if public_keys.is_empty() || public_keys.len() > MAX_SCRIPT_KEYS || distinct.len() != public_keys.len() {
+                    return Err(LedgerError::InvalidScript("multisig needs 1 to 16 distinct keys".to_string()));
+                }
+                if *threshold == 0 || *threshold as usize > public_keys.len() {
+                    return Err(LedgerError::InvalidScript("threshold out of range".to_string()));
+                }
+                if public_keys.iter().any(|key| keys::address_of(key).is_none()) {
+                    return Err(LedgerError::InvalidScript("invalid public key".to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether `witness` unlocks the script for a transaction with hash
+    /// `sighash` in block `height`.
+    pub fn is_satisfied(&self, witness: &[Witness], sighash: &Hash, height: u32) -> bool {
+        let signed = |public_key: &String| {
+            witness.iter().any(|w| w.public_key == *public_key && keys::verify(&w.public_key, sighash.as_bytes(), &w.signature))
+        };
+        match self {
+            Script::PayToPubkeyHash(address) => witness.iter().any(|w| {
+                keys::address_of(&w.public_key).as_ref() == Some(address) && signed(&w.public_key)
+            }),
+            Script::After { height: unlock_at, script } => height >= *unlock_at && script.is_satisfied(witness, sighash, height),
+            Script::Multisig { threshold, public_keys } => {
+                public_keys.iter().filter(|key| signed(key)).count() >= *threshold as usize
+            }
+        }
+    }
+
+    fn parse_tokens<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<Script, LedgerError> {
+        let invalid = |reason: &str| LedgerError::InvalidScript(reason.to_string());
+        match tokens.next() {
+            Some("p2pkh") => {
+                let address = tokens.next().ok_or_else(|| invalid("p2pkh needs an address"))?;
+                Script::for_address(address).ok_or_else(|| invalid("invalid address"))
+            }
+            Some("after") => {
+                let height = tokens.next().and_then(|h| h.parse().ok()).ok_or_else(|| invalid("after needs a height"))?;
+                let script = Box::new(Self::parse_tokens(tokens)?);
+                Ok(Script::After { height, script })
+            }
+            Some("multisig") => {
+                let threshold = tokens.next().and_then(|t| t.parse().ok()).ok_or_else(|| invalid("multisig needs a threshold"))?;
+                let public_keys = tokens.map(str::to_string).collect();
+                Ok(Script::Multisig { threshold, public_keys })
+            }
+            Some(other) => Err(invalid(&format!("unknown script '{}'", other))),
+            None => Err(invalid("empty script")),
+        }
+    }
+}
+
+impl FromStr for Script {
+    type Err = LedgerError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut tokens = text.split_whitespace();
+        let script = Self::parse_tokens(&mut tokens)?;
+        if tokens.next().is_some() {
+            return Err(LedgerError::InvalidScript("trailing input".to_string()));
+        }
+        script.validate()?;
+        Ok(script)
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Script::PayToPubkeyHash(address) => write!(f, "p2pkh {}", address),
+            Script::After { height, script } => write!(f, "after {} {}", height, script),
+            Script::Multisig { threshold, public_keys } => write!(f, "multisig {} {}", threshold, public_keys.join(" ")),
+        }
+    }
+}
+
+/// A signature over a transaction's hash, with the key that made it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Witness {
+    pub public_key: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub tx_hash: Hash,
+    pub index: u32,
+}
+
+impl OutPoint {
+    /// Key of the output in the UTXO set.
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.tx_hash, self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxInput {
+    pub outpoint: OutPoint,
+    #[serde(default)]
+    pub witness: Vec<Witness>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxOutput {
+    pub value: u128,
+    pub script: Script,
+}
+
+/// A transaction in UTXO mode. Its hash leaves out the witnesses, so it is
+/// also the message every input signs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UtxoTransaction {
+    pub chain_id: u64,
+    /// Height of the block, on coinbase transactions only. Keeps every
+    /// coinbase hash unique.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase: Option<u32>,
+    pub inputs: Vec<TxInput>,
+    pub outputs: Vec<TxOutput>,
+    pub hash: Hash,
+}
+
+impl UtxoTransaction {
+    /// An unsigned transaction spending `outpoints`.
+    pub fn new(chain_id: u64, outpoints: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Self {
+        let inputs = outpoints.into_iter().map(|outpoint| TxInput { outpoint, witness: Vec::new() }).collect();
+        let mut tx = Self { chain_id, coinbase: None, inputs, outputs, hash: Hash::from_string("") };
+        tx.hash = tx.calculate_hash();
+        tx
+    }
+
+    /// Mints the block reward and fees of block `height`.
+    pub fn coinbase(chain_id: u64, height: u32, outputs: Vec<TxOutput>) -> Self {
+        let mut tx = Self { chain_id, coinbase: Some(height), inputs: Vec::new(), outputs, hash: Hash::from_string("") };
+        tx.hash = tx.calculate_hash();
+        tx
+    }
+
+    pub fn calculate_hash(&self) -> Hash {
+        let outpoints: Vec<&OutPoint> = self.inputs.iter().map(|input| &input.outpoint).collect();
+        let data = serde_json::to_string(&(self.chain_id, self.coinbase, outpoints, &self.outputs))
+            .expect("transaction is serializable");
+        Hash::from_string(&data)
+    }
+
+    /// Signs input `index` with `key`.
+    pub fn sign_input(&mut self, index: usize, key: &KeyPair) {
+        let signature = key.sign(self.hash.as_bytes());
+        if let Some(input) = self.inputs.get_mut(index) {
+            input.witness.push(Witness { public_key: key.public_hex(), signature });
+        }
+    }
+
+    pub fn output_value(&self) -> u128 {
+        self.outputs.iter().map(|output| output.value).fold(0, u128::saturating_add)
+    }
+
+    pub fn outpoint(&self, index: u32) -> OutPoint {
+        OutPoint { tx_hash: self.hash.clone(), index }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Utxo {
+    pub output: TxOutput,
+    /// Block the output was created in.
+    pub height: u32,
+}
+
+/// Every unspent output, by outpoint key.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UtxoSet {
+    pub unspent: BTreeMap<String, Utxo>,
+}
+
+impl UtxoSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Utxo> {
+        self.unspent.get(&outpoint.key())
+    }
+
+    /// Checks a spend in block `height` without applying it. Returns the
+    /// fee: inputs minus outputs.
+    pub fn check(&self, tx: &UtxoTransaction, height: u32) -> Result<u128, LedgerError> {
+        if tx.hash != tx.calculate_hash() || tx.coinbase.is_some() || tx.inputs.is_empty() {
+            return Err(LedgerError::InvalidTransaction);
+        }
+        Self::check_outputs(tx)?;
+
+        let mut spent = BTreeSet::new();
+        let mut input_value: u128 = 0;
+        for input in &tx.inputs {
+            if !spent.insert(input.outpoint.key()) {
+                return Err(LedgerError::DoubleSpend);
+            }
+            let utxo = self.get(&input.outpoint).ok_or(LedgerError::UnknownOutput)?;
+            if !utxo.output.script.is_satisfied(&input.witness, &tx.hash, height) {
+                return Err(LedgerError::ScriptFailed);
+            }
+            input_value = input_value.checked_add(utxo.output.value).ok_or(LedgerError::Arithmetic)?;
+        }
+        input_value.checked_sub(tx.output_value()).ok_or(LedgerError::OutputsExceedInputs)
+    }
+
+    /// Applies a spend in block `height`. Returns its fee.
+    pub fn apply(&mut self, tx: &UtxoTransaction, height: u32) -> Result<u128, LedgerError> {
+        let fee = self.check(tx, height)?;
+        for input in &tx.inputs {
+            self.unspent.remove(&input.outpoint.key());
+        }
+        self.add_outputs(tx, height);
+        Ok(fee)
+    }
+
+    /// Applies the coinbase of block `height`, which may mint up to
+    /// `max_value`. It has no outputs once there is nothing to mint.
+    pub fn apply_coinbase(&mut self, tx: &UtxoTransaction, height: u32, max_value: u128) -> Result<(), LedgerError> {
+        if tx.hash != tx.calculate_hash() || tx.coinbase != Some(height) || !tx.inputs.is_empty() {
+            return Err(LedgerError::InvalidCoinbase);
+        }
+        if !tx.outputs.is_empty() {
+            Self::check_outputs(tx)?;
+        }
+        if tx.output_value() > max_value {
+            return Err(LedgerError::InvalidCoinbase);
+        }
+        self.add_outputs(tx, height);
+        Ok(())
+    }
+
+    fn check_outputs(tx: &UtxoTransaction) -> Result<(), LedgerError> {
+        if tx.outputs.is_empty() || tx.outputs.iter().any(|output| output.value == 0) {
+            return Err(LedgerError::InvalidTransaction);
+        }
+        tx.outputs.iter().try_for_each(|output| output.script.validate())
+    }
+
+    fn add_outputs(&mut self, tx: &UtxoTransaction, height: u32) {
+        for (index, output) in tx.outputs.iter().enumerate() {
+            self.unspent.insert(tx.outpoint(index as u32).key(), Utxo { output: output.clone(), height });
+        }
+    }
+
+    /// Outputs locked to `address` alone, spendable by its key.
+    pub fn unspent_for(&self, address: &Hash) -> Vec<(OutPoint, &Utxo)> {
+        self.unspent
+            .iter()
+            .filter(|(_, utxo)| utxo.output.script == Script::PayToPubkeyHash(address.clone()))
+            .filter_map(|(key, utxo)| {
+                let (tx_hash, index) = key.split_once(':')?;
+                Some((OutPoint { tx_hash: Hash::from_hex(tx_hash)?, index: index.parse().ok()? }, utxo))
+            })
+            .collect()
+    }
+
+    pub fn total_value(&self) -> u128 {
+        self.unspent.values().map(|utxo| utxo.output.value).fold(0, u128::saturating_add)
+    }
+
+    /// Commitment to the whole set.
+    pub fn root(&self) -> Hash {
+        let mut tree = FastMerkleTree::new();
+        for (key, utxo) in &self.unspent {
+            tree.add_leaf(Hash::from_string(&format!("{}:{}:{}:{}", key, utxo.output.value, utxo.output.script, utxo.height)));
+        }
+        tree.build();
+        tree.get_root().cloned().unwrap_or_else(|| Hash::from_string("empty"))
+    }
+}
+
+/// The transactions of a block in UTXO mode, coinbase first, and the root
+/// of the UTXO set after them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UtxoBody {
+    pub transactions: Vec<UtxoTransaction>,
+    pub set_root: Hash,
+}
+
+impl UtxoBody {
+    pub fn transactions_root(&self) -> Hash {
+        let mut tree = FastMerkleTree::new();
+        for tx in &self.transactions {
+            tree.add_leaf(tx.hash.clone());
+        }
+        tree.build();
+        tree.get_root().cloned().unwrap_or_else(|| Hash::from_string("empty"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn funded(key: &KeyPair, value: u128) -> (UtxoSet, UtxoTransaction) {
+        let mut set = UtxoSet::new();
+        let coinbase = UtxoTransaction::coinbase(1, 0, vec![TxOutput { value, script: Script::PayToPubkeyHash(key.address()) }]);
+        set.apply_coinbase(&coinbase, 0, value).unwrap();
+        (set, coinbase)
+    }
+
+    #[test]
+    fn test_p2pkh_spend() {
+        let alice = KeyPair::from_seed([1; 32]);
+        let bob = KeyPair::from_seed([2; 32]);
+        let (mut set, coinbase) = funded(&alice, 100);
+
+        let outputs = vec![
+            TxOutput { value: 60, script: Script::PayToPubkeyHash(bob.address()) },
+            TxOutput { value: 35, script: Script::PayToPubkeyHash(alice.address()) },
+        ];
+        let mut spend = UtxoTransaction::new(1, vec![coinbase.outpoint(0)], outputs);
+        assert_eq!(set.check(&spend, 1), Err(LedgerError::ScriptFailed));
+        let mut forged = spend.clone();
+        forged.sign_input(0, &bob);
+        assert_eq!(set.check(&forged, 1), Err(LedgerError::ScriptFailed));
+
+        spend.sign_input(0, &alice);
+        assert_eq!(set.apply(&spend, 1), Ok(5));
+        assert_eq!(set.apply(&spend, 1), Err(LedgerError::UnknownOutput));
+        assert_eq!(set.unspent_for(&bob.address()).len(), 1);
+        assert_eq!(set.total_value(), 95);
+    }
+
+    #[test]
+    fn test_timelock_and_multisig_scripts() {
+        let keys: Vec<KeyPair> = (1..=3).map(|seed| KeyPair::from_seed([seed; 32])).collect();
+        let multisig = Script::Multisig { threshold: 2, public_keys: keys.iter().map(KeyPair::public_hex).collect() };
+        let locked = Script::After { height: 10, script: Box::new(multisig) };
+        let parsed: Script = locked.to_string().parse().unwrap();
+        assert_eq!(parsed, locked);
+
+        let mut set = UtxoSet::new();
+        let coinbase = UtxoTransaction::coinbase(1, 0, vec![TxOutput { value: 50, script: locked }]);
+        set.apply_coinbase(&coinbase, 0, 50).unwrap();
+
+        let payout = vec![TxOutput { value: 50, script: Script::PayToPubkeyHash(keys[0].address()) }];
+        let mut spend = UtxoTransaction::new(1, vec![coinbase.outpoint(0)], payout);
+        spend.sign_input(0, &keys[0]);
+        assert_eq!(set.check(&spend, 12), Err(LedgerError::ScriptFailed));
+        spend.sign_input(0, &keys[2]);
+        assert_eq!(set.check(&spend, 9), Err(LedgerError::ScriptFailed));
+        assert_eq!(set.check(&spend, 10), Ok(0));
+
+        assert!("multisig 3 abc".parse::<Script>().is_err());
+        assert!("p2pkh 00".parse::<Script>().is_err());
+    }
+
+    #[test]
+    fn test_coinbase_and_value_checks() {
+        let alice = KeyPair::from_seed([1; 32]);
+        let (mut set, coinbase) = funded(&alice, 100);
+        let before = set.root();
+
+        let greedy = UtxoTransaction::coinbase(1, 1, vec![TxOutput { value: 101, script: Script::PayToPubkeyHash(alice.address()) }]);
+        assert_eq!(set.apply_coinbase(&greedy, 1, 100), Err(LedgerError::InvalidCoinbase));
+
+        let outputs = vec![TxOutput { value: 101, script: Script::PayToPubkeyHash(alice.address()) }];
+        let mut overspend = UtxoTransaction::new(1, vec![coinbase.outpoint(0), coinbase.outpoint(0)], outputs);
+        overspend.sign_input(0, &alice);
+        assert_eq!(set.check(&overspend, 1), Err(LedgerError::DoubleSpend));
+        assert_eq!(set.root(), before);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use num_traits::{Bounded, CheckedMul, NumCast, Saturating, Zero};
+use serde::{Deserialize, Serialize};
+use crate::balances::{self, LockId, Pallet as BalancesPallet};
+use crate::runtime::{DefaultConfig, DispatchResult};
+use crate::error::LedgerError;
+
+pub const VESTING_ID: LockId = *b"vesting ";
+
+/// How a vested amount becomes transferable.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum VestingSchedule<T: balances::Config = DefaultConfig> {
+    /// `per_block` unlocks every block after `starting_block`.
+    Linear { locked: T::Balance, per_block: T::Balance, starting_block: T::BlockNumber },
+    /// Everything unlocks at once at `unlock_at`.
+    Cliff { locked: T::Balance, unlock_at: T::BlockNumber },
+}
+
+// Derived `Clone` would require `T: Clone`; the fields are all `Copy`.
+impl<T: balances::Config> Clone for VestingSchedule<T> {
+    fn clone(&self) -> Self {
+        match self {
+            VestingSchedule::Linear { locked, per_block, starting_block } => {
+                VestingSchedule::Linear { locked: *locked, per_block: *per_block, starting_block: *starting_block }
+            }
+            VestingSchedule::Cliff { locked, unlock_at } => VestingSchedule::Cliff { locked: *locked, unlock_at: *unlock_at },
+        }
+    }
+}
+
+impl<T: balances::Config> VestingSchedule<T> {
+    pub fn locked(&self) -> T::Balance {
+        match self {
+            VestingSchedule::Linear { locked, .. } | VestingSchedule::Cliff { locked, .. } => *locked,
+        }
+    }
+
+    /// Amount still locked at block `n`.
+    pub fn locked_at(&self, n: T::BlockNumber) -> T::Balance {
+        match self {
+            VestingSchedule::Linear { locked, per_block, starting_block } => {
+                let elapsed: T::Balance = NumCast::from(n.saturating_sub(*starting_block))
+                    .unwrap_or_else(T::Balance::max_value);
+                let unlocked = elapsed.checked_mul(per_block).unwrap_or_else(T::Balance::max_value);
+                locked.saturating_sub(unlocked)
+            }
+            VestingSchedule::Cliff { locked, unlock_at } => {
+                if n < *unlock_at { *locked } else { T::Balance::zero() }
+            }
+        }
+    }
+
+    pub fn validate(&self) -> DispatchResult {
+        match self {
+            VestingSchedule::Linear { per_block, .. } if per_block.is_zero() => Err(LedgerError::ZeroVestingRate),
+            _ if self.locked().is_zero() => Err(LedgerError::NothingToVest),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Call<T: balances::Config = DefaultConfig> {
+    /// Transfers `schedule.locked()` to `target` and vests it there.
+    VestedTransfer { target: T::AccountId, schedule: VestingSchedule<T> },
+}
+
+impl<T: balances::Config> Call<T> {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Call::VestedTransfer { .. } => 25,
+        }
+    }
+}
+
+/// Locked versus vested funds for an account at some height.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct VestingInfo<T: balances::Config = DefaultConfig> {
+    pub at: T::BlockNumber,
+    pub locked: T::Balance,
+    pub vested: T::Balance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Pallet<T: balances::Config = DefaultConfig> {
+    pub schedules: BTreeMap<T::AccountId, Vec<VestingSchedule<T>>>,
+    pub min_vested_transfer: T::Balance,
+    pub max_schedules: usize,
+}
+
+impl<T: balances::Config> Pallet<T> {
+
+    pub fn new(min_vested_transfer: T::Balance, max_schedules: usize) -> Self {
+
+        Self {
+            schedules: BTreeMap::new(),
+            min_vested_transfer,
+            max_schedules,
+        }
+    }
+
+    /// Attaches a schedule to funds `who` already holds. Used for the
+    /// genesis spec's `vesting`; `vested_transfer` is the signed path.
+    pub fn add_schedule(&mut self, who: &T::AccountId, schedule: VestingSchedule<T>, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> DispatchResult {
+
+        schedule.validate()?;
+        if self.active_schedules(who, now) >= self.max_schedules {
+            return Err(LedgerError::TooManySchedules);
+        }
+        self.schedules.entry(who.clone()).or_default().push(schedule);
+        self.update_lock(who, now, balances);
+        Ok(())
+    }
+
+    pub fn vested_transfer(&mut self, from: &T::AccountId, target: &T::AccountId, schedule: VestingSchedule<T>, now: T::BlockNumber, balances: &mut BalancesPallet<T>) -> DispatchResult {
+
+        schedule.validate()?;
+        if schedule.locked() < self.min_vested_transfer {
+            return Err(LedgerError::VestedTransferTooSmall);
+        }
+        if self.active_schedules(target, now) >= self.max_schedules {
+            return Err(LedgerError::TooManySchedules);
+        }
+        balances.transfer(from.clone(), target.clone(), schedule.locked())?;
+        self.add_schedule(target, schedule, now, balances)
+    }
+
+    pub fn locked_at(&self, who: &T::AccountId, n: T::BlockNumber) -> T::Balance {
+
+        self.schedules
+            .get(who)
+            .into_iter()
+            .flatten()
+            .fold(T::Balance::zero(), |acc, schedule| acc.saturating_add(schedule.locked_at(n)))
+    }
+
+    pub fn vesting_info(&self, who: &T::AccountId, at: T::BlockNumber) -> VestingInfo<T> {
+
+        let total = self.schedules
+            .get(who)
+            .into_iter()
+            .flatten()
+            .fold(T::Balance::zero(), |acc, schedule| acc.saturating_add(schedule.locked()));
+        let locked = self.locked_at(who, at);
+        VestingInfo { at, locked, vested: total.saturating_sub(locked) }
+    }
+
+    /// Shrinks every vesting lock to what is still locked at `now`. Finished
+    /// schedules are kept so past heights can still be queried.
+    pub fn on_finalize(&mut self, now: T::BlockNumber, balances: &mut BalancesPallet<T>) {
+
+        let accounts: Vec<T::AccountId> = self.schedules.keys().cloned().collect();
+        for who in accounts {
+            self.update_lock(&who, now, balances);
+        }
+    }
+
+    pub fn dispatch(&mut self, caller: &T::AccountId, call: &Call<T>, balances: &mut BalancesPallet<T>, now: T::BlockNumber) -> DispatchResult {
+
+        match call {
+            Call::VestedTransfer { target, schedule } => {
+                self.vested_transfer(caller, target, schedule.clone(), now, balances)
+            }
+        }
+    }
+
+    fn active_schedules(&self, who: &T::AccountId, now: T::BlockNumber) -> usize {
+
+        self.schedules
+            .get(who)
+            .map_or(0, |schedules| schedules.iter().filter(|s| !s.locked_at(now).is_zero()).count())
+    }
+
+    fn update_lock(&self, who: &T::AccountId, now: T::BlockNumber, balances: &mut BalancesPallet<T>) {
+
+        let locked = self.locked_at(who, now);
+        if locked.is_zero() {
+            balances.remove_lock(VESTING_ID, who);
+        } else {
+            balances.set_lock(VESTING_ID, who, locked, T::BlockNumber::max_value());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Pallet, BalancesPallet) {
+        let mut balances = BalancesPallet::new();
+        balances.set_balance(&"alice".to_string(), 1000);
+        (Pallet::new(10, 3), balances)
+    }
+
+    #[test]
+    fn test_linear_vesting_unlocks_per_block() {
+        let (mut vesting, mut balances) = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let schedule = VestingSchedule::Linear { locked: 100, per_block: 10, starting_block: 2 };
+        vesting.vested_transfer(&alice, &bob, schedule, 0, &mut balances).unwrap();
+
+        assert_eq!(balances.get_balance(&bob), 100);
+        assert!(balances.transfer(bob.clone(), alice.clone(), 1).is_err());
+
+        assert_eq!(vesting.vesting_info(&bob, 5), VestingInfo { at: 5, locked: 70, vested: 30 });
+        vesting.on_finalize(5, &mut balances);
+        balances.transfer(bob.clone(), alice.clone(), 30).unwrap();
+        assert!(balances.transfer(bob.clone(), alice.clone(), 1).is_err());
+
+        vesting.on_finalize(12, &mut balances);
+        assert!(balances.locks.is_empty());
+        assert_eq!(vesting.vesting_info(&bob, 4).locked, 80);
+        balances.transfer(bob, alice, 70).unwrap();
+    }
+
+    #[test]
+    fn test_cliff_vesting() {
+        let (mut vesting, mut balances) = setup();
+        let alice = "alice".to_string();
+        vesting.add_schedule(&alice, VestingSchedule::Cliff { locked: 600, unlock_at: 10 }, 0, &mut balances).unwrap();
+
+        assert_eq!(vesting.vesting_info(&alice, 9).locked, 600);
+        assert_eq!(vesting.vesting_info(&alice, 10).vested, 600);
+        assert_eq!(balances.usable_balance(&alice), 400);
+        vesting.on_finalize(10, &mut balances);
+        assert_eq!(balances.usable_balance(&alice), 1000);
+    }
+
+    #[test]
+    fn test_vested_transfer_checks() {
+        let (mut vesting, mut balances) = setup();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        let small = VestingSchedule::Cliff { locked: 5, unlock_at: 10 };
+        assert_eq!(vesting.vested_transfer(&alice, &bob, small, 0, &mut balances), Err(LedgerError::VestedTransferTooSmall));
+        let stalled = VestingSchedule::Linear { locked: 50, per_block: 0, starting_block: 0 };
+        assert_eq!(vesting.vested_transfer(&alice, &bob, stalled, 0, &mut balances), Err(LedgerError::ZeroVestingRate));
+        assert_eq!(balances.get_balance(&alice), 1000);
+    }
+}
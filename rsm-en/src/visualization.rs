@@ -17,13 +17,13 @@ impl TradingVisualizer {
         result: &BacktestResult,
         bars: &[MarketBar],
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let mut files = Vec::new();
-
-        files.push(self.plot_equity_curve(result)?);
-        files.push(self.plot_price_and_trades(bars, &result.trade_history)?);
-        files.push(self.plot_drawdown(result)?);
-        files.push(self.plot_returns_distribution(result)?);
-        files.push(self.plot_trade_analysis(&result.trade_history)?);
+        let files = vec![
+            self.plot_equity_curve(result)?,
+            self.plot_price_and_trades(bars, &result.trade_history)?,
+            self.plot_drawdown(result)?,
+            self.plot_returns_distribution(result)?,
+            self.plot_trade_analysis(&result.trade_history)?,
+        ];
 
         Ok(files)
     }
@@ -70,17 +70,17 @@ impl TradingVisualizer {
             &BLUE.mix(0.8),
         ))?
         .label("Equity")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &BLUE));
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
         chart.draw_series(LineSeries::new(
             vec![(0.0, result.initial_capital), (equity_data.len() as f64, result.initial_capital)],
             RED.mix(0.5).stroke_width(2),
         ))?
         .label("Initial Capital")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
 
             chart.configure_series_labels()
-                .background_style(&WHITE.mix(0.8))
-                .border_style(&BLACK)
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
                 .draw()?;
 
             root.present()?;
@@ -244,7 +244,7 @@ impl TradingVisualizer {
             AreaSeries::new(
                 drawdowns.iter().map(|(x, y)| (*x, *y)),
                 0.0,
-                &RED.mix(0.3),
+                RED.mix(0.3),
             )
         )?;
             chart.draw_series(LineSeries::new(
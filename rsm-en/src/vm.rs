@@ -0,0 +1,613 @@
+use crate::error::ErrorCode;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Every value the VM handles: stack entries, storage keys and values,
+/// arguments, account words and amounts.
+pub type Word = u128;
+
+/// Deepest the stack can grow.
+pub const MAX_STACK: usize = 256;
+
+/// Instruction set. Operands are popped from the top of the stack; where an
+/// instruction takes a key or an address, that is the top operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Stop,
+    /// Followed by a length byte (0 to 16) and that many big-endian bytes.
+    Push,
+    Pop,
+    /// Followed by a byte `n`; copies the entry `n` below the top.
+    Dup,
+    /// Followed by a byte `n` (at least 1); swaps the top with the entry
+    /// `n` below it.
+    Swap,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Lt,
+    Gt,
+    Eq,
+    IsZero,
+    And,
+    Or,
+    /// `[dest]`: continues at `dest`, which must be a `JumpDest`.
+    Jump,
+    /// `[cond, dest]`: jumps if `cond` is not zero.
+    JumpI,
+    JumpDest,
+    /// `[key]` to `[value]`. Unset keys read as zero.
+    SLoad,
+    /// `[value, key]`. Storing zero clears the key.
+    SStore,
+    Caller,
+    CallValue,
+    /// Followed by a byte `n`; pushes argument `n`.
+    Arg,
+    ArgCount,
+    /// The contract's native balance, including the value sent.
+    Balance,
+    /// `[amount, to]`: pays `amount` of the contract's balance to the
+    /// account word `to`.
+    Transfer,
+    /// `[value]`: halts, returning `value`.
+    Return,
+    /// Halts and undoes everything the call did.
+    Revert,
+}
+
+impl Op {
+    pub const ALL: [Op; 29] = [
+        Op::Stop, Op::Push, Op::Pop, Op::Dup, Op::Swap,
+        Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Mod,
+        Op::Lt, Op::Gt, Op::Eq, Op::IsZero, Op::And, Op::Or,
+        Op::Jump, Op::JumpI, Op::JumpDest,
+        Op::SLoad, Op::SStore,
+        Op::Caller, Op::CallValue, Op::Arg, Op::ArgCount, Op::Balance, Op::Transfer,
+        Op::Return, Op::Revert,
+    ];
+
+    pub fn byte(self) -> u8 {
+        match self {
+            Op::Stop => 0x00,
+            Op::Push => 0x01,
+            Op::Pop => 0x02,
+            Op::Dup => 0x03,
+            Op::Swap => 0x04,
+            Op::Add => 0x10,
+            Op::Sub => 0x11,
+            Op::Mul => 0x12,
+            Op::Div => 0x13,
+            Op::Mod => 0x14,
+            Op::Lt => 0x20,
+            Op::Gt => 0x21,
+            Op::Eq => 0x22,
+            Op::IsZero => 0x23,
+            Op::And => 0x24,
+            Op::Or => 0x25,
+            Op::Jump => 0x30,
+            Op::JumpI => 0x31,
+            Op::JumpDest => 0x32,
+            Op::SLoad => 0x40,
+            Op::SStore => 0x41,
+            Op::Caller => 0x50,
+            Op::CallValue => 0x51,
+            Op::Arg => 0x52,
+            Op::ArgCount => 0x53,
+            Op::Balance => 0x54,
+            Op::Transfer => 0x55,
+            Op::Return => 0x60,
+            Op::Revert => 0x61,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Op> {
+        Op::ALL.into_iter().find(|op| op.byte() == byte)
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Stop => "STOP",
+            Op::Push => "PUSH",
+            Op::Pop => "POP",
+            Op::Dup => "DUP",
+            Op::Swap => "SWAP",
+            Op::Add => "ADD",
+            Op::Sub => "SUB",
+            Op::Mul => "MUL",
+            Op::Div => "DIV",
+            Op::Mod => "MOD",
+            Op::Lt => "LT",
+            Op::Gt => "GT",
+            Op::Eq => "EQ",
+            Op::IsZero => "ISZERO",
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Jump => "JUMP",
+            Op::JumpI => "JUMPI",
+            Op::JumpDest => "JUMPDEST",
+            Op::SLoad => "SLOAD",
+            Op::SStore => "SSTORE",
+            Op::Caller => "CALLER",
+            Op::CallValue => "CALLVALUE",
+            Op::Arg => "ARG",
+            Op::ArgCount => "ARGC",
+            Op::Balance => "BALANCE",
+            Op::Transfer => "TRANSFER",
+            Op::Return => "RETURN",
+            Op::Revert => "REVERT",
+        }
+    }
+
+    /// Gas charged before the instruction runs.
+    pub fn gas(self) -> u64 {
+        match self {
+            Op::Mul | Op::Div | Op::Mod => 3,
+            Op::Jump | Op::JumpI => 2,
+            Op::SLoad => 20,
+            Op::SStore => 50,
+            Op::Transfer => 100,
+            _ => 1,
+        }
+    }
+
+    /// Bytes of immediate data following the opcode, other than `Push`'s.
+    fn operand_len(self) -> usize {
+        match self {
+            Op::Dup | Op::Swap | Op::Arg => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Why a call stopped without completing. Every trap reverts the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trap {
+    OutOfGas,
+    StackUnderflow,
+    StackOverflow,
+    Arithmetic,
+    InvalidJump(usize),
+    InvalidOpcode(u8),
+    Truncated,
+    MissingArgument(u8),
+    InsufficientFunds,
+    Revert,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::OutOfGas => write!(f, "Out of gas"),
+            Trap::StackUnderflow => write!(f, "Stack underflow"),
+            Trap::StackOverflow => write!(f, "Stack overflow"),
+            Trap::Arithmetic => write!(f, "Arithmetic overflow, underflow or division by zero"),
+            Trap::InvalidJump(dest) => write!(f, "Invalid jump destination {}", dest),
+            Trap::InvalidOpcode(byte) => write!(f, "Invalid opcode 0x{:02x}", byte),
+            Trap::Truncated => write!(f, "Code ends mid-instruction"),
+            Trap::MissingArgument(n) => write!(f, "Missing argument {}", n),
+            Trap::InsufficientFunds => write!(f, "Contract balance too low"),
+            Trap::Revert => write!(f, "Reverted"),
+        }
+    }
+}
+
+/// Checks that `code` decodes cleanly and returns the offsets of its
+/// `JumpDest` instructions. Bytes inside immediates never count.
+pub fn validate(code: &[u8]) -> Result<BTreeSet<usize>, Trap> {
+    let mut jump_dests = BTreeSet::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = Op::from_byte(code[pc]).ok_or(Trap::InvalidOpcode(code[pc]))?;
+        if op == Op::JumpDest {
+            jump_dests.insert(pc);
+        }
+        pc += 1 + match op {
+            Op::Push => {
+                let len = *code.get(pc + 1).ok_or(Trap::Truncated)? as usize;
+                if len > 16 {
+                    return Err(Trap::Truncated);
+                }
+                1 + len
+            }
+            _ => op.operand_len(),
+        };
+        if pc > code.len() {
+            return Err(Trap::Truncated);
+        }
+    }
+    Ok(jump_dests)
+}
+
+/// What a call sees of the chain. `storage` is the contract's storage
+/// before the call.
+#[derive(Debug, Clone)]
+pub struct Context<'a> {
+    pub caller: Word,
+    pub value: Word,
+    pub balance: Word,
+    pub args: &'a [Word],
+    pub storage: &'a BTreeMap<Word, Word>,
+    pub gas_limit: u64,
+}
+
+/// Result of running a call. The writes and transfers are only to be
+/// applied if `result` is `Ok`; a trap leaves them empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    pub gas_used: u64,
+    pub result: Result<Option<Word>, Trap>,
+    /// Final value of every key written. Zero means cleared.
+    pub storage_writes: BTreeMap<Word, Word>,
+    /// `(to, amount)` in the order they were made.
+    pub transfers: Vec<(Word, Word)>,
+}
+
+struct Machine<'a> {
+    code: &'a [u8],
+    ctx: &'a Context<'a>,
+    jump_dests: BTreeSet<usize>,
+    pc: usize,
+    stack: Vec<Word>,
+    gas_used: u64,
+    balance: Word,
+    storage_writes: BTreeMap<Word, Word>,
+    transfers: Vec<(Word, Word)>,
+}
+
+impl Machine<'_> {
+    fn pop(&mut self) -> Result<Word, Trap> {
+        self.stack.pop().ok_or(Trap::StackUnderflow)
+    }
+
+    fn push(&mut self, value: Word) -> Result<(), Trap> {
+        if self.stack.len() >= MAX_STACK {
+            return Err(Trap::StackOverflow);
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn binary(&mut self, f: impl Fn(Word, Word) -> Option<Word>) -> Result<(), Trap> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        let result = f(a, b).ok_or(Trap::Arithmetic)?;
+        self.push(result)
+    }
+
+    fn immediate(&self, offset: usize) -> u8 {
+        self.code[self.pc + offset]
+    }
+
+    fn jump(&mut self, dest: Word) -> Result<(), Trap> {
+        let dest = usize::try_from(dest).unwrap_or(usize::MAX);
+        if !self.jump_dests.contains(&dest) {
+            return Err(Trap::InvalidJump(dest));
+        }
+        self.pc = dest;
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<Option<Word>, Trap> {
+        while self.pc < self.code.len() {
+            let op = Op::from_byte(self.code[self.pc]).ok_or(Trap::InvalidOpcode(self.code[self.pc]))?;
+            self.gas_used += op.gas();
+            if self.gas_used > self.ctx.gas_limit {
+                return Err(Trap::OutOfGas);
+            }
+            let mut next = self.pc + 1 + op.operand_len();
+            match op {
+                Op::Stop => return Ok(None),
+                Op::Push => {
+                    let len = self.immediate(1) as usize;
+                    let bytes = &self.code[self.pc + 2..self.pc + 2 + len];
+                    let value = bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as Word);
+                    next = self.pc + 2 + len;
+                    self.push(value)?;
+                }
+                Op::Pop => {
+                    self.pop()?;
+                }
+                Op::Dup => {
+                    let n = self.immediate(1) as usize;
+                    let value = *self.stack.iter().rev().nth(n).ok_or(Trap::StackUnderflow)?;
+                    self.push(value)?;
+                }
+                Op::Swap => {
+                    let n = self.immediate(1) as usize;
+                    let top = self.stack.len().checked_sub(1).ok_or(Trap::StackUnderflow)?;
+                    let other = top.checked_sub(n).filter(|_| n > 0).ok_or(Trap::StackUnderflow)?;
+                    self.stack.swap(top, other);
+                }
+                Op::Add => self.binary(Word::checked_add)?,
+                Op::Sub => self.binary(Word::checked_sub)?,
+                Op::Mul => self.binary(Word::checked_mul)?,
+                Op::Div => self.binary(Word::checked_div)?,
+                Op::Mod => self.binary(Word::checked_rem)?,
+                Op::Lt => self.binary(|a, b| Some((a < b) as Word))?,
+                Op::Gt => self.binary(|a, b| Some((a > b) as Word))?,
+                Op::Eq => self.binary(|a, b| Some((a == b) as Word))?,
+                Op::And => self.binary(|a, b| Some(a & b))?,
+                Op::Or => self.binary(|a, b| Some(a | b))?,
+                Op::IsZero => {
+                    let value = self.pop()?;
+                    self.push((value == 0) as Word)?;
+                }
+                Op::Jump => {
+                    let dest = self.pop()?;
+                    self.jump(dest)?;
+                    continue;
+                }
+                Op::JumpI => {
+                    let dest = self.pop()?;
+                    let cond = self.pop()?;
+                    if cond != 0 {
+                        self.jump(dest)?;
+                        continue;
+                    }
+                }
+                Op::JumpDest => {}
+                Op::SLoad => {
+                    let key = self.pop()?;
+                    let value = match self.storage_writes.get(&key) {
+                        Some(value) => *value,
+                        None => self.ctx.storage.get(&key).copied().unwrap_or(0),
+                    };
+                    self.push(value)?;
+                }
+                Op::SStore => {
+                    let key = self.pop()?;
+                    let value = self.pop()?;
+                    self.storage_writes.insert(key, value);
+                }
+                Op::Caller => self.push(self.ctx.caller)?,
+                Op::CallValue => self.push(self.ctx.value)?,
+                Op::Arg => {
+                    let n = self.immediate(1);
+                    let value = *self.ctx.args.get(n as usize).ok_or(Trap::MissingArgument(n))?;
+                    self.push(value)?;
+                }
+                Op::ArgCount => self.push(self.ctx.args.len() as Word)?,
+                Op::Balance => self.push(self.balance)?,
+                Op::Transfer => {
+                    let to = self.pop()?;
+                    let amount = self.pop()?;
+                    self.balance = self.balance.checked_sub(amount).ok_or(Trap::InsufficientFunds)?;
+                    self.transfers.push((to, amount));
+                }
+                Op::Return => return self.pop().map(Some),
+                Op::Revert => return Err(Trap::Revert),
+            }
+            self.pc = next;
+        }
+        Ok(None)
+    }
+}
+
+/// Runs `code` to completion. Deterministic: the same code and context
+/// always give the same execution.
+pub fn execute(code: &[u8], ctx: &Context) -> Execution {
+    let jump_dests = match validate(code) {
+        Ok(jump_dests) => jump_dests,
+        Err(trap) => {
+            return Execution { gas_used: 0, result: Err(trap), storage_writes: BTreeMap::new(), transfers: Vec::new() };
+        }
+    };
+    let mut machine = Machine {
+        code,
+        ctx,
+        jump_dests,
+        pc: 0,
+        stack: Vec::new(),
+        gas_used: 0,
+        balance: ctx.balance,
+        storage_writes: BTreeMap::new(),
+        transfers: Vec::new(),
+    };
+    let result = machine.run();
+    let gas_used = machine.gas_used.min(ctx.gas_limit);
+    match result {
+        Ok(output) => Execution {
+            gas_used,
+            result: Ok(output),
+            storage_writes: machine.storage_writes,
+            transfers: machine.transfers,
+        },
+        Err(trap) => Execution { gas_used, result: Err(trap), storage_writes: BTreeMap::new(), transfers: Vec::new() },
+    }
+}
+
+/// Assembles one instruction per line. `;` starts a comment, `name:`
+/// defines a label (emitted as `JUMPDEST`) and `PUSH @name` pushes its
+/// offset. `DUP`, `SWAP` and `ARG` take a small number.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    enum Item<'a> {
+        Op(Op),
+        OpWithByte(Op, u8),
+        Push(Word),
+        PushLabel(&'a str, usize),
+    }
+
+    let mut items = Vec::new();
+    let mut labels = BTreeMap::new();
+    let mut offset = 0;
+    for (index, raw) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            if label.is_empty() || labels.insert(label, offset).is_some() {
+                return Err(AsmError { line: line_no, message: format!("Invalid or duplicate label '{}'", label) });
+            }
+            items.push(Item::Op(Op::JumpDest));
+            offset += 1;
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let mnemonic = parts.next().unwrap_or("").to_ascii_uppercase();
+        let operand = parts.next();
+        if parts.next().is_some() {
+            return Err(AsmError { line: line_no, message: "Too many operands".to_string() });
+        }
+        let op = Op::ALL
+            .into_iter()
+            .find(|op| op.mnemonic() == mnemonic)
+            .ok_or_else(|| AsmError { line: line_no, message: format!("Unknown instruction '{}'", mnemonic) })?;
+        let item = match (op, operand) {
+            (Op::Push, Some(operand)) => match operand.strip_prefix('@') {
+                Some(label) => Item::PushLabel(label, line_no),
+                None => Item::Push(parse_number(operand).ok_or_else(|| AsmError {
+                    line: line_no,
+                    message: format!("Invalid number '{}'", operand),
+                })?),
+            },
+            (Op::Dup | Op::Swap | Op::Arg, Some(operand)) => {
+                let n = operand.parse().map_err(|_| AsmError { line: line_no, message: format!("Invalid operand '{}'", operand) })?;
+                Item::OpWithByte(op, n)
+            }
+            (Op::Push | Op::Dup | Op::Swap | Op::Arg, None) => {
+                return Err(AsmError { line: line_no, message: format!("{} needs an operand", op.mnemonic()) });
+            }
+            (_, Some(_)) => {
+                return Err(AsmError { line: line_no, message: format!("{} takes no operand", op.mnemonic()) });
+            }
+            (_, None) => Item::Op(op),
+        };
+        offset += match &item {
+            Item::Op(_) => 1,
+            Item::OpWithByte(..) => 2,
+            Item::Push(value) => 2 + push_bytes(*value).len(),
+            // Label offsets are unknown on the first pass, so they always
+            // take the full width.
+            Item::PushLabel(..) => 2 + 16,
+        };
+        items.push(item);
+    }
+
+    let mut code = Vec::with_capacity(offset);
+    for item in items {
+        match item {
+            Item::Op(op) => code.push(op.byte()),
+            Item::OpWithByte(op, n) => code.extend([op.byte(), n]),
+            Item::Push(value) => {
+                let bytes = push_bytes(value);
+                code.extend([Op::Push.byte(), bytes.len() as u8]);
+                code.extend(bytes);
+            }
+            Item::PushLabel(label, line) => {
+                let target = *labels
+                    .get(label)
+                    .ok_or_else(|| AsmError { line, message: format!("Unknown label '{}'", label) })?;
+                code.extend([Op::Push.byte(), 16]);
+                code.extend((target as Word).to_be_bytes());
+            }
+        }
+    }
+    Ok(code)
+}
+
+fn parse_number(text: &str) -> Option<Word> {
+    match text.strip_prefix("0x") {
+        Some(hex) => Word::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Shortest big-endian encoding of `value`; zero encodes as no bytes.
+fn push_bytes(value: Word) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    bytes[skip..].to_vec()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+impl ErrorCode for AsmError {
+    fn code(&self) -> u16 {
+        10001
+    }
+
+    fn module(&self) -> &'static str {
+        "vm"
+    }
+
+    fn http_status(&self) -> u16 {
+        400
+    }
+}
+
+/// Example contracts, as assembly.
+pub mod examples {
+    /// A counter anyone can bump.
+    pub const COUNTER: &str = include_str!("../contracts/counter.asm");
+    /// Holds a deposit until an arbiter releases or refunds it.
+    pub const ESCROW: &str = include_str!("../contracts/escrow.asm");
+    /// A fungible token with a fixed supply.
+    pub const TOKEN: &str = include_str!("../contracts/token.asm");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(code: &[u8], args: &[Word], storage: &BTreeMap<Word, Word>, gas_limit: u64) -> Execution {
+        let ctx = Context { caller: 7, value: 0, balance: 0, args, storage, gas_limit };
+        execute(code, &ctx)
+    }
+
+    #[test]
+    fn test_assemble_and_run_counter() {
+        let code = assemble(examples::COUNTER).unwrap();
+        let mut storage = BTreeMap::new();
+
+        let bumped = call(&code, &[1, 5], &storage, 1_000);
+        assert_eq!(bumped.result, Ok(Some(5)));
+        assert_eq!(bumped.storage_writes, BTreeMap::from([(0, 5)]));
+        storage.extend(bumped.storage_writes);
+
+        assert_eq!(call(&code, &[0], &storage, 1_000).result, Ok(Some(6)));
+        assert_eq!(call(&code, &[2], &storage, 1_000).result, Ok(Some(5)));
+        assert_eq!(call(&code, &[9], &storage, 1_000).result, Err(Trap::Revert));
+    }
+
+    #[test]
+    fn test_out_of_gas_and_traps_discard_writes() {
+        let code = assemble(examples::COUNTER).unwrap();
+        let storage = BTreeMap::new();
+
+        let starved = call(&code, &[0], &storage, 30);
+        assert_eq!(starved.result, Err(Trap::OutOfGas));
+        assert_eq!(starved.gas_used, 30);
+        assert!(starved.storage_writes.is_empty());
+
+        let overflow = assemble("PUSH 0xffffffffffffffffffffffffffffffff\nPUSH 1\nADD").unwrap();
+        assert_eq!(call(&overflow, &[], &storage, 100).result, Err(Trap::Arithmetic));
+        let bad_jump = assemble("PUSH 3\nJUMP").unwrap();
+        assert_eq!(call(&bad_jump, &[], &storage, 100).result, Err(Trap::InvalidJump(3)));
+    }
+
+    #[test]
+    fn test_assembler_errors() {
+        assert_eq!(assemble("PUSH @missing").unwrap_err().line, 1);
+        assert_eq!(assemble("; fine\nFROB").unwrap_err(), AsmError { line: 2, message: "Unknown instruction 'FROB'".to_string() });
+        assert!(assemble("ADD 1").is_err());
+        assert_eq!(validate(&[Op::Push.byte(), 4, 1]), Err(Trap::Truncated));
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use rsm_en::balances;
+use rsm_en::client::{http_request, DEFAULT_NODE};
+use rsm_en::governance::{self, ParameterChange};
+use rsm_en::keys::KeyPair;
+use rsm_en::merkle::{FastMerkleTree, Hash};
+use rsm_en::runtime::Call;
+use rsm_en::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const DEFAULT_WALLET: &str = "wallet.json";
+
+/// Named secret keys, stored unencrypted: keep the file private.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Wallet {
+    keys: BTreeMap<String, String>,
+}
+
+impl Wallet {
+    fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Cannot parse {}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Wallet::default()),
+            Err(e) => Err(format!("Cannot read {}: {}", path, e)),
+        }
+    }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap()).map_err(|e| format!("Cannot write {}: {}", path, e))
+    }
+
+    fn add(&mut self, name: &str, key: &KeyPair) -> Result<(), String> {
+        if self.keys.contains_key(name) {
+            return Err(format!("A key named '{}' already exists", name));
+        }
+        self.keys.insert(name.to_string(), key.secret_hex());
+        Ok(())
+    }
+
+    fn key(&self, name: &str) -> Result<KeyPair, String> {
+        let secret = self.keys.get(name).ok_or_else(|| format!("No key named '{}'", name))?;
+        KeyPair::from_secret_hex(secret).ok_or_else(|| format!("Key '{}' is corrupt", name))
+    }
+
+    /// The address of a stored key, or `account` itself.
+    fn resolve(&self, account: &str) -> String {
+        self.key(account).map(|key| key.address().to_hex()).unwrap_or_else(|_| account.to_string())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run_command(&args) {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Keys live in `PHLOP_WALLET` (default wallet.json); node commands go to
+/// `PHLOP_NODE` (default 127.0.0.1:3030) over JSON-RPC.
+///   new <name>                        generate a key
+///   import <name> <secret>            add a hex secret key
+///   list                              names and addresses
+///   balance <name|account>            balance and next nonce
+///   sign <name> <to> <amount>         print a signed transfer
+///   transfer <name> <to> <amount>     sign a transfer and submit it
+///   submit <file>                     submit a signed transfer
+///   history <name|account>            transactions involving the account
+///   proof <name|account> <tx_hash>    fetch and verify an inclusion proof
+///   propose <name> <parameter> <value>
+///   vote <name> <proposal_id> <aye|nay>
+fn run_command(args: &[String]) -> Result<(), String> {
+    let node = std::env::var("PHLOP_NODE").unwrap_or_else(|_| DEFAULT_NODE.to_string());
+    let path = std::env::var("PHLOP_WALLET").unwrap_or_else(|_| DEFAULT_WALLET.to_string());
+    let mut wallet = Wallet::load(&path)?;
+
+    let output = match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["new", name] => {
+            let key = KeyPair::generate();
+            wallet.add(name, &key)?;
+            wallet.save(&path)?;
+            format!("🔑 {}: {}", name, key.address().to_hex())
+        }
+        ["import", name, secret] => {
+            let key = KeyPair::from_secret_hex(secret).ok_or("Secret must be 32 bytes of hex")?;
+            wallet.add(name, &key)?;
+            wallet.save(&path)?;
+            format!("🔑 {}: {}", name, key.address().to_hex())
+        }
+        ["list"] => wallet
+            .keys
+            .keys()
+            .map(|name| Ok(format!("{}  {}", name, wallet.key(name)?.address().to_hex())))
+            .collect::<Result<Vec<_>, String>>()?
+            .join("\n"),
+        ["balance", account] => {
+            let account = wallet.resolve(account);
+            let balance = rpc(&node, "state_getBalance", json!([account]))?;
+            let nonce = rpc(&node, "state_getNonce", json!([account]))?;
+            format!("{}\nbalance: {}\nnext nonce: {}", account, balance.as_str().unwrap_or_default(), nonce)
+        }
+        ["sign", name, to, amount] => {
+            let transaction = build_transfer(&node, &wallet, name, to, amount)?;
+            serde_json::to_string_pretty(&transaction).unwrap()
+        }
+        ["transfer", name, to, amount] => {
+            let transaction = build_transfer(&node, &wallet, name, to, amount)?;
+            submit(&node, &transaction)?
+        }
+        ["submit", file] => {
+            let json = std::fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+            let transaction: Transaction = serde_json::from_str(&json).map_err(|e| format!("Invalid transaction in {}: {}", file, e))?;
+            submit(&node, &transaction)?
+        }
+        ["history", account] => {
+            let account = wallet.resolve(account);
+            history(&node, &account)?.iter().map(describe).collect::<Vec<_>>().join("\n")
+        }
+        ["proof", account, tx_hash] => {
+            let account = wallet.resolve(account);
+            verify_proof(&node, &account, tx_hash)?
+        }
+        ["propose", name, parameter, value] => {
+            let change = ParameterChange::parse(parameter, value).map_err(|e| e.to_string())?;
+            let transaction = build_call(&node, &wallet, name, Call::Governance(governance::Call::Propose { change }))?;
+            submit_governance(&node, "/api/governance/propose", &transaction)?
+        }
+        ["vote", name, proposal_id, choice] => {
+            let proposal_id: u32 = proposal_id.parse().map_err(|_| format!("Invalid proposal id '{}'", proposal_id))?;
+            let aye = match *choice {
+                "aye" => true,
+                "nay" => false,
+                _ => return Err(format!("Vote must be 'aye' or 'nay', got '{}'", choice)),
+            };
+            let transaction = build_call(&node, &wallet, name, Call::Governance(governance::Call::Vote { proposal_id, aye }))?;
+            submit_governance(&node, "/api/governance/vote", &transaction)?
+        }
+        _ => {
+            return Err("Usage: wallet [new <name> | import <name> <secret> | list | balance <name|account> | sign <name> <to> <amount> | transfer <name> <to> <amount> | submit <file> | history <name|account> | proof <name|account> <tx_hash> | propose <name> <parameter> <value> | vote <name> <proposal_id> <aye|nay>]".to_string());
+        }
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+fn build_transfer(node: &str, wallet: &Wallet, name: &str, to: &str, amount: &str) -> Result<Transaction, String> {
+    let amount: u128 = amount.parse().map_err(|_| format!("Invalid amount '{}'", amount))?;
+    build_call(node, wallet, name, Call::Balances(balances::Call::Transfer { to: wallet.resolve(to), amount }))
+}
+
+/// A call from key `name` with its next nonce, bound to the node's chain
+/// and signed.
+fn build_call(node: &str, wallet: &Wallet, name: &str, call: Call) -> Result<Transaction, String> {
+    let key = wallet.key(name)?;
+    let from = key.address().to_hex();
+    let nonce = rpc(node, "state_getNonce", json!([from]))?.as_u64().ok_or("Node sent an invalid nonce")?;
+    let chain_id = rpc(node, "chain_getChainId", json!([]))?.as_u64().ok_or("Node sent an invalid chain ID")?;
+    Ok(Transaction::new_call(from, call, nonce as u32).with_chain_id(chain_id).signed(&key))
+}
+
+fn submit_governance(node: &str, path: &str, transaction: &Transaction) -> Result<String, String> {
+    let response = http_request(node, "POST", path, &json!({ "transaction": transaction }).to_string())?;
+    let response: Value = serde_json::from_str(&response).map_err(|e| format!("Invalid response from node: {}", e))?;
+    Ok(format!("✅ {} ({})", response["message"].as_str().unwrap_or_default(), transaction.hash))
+}
+
+fn submit(node: &str, transaction: &Transaction) -> Result<String, String> {
+    let hash = rpc(node, "author_submitTransaction", json!([transaction]))?;
+    Ok(format!("✅ Submitted {}; it is included with the next block", hash.as_str().unwrap_or_default()))
+}
+
+fn history(node: &str, account: &str) -> Result<Vec<Transaction>, String> {
+    let history = rpc(node, "state_getTransactionHistory", json!([account]))?;
+    serde_json::from_value(history).map_err(|e| format!("Node sent an invalid history: {}", e))
+}
+
+fn describe(transaction: &Transaction) -> String {
+    match transaction.transfer_details() {
+        Some((to, amount)) => format!("{}  #{} {} -> {}: {}", transaction.hash, transaction.nonce, transaction.from, to, amount),
+        None => format!("{}  #{} {}: {:?}", transaction.hash, transaction.nonce, transaction.from, transaction.call),
+    }
+}
+
+/// Checks that `tx_hash` is one of `account`'s transactions and that the
+/// node's Merkle proof links it to the root of the block it names.
+fn verify_proof(node: &str, account: &str, tx_hash: &str) -> Result<String, String> {
+    let hash = Hash::from_hex(tx_hash).ok_or_else(|| format!("Invalid transaction hash '{}'", tx_hash))?;
+    let transaction = history(node, account)?
+        .into_iter()
+        .find(|tx| tx.hash == hash)
+        .ok_or_else(|| format!("{} is not a confirmed transaction of {}", tx_hash, account))?;
+    if transaction.calculate_hash() != hash {
+        return Err("Node sent a transaction that does not match its hash".to_string());
+    }
+
+    let proof = rpc(node, "chain_getTransactionProof", json!([tx_hash]))?;
+    let block_index = proof["block_index"].as_u64().ok_or("Node has no proof for this transaction")?;
+    let tx_index = proof["tx_index"].as_u64().ok_or("Node sent an invalid proof")? as usize;
+    let path = proof["proof"]
+        .as_array()
+        .ok_or("Node sent an invalid proof")?
+        .iter()
+        .map(|step| step.as_str().and_then(Hash::from_hex))
+        .collect::<Option<Vec<Hash>>>()
+        .ok_or("Node sent an invalid proof")?;
+
+    let block = rpc(node, "chain_getBlock", json!([block_index]))?;
+    let root = block["merkle_root"].as_str().and_then(Hash::from_hex).ok_or("Node sent an invalid block")?;
+    if !FastMerkleTree::verify_against_root(&root, &hash, &path, tx_index) {
+        return Err(format!("❌ Proof for {} does not match block #{}", tx_hash, block_index));
+    }
+    Ok(format!(
+        "✅ {} is transaction {} of block #{} ({}); {} proof hashes lead to Merkle root {}",
+        tx_hash,
+        tx_index,
+        block_index,
+        block["hash"].as_str().unwrap_or_default(),
+        path.len(),
+        root
+    ))
+}
+
+/// Calls `method` on the node's JSON-RPC endpoint.
+fn rpc(node: &str, method: &str, params: Value) -> Result<Value, String> {
+    let body = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+    let response = http_request(node, "POST", "/rpc", &body.to_string())?;
+    let mut response: Value = serde_json::from_str(&response).map_err(|e| format!("Invalid response from node: {}", e))?;
+    match response.get("error") {
+        Some(error) => Err(format!("Node rejected {}: {}", method, error["message"].as_str().unwrap_or_default())),
+        None => Ok(response["result"].take()),
+    }
+}
+
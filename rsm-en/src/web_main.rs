@@ -1,17 +1,24 @@
 use std::io::prelude::*;
 use std::net::{TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
 use std::thread;
-mod balances;
-mod system;
-mod merkle;
-mod transaction;
-mod blockchain;
-mod rps_mining;
-
-use blockchain::Blockchain;
-use transaction::Transaction;
+use std::time::Duration;
+
+use rsm_en::blockchain::{Blockchain, TransactionStatus};
+use rsm_en::error::{reason_phrase, ErrorCode, RequestError};
+use rsm_en::events::EventFilter;
+use rsm_en::explorer::{self, BlockSummary, PageRequest};
+use rsm_en::genesis::GenesisSpec;
+use rsm_en::governance;
+use rsm_en::merkle::Hash;
+use rsm_en::p2p::{Node, NodeConfig};
+use rsm_en::rpc;
+use rsm_en::runtime::Call;
+use rsm_en::snapshot::SyncBundle;
+use rsm_en::stream::{self, EventStream, Subscription};
+use rsm_en::transaction::Transaction;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +50,40 @@ struct MineBlockRequest {
     session_id: String,
 }
 
+/// A governance transaction signed by the proposer's or voter's key, as
+/// `wallet propose` and `wallet vote` send.
+#[derive(Debug, Deserialize)]
+struct GovernanceRequest {
+    transaction: Transaction,
+}
+
+/// A transfer to queue: a complete, usually signed, `transaction`, or
+/// `from`, `to` and `amount` for the node to build an unsigned one.
+#[derive(Debug, Deserialize)]
+struct SendRequest {
+    transaction: Option<Transaction>,
+    from: Option<String>,
+    to: Option<String>,
+    amount: Option<u128>,
+    /// Defaults to the sender's next nonce.
+    nonce: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct TransactionStatusResponse {
+    transaction_hash: String,
+    #[serde(flatten)]
+    status: Option<TransactionStatus>,
+    pending_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct GovernanceResponse {
+    success: bool,
+    message: String,
+    transaction_hash: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 struct MiningResponse {
     success: bool,
@@ -51,6 +92,24 @@ struct MiningResponse {
     mining_result: Option<MiningResult>,
 }
 
+/// Progress of a `/api/mine` request, streamed as `mining` events.
+#[derive(Debug, Serialize)]
+struct MiningEvent {
+    session_id: String,
+    miner: String,
+    block_number: u32,
+    /// `started`, `mined` or `failed`.
+    status: &'static str,
+    result: Option<MiningResult>,
+}
+
+/// Blocks above `fork_height` that were streamed before were replaced.
+#[derive(Debug, Serialize)]
+struct ReorgEvent {
+    fork_height: u32,
+    height: u32,
+}
+
 #[derive(Debug, Serialize)]
 struct BlockchainStatus {
     total_blocks: usize,
@@ -61,33 +120,132 @@ struct BlockchainStatus {
 
 type SharedBlockchain = Arc<Mutex<Blockchain>>;
 type SharedSessions = Arc<Mutex<HashMap<String, MinerSession>>>;
+/// Transfers the pool refused, newest last. The chain never sees them,
+/// so their status is kept here.
+type SharedRejections = Arc<Mutex<VecDeque<(Hash, TransactionStatus)>>>;
+
+const REMEMBERED_REJECTIONS: usize = 256;
+
+/// How often the chain is checked for blocks to stream.
+const CHAIN_WATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// Streamed blocks remembered for spotting reorgs.
+const WATCHED_BLOCKS: usize = 256;
 
 fn main() {
-    println!("🌐 PhlopChain Web Interface starting on http://localhost:3030");
-    println!("📖 Visit http://localhost:3030 in your browser to start mining!");
+    let port = std::env::var("PHLOP_HTTP_PORT").unwrap_or_else(|_| "3030".to_string());
+    println!("🌐 PhlopChain Web Interface starting on http://localhost:{}", port);
+    println!("📖 Visit http://localhost:{} in your browser to start mining!", port);
     
-    let blockchain = Arc::new(Mutex::new(Blockchain::new()));
+    let spec = load_genesis();
+    let mut blockchain = load_blockchain(&spec);
+    blockchain.set_dev_accounts(dev_accounts_enabled());
+    let blockchain = Arc::new(Mutex::new(blockchain));
     let sessions: SharedSessions = Arc::new(Mutex::new(HashMap::new()));
+    let rejections: SharedRejections = Arc::new(Mutex::new(VecDeque::new()));
+    let network = start_network(&blockchain, spec);
+    if dev_accounts_enabled() {
+        println!("⚠️  PHLOP_DEV_ACCOUNTS=1: unsigned transfers from named accounts are accepted");
+    }
+    let events = EventStream::new(stream::DEFAULT_CAPACITY);
+    let watched_chain = Arc::clone(&blockchain);
+    let block_events = events.clone();
+    thread::spawn(move || watch_chain(watched_chain, block_events));
 
-    let listener = TcpListener::bind("0.0.0.0:3030").unwrap();
-    println!("PhlopChain web server running on http://0.0.0.0:3030");
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).unwrap();
+    println!("PhlopChain web server running on http://0.0.0.0:{}", port);
     
     for stream in listener.incoming() {
         let stream = stream.unwrap();
         let blockchain_clone = Arc::clone(&blockchain);
         let sessions_clone = Arc::clone(&sessions);
+        let network_clone = network.clone();
+        let events_clone = events.clone();
+        let rejections_clone = Arc::clone(&rejections);
         
         thread::spawn(move || {
-            handle_connection(stream, blockchain_clone, sessions_clone);
+            handle_connection(stream, blockchain_clone, sessions_clone, network_clone, events_clone, rejections_clone);
         });
     }
 }
 
-fn handle_connection(mut stream: TcpStream, blockchain: SharedBlockchain, sessions: SharedSessions) {
-    let mut buffer = [0; 4096];
-    let bytes_read = stream.read(&mut buffer).unwrap_or(0);
-    
-    let request = String::from_utf8_lossy(&buffer[..bytes_read]);
+/// Whether `PHLOP_DEV_ACCOUNTS=1` lets unsigned transfers from named
+/// accounts into the pool, whether they come over HTTP, RPC or gossip.
+fn dev_accounts_enabled() -> bool {
+    std::env::var("PHLOP_DEV_ACCOUNTS").is_ok_and(|value| value == "1")
+}
+
+/// Joins the P2P network when `PHLOP_P2P_LISTEN` is set, dialing the
+/// comma-separated addresses in `PHLOP_PEERS`. Catch-up progress is kept
+/// in `PHLOP_SYNC_STATE` if set.
+fn start_network(blockchain: &SharedBlockchain, genesis: GenesisSpec) -> Option<Node> {
+    let listen = std::env::var("PHLOP_P2P_LISTEN").ok()?;
+    let bootstrap = std::env::var("PHLOP_PEERS")
+        .map(|peers| peers.split(',').map(|peer| peer.trim().to_string()).filter(|peer| !peer.is_empty()).collect())
+        .unwrap_or_default();
+    let sync_state = std::env::var("PHLOP_SYNC_STATE").ok().map(PathBuf::from);
+    let config = NodeConfig { listen, bootstrap, genesis, sync_state, ..Default::default() };
+    match Node::start(Arc::clone(blockchain), config) {
+        Ok(node) => {
+            println!("🔗 P2P listening on {}", node.local_addr());
+            Some(node)
+        }
+        Err(e) => {
+            eprintln!("❌ Cannot start P2P networking: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Boots from the sync bundle at `PHLOP_SNAPSHOT` if set, else from genesis.
+fn load_blockchain(spec: &GenesisSpec) -> Blockchain {
+    let Ok(path) = std::env::var("PHLOP_SNAPSHOT") else {
+        return Blockchain::from_genesis(spec).expect("genesis spec was validated");
+    };
+    let synced = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| SyncBundle::from_json(&json).map_err(|e| e.to_string()))
+        .and_then(|bundle| Blockchain::from_snapshot(bundle).map_err(|e| e.to_string()));
+    match synced {
+        Ok(blockchain) => {
+            println!("⚡ Synced from {} up to block #{}", path, blockchain.get_latest_block().index);
+            blockchain
+        }
+        Err(e) => {
+            eprintln!("❌ Cannot sync from {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the spec file in `PHLOP_GENESIS`, or the dev chain's spec.
+fn load_genesis() -> GenesisSpec {
+    let Ok(path) = std::env::var("PHLOP_GENESIS") else {
+        return GenesisSpec::default();
+    };
+    let loaded = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| GenesisSpec::from_json(&json).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(spec) => {
+            println!("🌱 Loaded {} (chain ID {}) from {}", spec.name, spec.chain_id, path);
+            spec
+        }
+        Err(e) => {
+            eprintln!("❌ Cannot load genesis from {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    blockchain: SharedBlockchain,
+    sessions: SharedSessions,
+    network: Option<Node>,
+    events: EventStream,
+    rejections: SharedRejections,
+) {
+    let request = read_request(&mut stream);
     let request_line = request.lines().next().unwrap_or("");
     
     println!("Received request: {}", request_line);
@@ -101,16 +259,43 @@ fn handle_connection(mut stream: TcpStream, blockchain: SharedBlockchain, sessio
     } else if request_line.starts_with("POST /api/start") {
         handle_start_mining(&request, sessions)
     } else if request_line.starts_with("POST /api/mine") {
-        handle_mine_block(&request, blockchain, sessions)
+        handle_mine_block(&request, blockchain, sessions, network.as_ref(), &events)
+    } else if request_line.starts_with("GET /api/stream") {
+        match subscribe(&request, &events) {
+            Ok(subscription) => return stream_events(stream, subscription),
+            Err(e) => error_response(&e),
+        }
+    } else if request_line.starts_with("POST /api/transactions") {
+        handle_send_transaction(&request, blockchain, network.as_ref(), rejections)
+    } else if request_line.starts_with("GET /api/transactions/") {
+        handle_transaction_status(request_line, blockchain, rejections)
     } else if request_line.starts_with("GET /api/blockchain") {
         handle_blockchain_status(blockchain, sessions)
     } else if request_line.starts_with("GET /api/history") {
         handle_mining_history(blockchain, sessions)
+    } else if request_line.starts_with("POST /api/governance/propose") {
+        handle_propose(&request, blockchain, network.as_ref())
+    } else if request_line.starts_with("POST /api/governance/vote") {
+        handle_vote(&request, blockchain, network.as_ref())
+    } else if request_line.starts_with("GET /api/governance/proposals") {
+        handle_list_proposals(blockchain)
+    } else if request_line.starts_with("GET /api/events") {
+        handle_events(request_line, blockchain)
+    } else if request_line.starts_with("GET /api/snapshot") {
+        handle_snapshot(blockchain)
+    } else if request_line.starts_with("GET /api/peers") {
+        handle_peers(network.as_ref())
+    } else if request_line.starts_with("GET /api/sync") {
+        handle_sync_status(blockchain, network.as_ref())
+    } else if request_line.starts_with("GET /api/explorer/") {
+        handle_explorer(request_line, blockchain)
+    } else if request_line.starts_with("POST /rpc") {
+        handle_rpc(&request, blockchain, network.as_ref())
     } else if request_line.starts_with("GET /api/status/") {
         let session_id = extract_session_id(request_line);
         handle_get_status(&session_id, sessions)
     } else {
-        ("HTTP/1.1 404 NOT FOUND".to_string(), "404 Not Found".to_string())
+        error_response(&RequestError::NotFound("Route".to_string()))
     };
     
     let response = format!(
@@ -120,10 +305,37 @@ fn handle_connection(mut stream: TcpStream, blockchain: SharedBlockchain, sessio
         contents
     );
     
-    stream.write(response.as_bytes()).unwrap();
+    stream.write_all(response.as_bytes()).unwrap();
     stream.flush().unwrap();
 }
 
+/// Reads the head, then as much body as `Content-Length` announces, so
+/// large RPC batches are not cut off at one read.
+fn read_request(stream: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let bytes_read = stream.read(&mut buffer).unwrap_or(0);
+        request.extend_from_slice(&buffer[..bytes_read]);
+        let Some(head_end) = request.windows(4).position(|window| window == b"\r\n\r\n") else {
+            if bytes_read == 0 {
+                break;
+            }
+            continue;
+        };
+        let content_length = String::from_utf8_lossy(&request[..head_end])
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        if bytes_read == 0 || request.len() >= head_end + 4 + content_length {
+            break;
+        }
+    }
+    String::from_utf8_lossy(&request).into_owned()
+}
+
 fn extract_body(request: &str) -> String {
     if let Some(body_start) = request.find("\r\n\r\n") {
         let body = &request[body_start + 4..];
@@ -134,6 +346,47 @@ fn extract_body(request: &str) -> String {
     }
 }
 
+/// Query string of the request path as key/value pairs. Values are taken
+/// verbatim; callers only pass account names and numbers.
+fn extract_query(request_line: &str) -> HashMap<String, String> {
+    request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|path| path.split_once('?'))
+        .map(|(_, query)| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Decodes `%XX` escapes, as browsers send account names in paths.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 fn extract_session_id(request_line: &str) -> String {
     if let Some(path) = request_line.split_whitespace().nth(1) {
         if let Some(id) = path.strip_prefix("/api/status/") {
@@ -147,61 +400,58 @@ fn handle_start_mining(request: &str, sessions: SharedSessions) -> (String, Stri
     let body = extract_body(request);
     println!("Received start mining request body: '{}'", body);
     
-    if let Ok(req) = serde_json::from_str::<StartMiningRequest>(&body) {
-        let session_id = generate_uuid();
-        let session = MinerSession {
-            id: session_id.clone(),
-            name: req.miner_name,
-            total_phlopcoin: 0.0,
-            blocks_mined: 0,
-            mining_history: Vec::new(),
-        };
-        
-        let mut sessions_guard = sessions.lock().unwrap();
-        sessions_guard.insert(session_id, session.clone());
-        
-        let response = MiningResponse {
-            success: true,
-            message: "Mining session started successfully!".to_string(),
-            session: Some(session),
-            mining_result: None,
-        };
-        
-        ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&response).unwrap())
-    } else {
-        ("HTTP/1.1 400 BAD REQUEST".to_string(), "Invalid request".to_string())
-    }
+    let req = match serde_json::from_str::<StartMiningRequest>(&body) {
+        Ok(req) => req,
+        Err(e) => return error_response(&RequestError::InvalidBody(e.to_string())),
+    };
+
+    let session_id = generate_uuid();
+    let session = MinerSession {
+        id: session_id.clone(),
+        name: req.miner_name,
+        total_phlopcoin: 0.0,
+        blocks_mined: 0,
+        mining_history: Vec::new(),
+    };
+    
+    let mut sessions_guard = sessions.lock().unwrap();
+    sessions_guard.insert(session_id, session.clone());
+    
+    let response = MiningResponse {
+        success: true,
+        message: "Mining session started successfully!".to_string(),
+        session: Some(session),
+        mining_result: None,
+    };
+    
+    ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&response).unwrap())
 }
 
-fn handle_mine_block(request: &str, blockchain: SharedBlockchain, sessions: SharedSessions) -> (String, String) {
+fn handle_mine_block(request: &str, blockchain: SharedBlockchain, sessions: SharedSessions, network: Option<&Node>, events: &EventStream) -> (String, String) {
     let body = extract_body(request);
     println!("Received mine block request body: '{}'", body);
     
     if let Ok(req) = serde_json::from_str::<MineBlockRequest>(&body) {
         let mut sessions_guard = sessions.lock().unwrap();
         if let Some(session) = sessions_guard.get_mut(&req.session_id) {
-
-            let tx1 = Transaction::new(
-                "alice".to_string(),
-                session.name.clone(),
-                5,
-                1,
-            );
-            let tx2 = Transaction::new(
-                session.name.clone(),
-                "bob".to_string(),
-                3,
-                session.blocks_mined + 1,
-            );
-            
             let mut blockchain_guard = blockchain.lock().unwrap();
-            
 
-            let _ = blockchain_guard.add_transaction(tx1);
-            let _ = blockchain_guard.add_transaction(tx2);
+            let mut progress = MiningEvent {
+                session_id: session.id.clone(),
+                miner: session.name.clone(),
+                block_number: blockchain_guard.get_latest_block().index + 1,
+                status: "started",
+                result: None,
+            };
+            events.publish("mining", &progress);
             
-            match blockchain_guard.mine_pending_transactions(session.name.clone()) {
+            let mined = blockchain_guard.mine_pending_transactions(session.name.clone());
+            progress.status = if mined.as_ref().is_ok_and(|block| block.rps_mining_result.is_some()) { "mined" } else { "failed" };
+            match mined {
                 Ok(block) => {
+                    if let Some(node) = network {
+                        node.announce_block(&block);
+                    }
                     if let Some(ref rps_result) = block.rps_mining_result {
                         let min_games_needed = calculate_minimum_games_needed(&blockchain_guard);
                         let actual_games = rps_result.total_games as f64;
@@ -219,6 +469,8 @@ fn handle_mine_block(request: &str, blockchain: SharedBlockchain, sessions: Shar
                         session.total_phlopcoin += phlopcoin_earned;
                         session.blocks_mined += 1;
                         session.mining_history.push(mining_result.clone());
+                        progress.result = Some(mining_result.clone());
+                        events.publish("mining", &progress);
                         
                         let response = MiningResponse {
                             success: true,
@@ -229,6 +481,7 @@ fn handle_mine_block(request: &str, blockchain: SharedBlockchain, sessions: Shar
                         
                         ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&response).unwrap())
                     } else {
+                        events.publish("mining", &progress);
                         let response = MiningResponse {
                             success: false,
                             message: "Mining failed - no RPS result".to_string(),
@@ -239,20 +492,89 @@ fn handle_mine_block(request: &str, blockchain: SharedBlockchain, sessions: Shar
                     }
                 }
                 Err(e) => {
-                    let response = MiningResponse {
-                        success: false,
-                        message: format!("Mining failed: {}", e),
-                        session: Some(session.clone()),
-                        mining_result: None,
-                    };
-                    ("HTTP/1.1 500 INTERNAL SERVER ERROR".to_string(), serde_json::to_string(&response).unwrap())
+                    events.publish("mining", &progress);
+                    error_response(&e)
                 }
             }
         } else {
-            ("HTTP/1.1 404 NOT FOUND".to_string(), "Session not found".to_string())
+            error_response(&RequestError::NotFound("Session".to_string()))
         }
     } else {
-        ("HTTP/1.1 400 BAD REQUEST".to_string(), "Invalid request".to_string())
+        error_response(&RequestError::InvalidBody("expected {\"session_id\"}".to_string()))
+    }
+}
+
+/// POST /api/transactions: validates a transfer through the pool and
+/// queues it for the next block. Takes a signed `transaction`, as made by
+/// the wallet. Unsigned transactions, including the `from`/`to`/`amount`
+/// form, spend from named accounts that anyone can name, so they are only
+/// taken when `PHLOP_DEV_ACCOUNTS=1` is set on a development node.
+fn handle_send_transaction(request: &str, blockchain: SharedBlockchain, network: Option<&Node>, rejections: SharedRejections) -> (String, String) {
+    let req = match serde_json::from_str::<SendRequest>(&extract_body(request)) {
+        Ok(req) => req,
+        Err(e) => return error_response(&RequestError::InvalidBody(e.to_string())),
+    };
+    let tx = match req {
+        SendRequest { transaction: Some(tx), .. } => tx,
+        SendRequest { from: Some(from), to: Some(to), amount: Some(amount), nonce, .. } => {
+            let blockchain_guard = blockchain.lock().unwrap();
+            let nonce = nonce.unwrap_or_else(|| blockchain_guard.next_nonce(&from));
+            Transaction::new(from, to, amount, nonce).with_chain_id(blockchain_guard.chain_id)
+        }
+        _ => return error_response(&RequestError::InvalidBody("expected a transaction, or from, to and amount".to_string())),
+    };
+    let hash = tx.hash.clone();
+
+    let queued = match network {
+        Some(node) => node.submit_transaction(tx),
+        None => blockchain.lock().unwrap().add_transaction(tx),
+    };
+    if let Err(e) = queued {
+        let mut rejections_guard = rejections.lock().unwrap();
+        if rejections_guard.len() == REMEMBERED_REJECTIONS {
+            rejections_guard.pop_front();
+        }
+        let status = TransactionStatus::Rejected { block_index: None, code: e.code(), reason: e.to_string() };
+        rejections_guard.push_back((hash, status));
+        return error_response(&e);
+    }
+
+    let blockchain_guard = blockchain.lock().unwrap();
+    let response = TransactionStatusResponse {
+        transaction_hash: hash.to_hex(),
+        status: blockchain_guard.transaction_status(&hash),
+        pending_count: blockchain_guard.get_pending_transaction_count(),
+    };
+    ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&response).unwrap())
+}
+
+/// GET /api/transactions/<hash>: pending, included in a block, or
+/// rejected with the reason.
+fn handle_transaction_status(request_line: &str, blockchain: SharedBlockchain, rejections: SharedRejections) -> (String, String) {
+    let hash = request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|path| path.strip_prefix("/api/transactions/"))
+        .and_then(Hash::from_hex);
+    let Some(hash) = hash else {
+        return error_response(&RequestError::InvalidQuery("expected a transaction hash".to_string()));
+    };
+
+    let blockchain_guard = blockchain.lock().unwrap();
+    let status = blockchain_guard.transaction_status(&hash).or_else(|| {
+        let rejections_guard = rejections.lock().unwrap();
+        rejections_guard.iter().rev().find(|(rejected, _)| *rejected == hash).map(|(_, status)| status.clone())
+    });
+    match status {
+        Some(status) => {
+            let response = TransactionStatusResponse {
+                transaction_hash: hash.to_hex(),
+                status: Some(status),
+                pending_count: blockchain_guard.get_pending_transaction_count(),
+            };
+            ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&response).unwrap())
+        }
+        None => error_response(&RequestError::NotFound("Transaction".to_string())),
     }
 }
 
@@ -281,7 +603,7 @@ fn handle_mining_history(_blockchain: SharedBlockchain, sessions: SharedSessions
     }
     
 
-    all_mining_history.sort_by(|a, b| b.block_number.cmp(&a.block_number));
+    all_mining_history.sort_by_key(|r| std::cmp::Reverse(r.block_number));
     
 
     let recent_history: Vec<MiningResult> = all_mining_history.into_iter().take(20).collect();
@@ -289,15 +611,234 @@ fn handle_mining_history(_blockchain: SharedBlockchain, sessions: SharedSessions
     ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&recent_history).unwrap())
 }
 
+/// GET /api/events?account=alice&type=Transfer&from=1&to=10
+fn handle_events(request_line: &str, blockchain: SharedBlockchain) -> (String, String) {
+    let query = extract_query(request_line);
+    let parse_block = |key: &str| query.get(key).map(|value| value.parse::<u32>()).transpose();
+    let filter = match (parse_block("from"), parse_block("to")) {
+        (Ok(from_block), Ok(to_block)) => EventFilter {
+            account: query.get("account").cloned(),
+            kind: query.get("type").cloned(),
+            from_block,
+            to_block,
+        },
+        _ => return error_response(&RequestError::InvalidQuery("from and to must be block numbers".to_string())),
+    };
+
+    let blockchain_guard = blockchain.lock().unwrap();
+    ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&blockchain_guard.query_events(&filter)).unwrap())
+}
+
+/// Block explorer, paginated with `?page=0&limit=20`:
+///   GET /api/explorer/blocks                 latest blocks and the state root
+///   GET /api/explorer/blocks/<number|hash>
+///   GET /api/explorer/transactions/<hash>    with its inclusion proof
+///   GET /api/explorer/accounts/<account>     balance, nonce and history
+///   GET /api/explorer/richlist
+fn handle_explorer(request_line: &str, blockchain: SharedBlockchain) -> (String, String) {
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let route = percent_decode(path.split('?').next().unwrap_or("").trim_start_matches("/api/explorer/"));
+    let page = match PageRequest::from_query(&extract_query(request_line)) {
+        Ok(page) => page,
+        Err(e) => return error_response(&e),
+    };
+
+    let blockchain_guard = blockchain.lock().unwrap();
+    let body = match route.split_once('/').unwrap_or((&route, "")) {
+        ("blocks", "") => serde_json::to_string(&explorer::latest_blocks(&blockchain_guard, page)),
+        ("blocks", id) => match explorer::block(&blockchain_guard, id) {
+            Ok(block) => serde_json::to_string(&block),
+            Err(e) => return error_response(&e),
+        },
+        ("transactions", hash) if !hash.is_empty() => match explorer::transaction(&blockchain_guard, hash) {
+            Ok(transaction) => serde_json::to_string(&transaction),
+            Err(e) => return error_response(&e),
+        },
+        ("accounts", account) if !account.is_empty() => serde_json::to_string(&explorer::account(&blockchain_guard, account, page)),
+        ("richlist", "") => serde_json::to_string(&explorer::rich_list(&blockchain_guard, page)),
+        _ => return error_response(&RequestError::NotFound("Route".to_string())),
+    };
+    ("HTTP/1.1 200 OK".to_string(), body.unwrap())
+}
+
+/// GET /api/peers: connected P2P peers with their ban scores.
+fn handle_peers(network: Option<&Node>) -> (String, String) {
+    match network {
+        Some(node) => ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&node.peers()).unwrap()),
+        None => error_response(&RequestError::NotFound("P2P network".to_string())),
+    }
+}
+
+/// GET /api/sync: catch-up progress, `syncing: false` once caught up.
+fn handle_sync_status(blockchain: SharedBlockchain, network: Option<&Node>) -> (String, String) {
+    let Some(node) = network else {
+        return error_response(&RequestError::NotFound("P2P network".to_string()));
+    };
+    let height = blockchain.lock().unwrap().get_latest_block().index;
+    let body = match node.sync_status() {
+        Some(status) => serde_json::json!({ "syncing": true, "height": height, "progress": status }),
+        None => serde_json::json!({ "syncing": false, "height": height }),
+    };
+    ("HTTP/1.1 200 OK".to_string(), body.to_string())
+}
+
+/// POST /rpc: JSON-RPC 2.0, one request or a batch. Only notifications
+/// get an empty 204.
+fn handle_rpc(request: &str, blockchain: SharedBlockchain, network: Option<&Node>) -> (String, String) {
+    match rpc::handle(&blockchain, network, &extract_body(request)) {
+        Some(reply) => ("HTTP/1.1 200 OK".to_string(), reply),
+        None => ("HTTP/1.1 204 NO CONTENT".to_string(), String::new()),
+    }
+}
+
+/// Opens a subscription to the node's event stream for GET /api/stream.
+/// Resumes after the token in `Last-Event-ID`, which browsers send when
+/// they reconnect, or in `?resume=`.
+fn subscribe(request: &str, events: &EventStream) -> Result<Subscription, RequestError> {
+    let request_line = request.lines().next().unwrap_or("");
+    let token = request
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("last-event-id"))
+        .map(|(_, value)| value.trim().to_string())
+        .or_else(|| extract_query(request_line).remove("resume"));
+    events.subscribe(token.as_deref())
+}
+
+/// GET /api/stream: server-sent `block`, `reorg` and `mining` events until
+/// the client goes away.
+fn stream_events(mut stream: TcpStream, mut subscription: Subscription) {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\r\n";
+    if stream.write_all(head.as_bytes()).is_err() {
+        return;
+    }
+    loop {
+        let frame = subscription.next_frame(stream::KEEP_ALIVE);
+        if stream.write_all(frame.as_bytes()).and_then(|_| stream.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Streams every block that joins the chain, whether mined here or
+/// received from peers. When streamed blocks are replaced, a `reorg`
+/// event comes first and the new branch follows from the fork.
+fn watch_chain(blockchain: SharedBlockchain, events: EventStream) {
+    let mut streamed: BTreeMap<u32, Hash> = BTreeMap::new();
+    {
+        let blockchain = blockchain.lock().unwrap();
+        let head = blockchain.get_latest_block();
+        streamed.insert(head.index, head.hash.clone());
+    }
+    loop {
+        thread::sleep(CHAIN_WATCH_INTERVAL);
+        let blockchain = blockchain.lock().unwrap();
+        let height = blockchain.get_latest_block().index;
+        let fork_height = streamed
+            .iter()
+            .rev()
+            .find(|(index, hash)| blockchain.get_block_by_index(**index).is_some_and(|block| block.hash == **hash))
+            .map_or(height.saturating_sub(1), |(index, _)| *index);
+        if streamed.keys().next_back().is_some_and(|last| *last > fork_height) {
+            streamed.split_off(&(fork_height + 1));
+            events.publish("reorg", &ReorgEvent { fork_height, height });
+        }
+        for block in blockchain.chain.iter().filter(|block| block.index > fork_height) {
+            events.publish("block", &BlockSummary::new(block));
+            streamed.insert(block.index, block.hash.clone());
+        }
+        while streamed.len() > WATCHED_BLOCKS {
+            streamed.pop_first();
+        }
+    }
+}
+
+/// GET /api/snapshot: the latest snapshot plus the blocks after it.
+fn handle_snapshot(blockchain: SharedBlockchain) -> (String, String) {
+    let blockchain_guard = blockchain.lock().unwrap();
+    match blockchain_guard.export_snapshot() {
+        Some(bundle) => ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&bundle).unwrap()),
+        None => error_response(&RequestError::NotFound("Snapshot".to_string())),
+    }
+}
+
 fn handle_get_status(session_id: &str, sessions: SharedSessions) -> (String, String) {
     let sessions_guard = sessions.lock().unwrap();
     if let Some(session) = sessions_guard.get(session_id) {
         ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(session).unwrap())
     } else {
-        ("HTTP/1.1 404 NOT FOUND".to_string(), "Session not found".to_string())
+        error_response(&RequestError::NotFound("Session".to_string()))
+    }
+}
+
+fn handle_propose(request: &str, blockchain: SharedBlockchain, network: Option<&Node>) -> (String, String) {
+    match governance_transaction(request, |call| matches!(call, governance::Call::Propose { .. })) {
+        Ok(tx) => submit_governance_call(blockchain, network, tx),
+        Err(e) => error_response(&e),
+    }
+}
+
+fn handle_vote(request: &str, blockchain: SharedBlockchain, network: Option<&Node>) -> (String, String) {
+    match governance_transaction(request, |call| matches!(call, governance::Call::Vote { .. })) {
+        Ok(tx) => submit_governance_call(blockchain, network, tx),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// The signed governance transaction in the body, if its call is one
+/// `expected` accepts. Named accounts cannot sign, so anyone could act
+/// for them; they cannot govern over HTTP.
+fn governance_transaction(request: &str, expected: fn(&governance::Call) -> bool) -> Result<Transaction, RequestError> {
+    let req = serde_json::from_str::<GovernanceRequest>(&extract_body(request))
+        .map_err(|e| RequestError::InvalidBody(e.to_string()))?;
+    match &req.transaction.call {
+        Call::Governance(call) if expected(call) => {}
+        _ => return Err(RequestError::InvalidBody("transaction is not the expected governance call".to_string())),
+    }
+    if req.transaction.signature.is_none() {
+        return Err(RequestError::InvalidBody("governance transactions must be signed".to_string()));
+    }
+    Ok(req.transaction)
+}
+
+fn handle_list_proposals(blockchain: SharedBlockchain) -> (String, String) {
+    let blockchain_guard = blockchain.lock().unwrap();
+    ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&blockchain_guard.get_proposals()).unwrap())
+}
+
+/// Queues a signed governance call and gossips it to peers. It takes
+/// effect when the next block is mined.
+fn submit_governance_call(blockchain: SharedBlockchain, network: Option<&Node>, tx: Transaction) -> (String, String) {
+    let hash = tx.hash.to_hex();
+
+    let queued = match network {
+        Some(node) => node.submit_transaction(tx),
+        None => blockchain.lock().unwrap().add_transaction(tx),
+    };
+    match queued {
+        Ok(()) => {
+            let response = GovernanceResponse {
+                success: true,
+                message: "Transaction queued for the next block".to_string(),
+                transaction_hash: Some(hash),
+            };
+            ("HTTP/1.1 200 OK".to_string(), serde_json::to_string(&response).unwrap())
+        }
+        Err(e) => error_response(&e),
     }
 }
 
+/// Status line and JSON body for any module error. Clients branch on
+/// `error.code`; the message is for humans.
+fn error_response<E: ErrorCode>(error: &E) -> (String, String) {
+    let status = error.http_status();
+    (
+        format!("HTTP/1.1 {} {}", status, reason_phrase(status)),
+        serde_json::to_string(&error.to_body()).unwrap(),
+    )
+}
+
 fn calculate_minimum_games_needed(blockchain: &Blockchain) -> f64 {
     let difficulty_info = blockchain.get_rps_difficulty_info();
     let mut min_games = 0.0;